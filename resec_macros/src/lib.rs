@@ -33,9 +33,13 @@ pub fn make_schema(input: TokenStream) -> TokenStream {
         // Generate idents.
         let ident_name = format_ident!("{}", replaced.to_string());
 
+        // Properties must be string literals for strum to pick them up.
+        let name = replaced.to_string();
+        let id = k.to_string();
+
         // Generate the field.
         let token = quote! {
-            #[strum(props(name = #ident_name, id = #k))]
+            #[strum(props(name = #name, id = #id, label = #v))]
             #ident_name,
         };
 
@@ -46,7 +50,7 @@ pub fn make_schema(input: TokenStream) -> TokenStream {
     let tokens = quote! {
         /// Subjects that offer documents on the SEC website.
         /// Each subject contains its name and id that can be used to generate a query.
-        #[derive(EnumProperty, EnumIter, Serialize, Deserialize, Debug, Clone, PartialEq)]
+        #[derive(EnumProperty, EnumIter, Debug, Clone, PartialEq)]
         pub enum Subject {
             #(#output)*
         }
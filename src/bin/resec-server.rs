@@ -0,0 +1,93 @@
+//! REST API server for ``resec``.
+//!
+//! Serves the examination archive as JSON, caching stage pages
+//! and limiting the rate of requests made to the website.
+
+use clap::Parser;
+use resec::{
+    client::SecClient,
+    config::Config,
+    error::SecError,
+    server::{serve, Api},
+};
+use std::{env, net::TcpListener, path::PathBuf, time::Duration};
+
+/// Seconds to cache each stage page for, unless configured.
+const DEFAULT_CACHE_TTL: u64 = 3600;
+
+/// Maximum requests per second made to the website, unless configured.
+const DEFAULT_RATE_LIMIT: u32 = 2;
+
+#[derive(Parser)]
+#[command(
+    name = "resec-server",
+    version,
+    about = "Serve the SEC examination material archive as JSON"
+)]
+struct Cli {
+    /// Address to listen on.
+    #[arg(long, default_value = "127.0.0.1:8080")]
+    bind: String,
+
+    /// Configuration file to load, defaults to $RESEC_CONFIG if set.
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// URL of the archive to query, such as a local mirror.
+    #[arg(long)]
+    base_url: Option<String>,
+
+    /// Seconds to cache each stage page for [default: 3600].
+    #[arg(long)]
+    cache_ttl: Option<u64>,
+
+    /// Maximum requests per second made to the website [default: 2].
+    #[arg(long)]
+    rate_limit: Option<u32>,
+
+    /// Largest material served through /download, in bytes [default: 64 MiB].
+    #[arg(long)]
+    max_download_size: Option<u64>,
+}
+
+/// Load the configuration, overridden by the command line.
+fn load_config(cli: &Cli) -> Result<Config, SecError> {
+    let path = cli
+        .config
+        .clone()
+        .or_else(|| env::var_os("RESEC_CONFIG").map(PathBuf::from));
+    let mut config = match path {
+        Some(path) => Config::load(path)?,
+        None => Config::from_env()?,
+    };
+
+    if let Some(url) = &cli.base_url {
+        config = config.base_url(url)?;
+    }
+
+    // The server always caches and limits its requests.
+    let cache_ttl = cli.cache_ttl.map(Duration::from_secs).or(config.cache_ttl);
+    let rate_limit = cli.rate_limit.or(config.rate_limit);
+    Ok(config
+        .cache_ttl(cache_ttl.unwrap_or(Duration::from_secs(DEFAULT_CACHE_TTL)))
+        .rate_limit(rate_limit.unwrap_or(DEFAULT_RATE_LIMIT)))
+}
+
+#[tokio::main]
+async fn main() -> Result<(), SecError> {
+    let cli = Cli::parse();
+    let config = load_config(&cli)?;
+    let mut api = Api::new(SecClient::from_config(&config)?);
+
+    // Keep downloads alongside the persisted stage pages, if any.
+    if let Some(directory) = &config.cache_dir {
+        api = api.download_dir(directory.join("downloads"));
+    }
+    if let Some(bytes) = cli.max_download_size {
+        api = api.max_download_size(bytes);
+    }
+
+    let listener = TcpListener::bind(&cli.bind)?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    serve(api, listener).await
+}
@@ -0,0 +1,529 @@
+//! Command line interface for ``resec``.
+//!
+//! Crawls, downloads and exports material from the SEC examination archive.
+
+use clap::{Args, Parser, Subcommand};
+use futures_util::stream;
+use resec::{
+    checkpoint::Checkpoint,
+    export::{export, export_stream, writer_for, ExportFormat, ExportRow},
+    prelude::*,
+};
+use std::{
+    collections::HashMap,
+    env,
+    fs::File,
+    io,
+    io::Write,
+    path::{Path, PathBuf},
+    process,
+    sync::{Arc, Mutex},
+};
+use tokio::sync::mpsc::{self, UnboundedSender};
+
+/// How many subjects to crawl between checkpoint saves.
+const CHECKPOINT_INTERVAL: usize = 20;
+
+/// Width of each progress bar, in characters.
+const BAR_WIDTH: u64 = 24;
+
+#[derive(Parser)]
+#[command(
+    name = "resec",
+    version,
+    about = "Crawl the SEC examination material archive"
+)]
+struct Cli {
+    /// Configuration file to load, defaults to $RESEC_CONFIG if set.
+    #[arg(long, global = true)]
+    config: Option<PathBuf>,
+
+    /// URL of the archive to query, such as a local mirror.
+    #[arg(long, global = true)]
+    base_url: Option<String>,
+
+    #[command(flatten)]
+    options: Options,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+/// Options shared by every command.
+#[derive(Args, Clone, Copy)]
+struct Options {
+    /// Number of requests to have in flight at once.
+    #[arg(short, long, global = true)]
+    jobs: Option<usize>,
+
+    /// How many times to retry a failed request.
+    #[arg(long, global = true, default_value_t = 3)]
+    retries: u32,
+
+    /// Don't report progress on standard error.
+    #[arg(short, long, global = true)]
+    quiet: bool,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Crawl the archive and save a manifest of the material found.
+    Crawl {
+        #[command(flatten)]
+        filters: Filters,
+
+        /// Where to save the manifest.
+        #[arg(short, long, required_unless_present = "dry_run")]
+        output: Option<PathBuf>,
+
+        /// Checkpoint file to resume from and save progress into.
+        #[arg(long)]
+        checkpoint: Option<PathBuf>,
+
+        /// Skip subjects which failed this many times in the checkpoint.
+        #[arg(long, requires = "checkpoint")]
+        max_failures: Option<u32>,
+
+        /// List the queries the crawl would make, only fetching the lists of
+        /// years, examinations and subjects.
+        #[arg(long)]
+        dry_run: bool,
+    },
+    /// Download the material listed in a manifest.
+    Download {
+        /// Manifest listing the material to download.
+        #[arg(short, long)]
+        manifest: PathBuf,
+
+        /// Directory to save the material into, overriding the configuration.
+        #[arg(short, long)]
+        directory: Option<PathBuf>,
+
+        /// Where to save a manifest of the downloads, including hashes.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Manifest of an earlier download, material unchanged since is not fetched again.
+        #[arg(short, long)]
+        previous: Option<PathBuf>,
+    },
+    /// Export material to a flat file.
+    ///
+    /// The material is read from a manifest if given, otherwise the archive is crawled.
+    Export {
+        /// Format to export: csv, jsonl or parquet.
+        #[arg(short, long, default_value = "csv")]
+        format: ExportFormat,
+
+        /// Where to save the export, defaults to standard output.
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Manifest listing the material to export.
+        #[arg(short, long)]
+        manifest: Option<PathBuf>,
+
+        #[command(flatten)]
+        filters: Filters,
+    },
+}
+
+/// Filters for choosing what to crawl.
+///
+/// Manifests exported with ``export --manifest`` are filtered the same way.
+#[derive(Args)]
+struct Filters {
+    /// Paper type IDs to crawl, such as exampapers.
+    #[arg(long = "type")]
+    types: Vec<String>,
+
+    /// Examination IDs to crawl, such as lc.
+    #[arg(long = "exam")]
+    exams: Vec<String>,
+
+    /// Subject IDs to crawl, such as 3.
+    #[arg(long = "subject")]
+    subjects: Vec<u32>,
+
+    /// Years to crawl, such as 2015..2019.
+    #[arg(long)]
+    years: Option<YearRange>,
+
+    /// Filter expression selecting material, such as "exam:lc subject:maths level:higher".
+    #[arg(long)]
+    filter: Option<MaterialFilter>,
+}
+
+impl Filters {
+    /// Combine the filters into a single material filter.
+    fn material_filter(&self) -> Result<MaterialFilter, SecError> {
+        let mut filter = self.filter.clone().unwrap_or_default();
+
+        for id in &self.types {
+            filter = filter.paper_type(Type::from_id(id, ""));
+        }
+        for id in &self.exams {
+            filter = filter.examination(Examination::from_id(id, ""));
+        }
+        for id in &self.subjects {
+            let subject = Subject::iter()
+                .find(|x| x.get_str("id") == Some(id.to_string().as_str()))
+                .ok_or(SecError::Value("subject"))?;
+            filter = filter.subject(subject);
+        }
+        if let Some(years) = self.years {
+            filter = filter.years(years);
+        }
+
+        Ok(filter)
+    }
+
+    /// Build a crawler using the filters.
+    fn crawler(&self, config: &Config) -> Result<Crawler, SecError> {
+        Ok(Crawler::from_config(config)?.filter(self.material_filter()?))
+    }
+}
+
+/// Progress bars drawn on a single status line on standard error.
+///
+/// Crawls show the subjects completed, downloads show the materials
+/// completed and the bytes received of those in flight.
+#[derive(Default)]
+struct StatusLine {
+    subjects: (usize, usize),
+    materials: usize,
+    downloads: (usize, usize),
+    bytes: u64,
+    in_flight: HashMap<String, (u64, Option<u64>)>,
+}
+
+impl StatusLine {
+    /// Create a shared status line, expecting the given number of downloads.
+    fn shared(downloads: usize) -> Arc<Mutex<Self>> {
+        Arc::new(Mutex::new(Self {
+            downloads: (0, downloads),
+            ..Self::default()
+        }))
+    }
+
+    /// Update the line with an event and redraw it.
+    fn update(&mut self, event: &ProgressEvent) {
+        match event {
+            ProgressEvent::SubjectCompleted {
+                completed, total, ..
+            } => self.subjects = (*completed, *total),
+            ProgressEvent::MaterialFound(_) => self.materials += 1,
+            ProgressEvent::DownloadStarted { link, size } => {
+                self.in_flight.insert(link.to_string(), (0, *size));
+            }
+            ProgressEvent::BytesDownloaded { link, bytes, size } => {
+                self.in_flight.insert(link.to_string(), (*bytes, *size));
+            }
+            ProgressEvent::DownloadCompleted { link, bytes } => {
+                self.downloads.0 += 1;
+                self.bytes += bytes;
+                self.in_flight.remove(link.as_str());
+            }
+            ProgressEvent::DownloadUnchanged { link, .. } => {
+                self.downloads.0 += 1;
+                self.in_flight.remove(link.as_str());
+            }
+            ProgressEvent::Retry {
+                target,
+                attempt,
+                error,
+            } => eprintln!(
+                "\r\x1b[Kretrying {} (attempt {}): {}",
+                target, attempt, error
+            ),
+            ProgressEvent::Error { target, error } => {
+                self.in_flight.remove(target);
+                eprintln!("\r\x1b[Kerror: {}: {}", target, error)
+            }
+            ProgressEvent::StageDiscovered { .. } => return,
+        }
+
+        let line = if self.downloads.1 > 0 {
+            // Sum the downloads in flight, the size is only known if
+            // every server sent one.
+            let (bytes, size) = self
+                .in_flight
+                .values()
+                .fold((0, Some(0)), |(bytes, size), (x, y)| {
+                    (bytes + x, size.zip(*y).map(|(a, b)| a + b))
+                });
+            let current = match size {
+                _ if self.in_flight.is_empty() => String::new(),
+                Some(size) => format!(
+                    " {} {}/{}",
+                    bar(bytes, size),
+                    mebibytes(bytes),
+                    mebibytes(size)
+                ),
+                None => format!(" {}", mebibytes(bytes)),
+            };
+            format!(
+                "Downloading {} {}/{} materials, {}{}",
+                bar(self.downloads.0 as u64, self.downloads.1 as u64),
+                self.downloads.0,
+                self.downloads.1,
+                mebibytes(self.bytes),
+                current
+            )
+        } else {
+            format!(
+                "Crawling {} {}/{} subjects, {} materials found",
+                bar(self.subjects.0 as u64, self.subjects.1 as u64),
+                self.subjects.0,
+                self.subjects.1,
+                self.materials
+            )
+        };
+        eprint!("\r\x1b[K{}", line);
+    }
+
+    /// Clear the line.
+    fn finish(&self) {
+        eprint!("\r\x1b[K");
+    }
+}
+
+/// Draw a progress bar, such as ``[######------]``.
+fn bar(done: u64, total: u64) -> String {
+    let filled = match total {
+        0 => 0,
+        _ => done.min(total) * BAR_WIDTH / total,
+    };
+    format!(
+        "[{}{}]",
+        "#".repeat(filled as usize),
+        "-".repeat((BAR_WIDTH - filled) as usize)
+    )
+}
+
+/// Format a byte count in mebibytes.
+fn mebibytes(bytes: u64) -> String {
+    format!("{:.1} MiB", bytes as f64 / (1024.0 * 1024.0))
+}
+
+/// Crawl the archive, reporting progress and any skipped rows.
+///
+/// With a checkpoint, the crawl resumes from it and saves into it
+/// when finished, failed or interrupted with Ctrl-C. Subjects which fail
+/// are reported at the end, and skipped once they fail ``max_failures``
+/// times. Material is also sent to ``found`` as soon as it is found.
+async fn crawl(
+    filters: &Filters,
+    config: &Config,
+    options: Options,
+    checkpoint: Option<&Path>,
+    max_failures: Option<u32>,
+    found: Option<UnboundedSender<Material>>,
+) -> Result<Vec<Material>, SecError> {
+    let mut crawler = filters
+        .crawler(config)?
+        .retry_policy(RetryPolicy::new(options.retries));
+    if let Some(failures) = max_failures {
+        crawler = crawler.max_failures(failures);
+    }
+    let status = StatusLine::shared(0);
+    if !options.quiet || found.is_some() {
+        let status = status.clone();
+        crawler = crawler.progress(move |x: &ProgressEvent| {
+            if let (ProgressEvent::MaterialFound(material), Some(found)) = (x, &found) {
+                let _ = found.send(material.clone());
+            }
+            if !options.quiet {
+                status.lock().unwrap().update(x);
+            }
+        });
+    }
+
+    let saved = match checkpoint {
+        Some(path) => {
+            let saved = Arc::new(Mutex::new(Checkpoint::open(path, CHECKPOINT_INTERVAL)?));
+            crawler = crawler.checkpoint(saved.clone());
+            Some((saved, path))
+        }
+        None => None,
+    };
+
+    let papers = tokio::select! {
+        papers = crawler.crawl() => papers,
+        _ = tokio::signal::ctrl_c() => {
+            if !options.quiet {
+                status.lock().unwrap().finish();
+            }
+            if let Some((saved, path)) = &saved {
+                saved.lock().unwrap().save(path)?;
+                eprintln!("Interrupted, progress saved to {}.", path.display());
+            }
+            process::exit(130);
+        }
+    };
+    if !options.quiet {
+        status.lock().unwrap().finish();
+    }
+    if let Some((saved, path)) = &saved {
+        let mut saved = saved.lock().unwrap();
+        saved.save(path)?;
+        for failure in saved.failures() {
+            eprintln!(
+                "error: {} failed {} times: {}",
+                failure.node, failure.failures, failure.error
+            );
+        }
+    }
+    let papers = papers?;
+    for warning in &papers.warnings {
+        eprintln!(
+            "warning: skipped row {} of {}: {}",
+            warning.row, warning.node, warning.reason
+        );
+    }
+    Ok(papers.materials)
+}
+
+/// Load the configuration, overridden by the command line.
+fn load_config(cli: &Cli) -> Result<Config, SecError> {
+    let path = cli
+        .config
+        .clone()
+        .or_else(|| env::var_os("RESEC_CONFIG").map(PathBuf::from));
+    let mut config = match path {
+        Some(path) => Config::load(path)?,
+        None => Config::from_env()?,
+    };
+
+    if let Some(url) = &cli.base_url {
+        config = config.base_url(url)?;
+    }
+    if let Some(jobs) = cli.options.jobs {
+        config = config.max_in_flight(jobs);
+    }
+    Ok(config)
+}
+
+#[tokio::main]
+async fn main() -> Result<(), SecError> {
+    let cli = Cli::parse();
+    let config = load_config(&cli)?;
+
+    match cli.command {
+        Command::Crawl {
+            filters,
+            output,
+            checkpoint,
+            max_failures,
+            dry_run,
+        } => {
+            if dry_run {
+                let plan = Planner::new(filters.material_filter()?)
+                    .client(SecClient::from_config(&config)?)
+                    .fetch_lists(true)
+                    .dry_run()
+                    .await?;
+                for planned in &plan.queries {
+                    let cached = if planned.cached { " (cached)" } else { "" };
+                    println!("{}{}", planned.query, cached);
+                }
+                eprintln!("{} requests would be sent.", plan.requests().count());
+                return Ok(());
+            }
+
+            let materials = crawl(
+                &filters,
+                &config,
+                cli.options,
+                checkpoint.as_deref(),
+                max_failures,
+                None,
+            )
+            .await?;
+            if let Some(output) = output {
+                Manifest::from_materials(&materials).save(&output)?;
+            }
+            eprintln!("Found {} materials.", materials.len());
+        }
+        Command::Download {
+            manifest,
+            directory,
+            output,
+            previous,
+        } => {
+            let materials: Vec<Material> = Manifest::load(&manifest)?
+                .entries
+                .into_iter()
+                .map(|x| x.material)
+                .collect();
+            let config = match directory {
+                Some(directory) => config.download_dir(directory),
+                None => config,
+            };
+            let mut downloader = Downloader::from_config(&config)?.retries(cli.options.retries);
+            if let Some(previous) = previous {
+                downloader = downloader.previous(&Manifest::load(previous)?);
+            }
+            let status = StatusLine::shared(materials.len());
+            if !cli.options.quiet {
+                let status = status.clone();
+                downloader =
+                    downloader.progress(move |x: &ProgressEvent| status.lock().unwrap().update(x));
+            }
+
+            let downloads = downloader.download_all(&materials).await;
+            if !cli.options.quiet {
+                status.lock().unwrap().finish();
+            }
+            let downloads = downloads?;
+            if let Some(output) = output {
+                Manifest::from_downloads(&downloads).save(&output)?;
+            }
+            eprintln!("Downloaded {} materials.", downloads.len());
+        }
+        Command::Export {
+            format,
+            output,
+            manifest,
+            filters,
+        } => {
+            let out: Box<dyn Write + Send> = match output {
+                Some(path) => Box::new(io::BufWriter::new(File::create(path)?)),
+                None => Box::new(io::stdout()),
+            };
+            let mut writer = writer_for(format, out)?;
+
+            // Rows from a manifest keep their sizes and hashes.
+            let count = match manifest {
+                Some(path) => {
+                    let manifest = Manifest::load(&path)?;
+                    let filter = filters.material_filter()?;
+                    export(
+                        manifest
+                            .entries
+                            .iter()
+                            .filter(|x| filter.matches(&x.material))
+                            .map(ExportRow::from),
+                        writer.as_mut(),
+                    )?
+                }
+                // Rows from a crawl are written as they are found.
+                None => {
+                    let (sender, receiver) = mpsc::unbounded_channel();
+                    let rows = stream::unfold(receiver, |mut receiver| async move {
+                        let material = receiver.recv().await?;
+                        Some((ExportRow::from(&material), receiver))
+                    });
+                    let crawled = crawl(&filters, &config, cli.options, None, None, Some(sender));
+                    let (crawled, count) =
+                        tokio::join!(crawled, export_stream(rows, writer.as_mut()));
+                    crawled?;
+                    count?
+                }
+            };
+            eprintln!("Exported {} materials.", count);
+        }
+    }
+
+    Ok(())
+}
@@ -0,0 +1,196 @@
+//! A cache of responses from the SEC website.
+//!
+//! Stage pages rarely change, so caching them for a while avoids
+//! querying the website again when the same options are requested.
+//! Pages can also be persisted in a directory to survive restarts.
+
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+    sync::Mutex,
+    time::{Duration, Instant, SystemTime},
+};
+use tracing::warn;
+
+/// A cache of stage pages, keyed on the query.
+#[derive(Debug)]
+pub struct ResponseCache {
+    ttl: Duration,
+    capacity: usize,
+    directory: Option<PathBuf>,
+    entries: Mutex<HashMap<String, (Instant, String)>>,
+}
+
+impl ResponseCache {
+    /// Create a new cache keeping each response for the given time.
+    pub fn new(ttl: Duration) -> Self {
+        Self {
+            ttl,
+            capacity: 4096,
+            directory: None,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// How long each response is kept for.
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    /// Set the maximum number of responses kept.
+    pub fn capacity(mut self, capacity: usize) -> Self {
+        self.capacity = capacity;
+        self
+    }
+
+    /// Persist responses as files in the given directory.
+    ///
+    /// Responses missing from memory are read back from the directory
+    /// if they haven't expired, so the cache survives restarts.
+    pub fn directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.directory = Some(directory.into());
+        self
+    }
+
+    /// The file a response is persisted in.
+    fn file_for(directory: &Path, key: &str) -> PathBuf {
+        let digest = Sha256::digest(key.as_bytes());
+        let name: String = digest.iter().map(|x| format!("{:02x}", x)).collect();
+        directory.join(name + ".html")
+    }
+
+    /// Fetch a response, if cached and not expired.
+    pub fn get(&self, key: &str) -> Option<String> {
+        {
+            let mut entries = self.entries.lock().unwrap();
+            match entries.get(key) {
+                Some((stored, html)) if stored.elapsed() < self.ttl => return Some(html.clone()),
+                Some(_) => {
+                    entries.remove(key);
+                }
+                None => {}
+            }
+        }
+
+        // Fall back to the persisted response.
+        let path = Self::file_for(self.directory.as_ref()?, key);
+        let age = fs::metadata(&path)
+            .and_then(|x| x.modified())
+            .ok()
+            .and_then(|x| SystemTime::now().duration_since(x).ok())?;
+        if age >= self.ttl {
+            return None;
+        }
+        let html = fs::read_to_string(&path).ok()?;
+        let stored = Instant::now().checked_sub(age).unwrap_or_else(Instant::now);
+        self.remember(key.to_string(), stored, html.clone());
+        Some(html)
+    }
+
+    /// Store a response.
+    pub fn insert(&self, key: String, html: String) {
+        if let Some(directory) = &self.directory {
+            let path = Self::file_for(directory, &key);
+            if let Err(e) = fs::create_dir_all(directory).and_then(|_| fs::write(&path, &html)) {
+                warn!(path = %path.display(), error = %e, "failed to persist cached response");
+            }
+        }
+        self.remember(key, Instant::now(), html);
+    }
+
+    /// Store a response in memory.
+    fn remember(&self, key: String, stored: Instant, html: String) {
+        let mut entries = self.entries.lock().unwrap();
+
+        // Make room by dropping expired responses, then the oldest.
+        if entries.len() >= self.capacity && !entries.contains_key(&key) {
+            let ttl = self.ttl;
+            entries.retain(|_, (stored, _)| stored.elapsed() < ttl);
+            if entries.len() >= self.capacity {
+                let oldest = entries
+                    .iter()
+                    .min_by_key(|(_, (stored, _))| *stored)
+                    .map(|(key, _)| key.clone());
+                if let Some(oldest) = oldest {
+                    entries.remove(&oldest);
+                }
+            }
+        }
+
+        entries.insert(key, (stored, html));
+    }
+
+    /// Number of responses cached.
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    /// Check if the cache is empty.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Drop every cached response, including persisted ones.
+    pub fn clear(&self) {
+        self.entries.lock().unwrap().clear();
+        if let Some(Ok(files)) = self.directory.as_ref().map(fs::read_dir) {
+            for path in files.filter_map(|x| x.ok()).map(|x| x.path()) {
+                if path.extension().is_some_and(|x| x == "html") {
+                    let _ = fs::remove_file(path);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod cache_tests {
+    use super::*;
+
+    #[test]
+    fn expiry() {
+        let cache = ResponseCache::new(Duration::from_millis(50));
+        cache.insert("a".into(), "<html>".into());
+        assert_eq!(Some("<html>".into()), cache.get("a"));
+
+        std::thread::sleep(Duration::from_millis(60));
+        assert_eq!(None, cache.get("a"));
+        assert!(cache.is_empty());
+    }
+
+    #[test]
+    fn capacity() {
+        let cache = ResponseCache::new(Duration::from_secs(60)).capacity(2);
+        for key in &["a", "b", "c"] {
+            cache.insert(key.to_string(), key.to_string());
+            std::thread::sleep(Duration::from_millis(2));
+        }
+        assert_eq!(2, cache.len());
+        assert_eq!(None, cache.get("a"));
+        assert_eq!(Some("c".into()), cache.get("c"));
+    }
+
+    #[test]
+    fn persisted() {
+        let directory = std::env::temp_dir().join(format!("resec-cache-{}", std::process::id()));
+        let cache = ResponseCache::new(Duration::from_secs(60)).directory(&directory);
+        cache.insert("a".into(), "<html>".into());
+
+        // A new cache reads the response back from disk.
+        let restarted = ResponseCache::new(Duration::from_secs(60)).directory(&directory);
+        assert_eq!(Some("<html>".into()), restarted.get("a"));
+        assert_eq!(1, restarted.len());
+
+        restarted.clear();
+        assert_eq!(None, cache.get("b"));
+        assert_eq!(
+            None,
+            ResponseCache::new(Duration::from_secs(60))
+                .directory(&directory)
+                .get("a")
+        );
+        fs::remove_dir_all(directory).unwrap();
+    }
+}
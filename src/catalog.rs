@@ -0,0 +1,541 @@
+//! A SQLite catalogue of crawled material.
+//!
+//! The catalogue stores the output of crawls, so questions about the
+//! archive can be answered without querying the website again. The
+//! paper types, examinations and subjects known to the library are
+//! seeded when a catalogue is opened.
+
+use crate::{
+    error::SecResult,
+    filter::MaterialFilter,
+    material::{FileFormat, Material, MaterialKind},
+    schema::{
+        metadata::{Examination, Language, Level, Session, Type},
+        subjects::Subject,
+    },
+    years::YearRange,
+};
+use chrono::Utc;
+use futures_util::stream::{Stream, StreamExt};
+use rusqlite::{params, types::ToSql, Connection, Row};
+use std::{path::Path, str::FromStr};
+use strum::{EnumProperty, IntoEnumIterator};
+use url::Url;
+
+/// The catalogue schema.
+const SCHEMA: &str = "
+CREATE TABLE IF NOT EXISTS types (
+    id TEXT PRIMARY KEY,
+    label TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS examinations (
+    id TEXT PRIMARY KEY,
+    label TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS subjects (
+    id INTEGER PRIMARY KEY,
+    name TEXT NOT NULL
+);
+CREATE TABLE IF NOT EXISTS years (
+    type_id TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    PRIMARY KEY (type_id, year)
+);
+CREATE TABLE IF NOT EXISTS crawl_runs (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    started TEXT NOT NULL,
+    finished TEXT,
+    materials INTEGER NOT NULL DEFAULT 0
+);
+CREATE TABLE IF NOT EXISTS materials (
+    link TEXT PRIMARY KEY,
+    type_id TEXT NOT NULL,
+    year INTEGER NOT NULL,
+    exam_id TEXT NOT NULL,
+    subject_id INTEGER NOT NULL,
+    name TEXT NOT NULL,
+    level TEXT NOT NULL,
+    language TEXT NOT NULL,
+    session TEXT NOT NULL,
+    kind TEXT NOT NULL,
+    format TEXT NOT NULL,
+    extra TEXT NOT NULL,
+    first_run INTEGER NOT NULL REFERENCES crawl_runs(id),
+    last_run INTEGER NOT NULL REFERENCES crawl_runs(id)
+);
+CREATE INDEX IF NOT EXISTS materials_query
+    ON materials (exam_id, subject_id, year);
+";
+
+/// A recorded crawl.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CrawlRun {
+    pub id: i64,
+    pub started: String,
+    pub finished: Option<String>,
+    pub materials: u64,
+}
+
+/// A typed query over the catalogued material.
+///
+/// Every criteria left unset matches all material.
+#[derive(Debug, Clone, Default)]
+pub struct CatalogQuery {
+    paper_type: Option<Type>,
+    examination: Option<Examination>,
+    subject: Option<Subject>,
+    level: Option<Level>,
+    language: Option<Language>,
+    session: Option<Session>,
+    kind: Option<MaterialKind>,
+    years: Option<YearRange>,
+}
+
+impl CatalogQuery {
+    /// Create a new query matching all material.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only match the given paper type.
+    pub fn paper_type(mut self, paper_type: Type) -> Self {
+        self.paper_type = Some(paper_type);
+        self
+    }
+
+    /// Only match the given examination.
+    pub fn examination(mut self, examination: Examination) -> Self {
+        self.examination = Some(examination);
+        self
+    }
+
+    /// Only match the given subject.
+    pub fn subject(mut self, subject: Subject) -> Self {
+        self.subject = Some(subject);
+        self
+    }
+
+    /// Only match the given level.
+    pub fn level(mut self, level: Level) -> Self {
+        self.level = Some(level);
+        self
+    }
+
+    /// Only match the given language.
+    pub fn language(mut self, language: Language) -> Self {
+        self.language = Some(language);
+        self
+    }
+
+    /// Only match the given session.
+    pub fn session(mut self, session: Session) -> Self {
+        self.session = Some(session);
+        self
+    }
+
+    /// Only match the given kind of material.
+    pub fn kind(mut self, kind: MaterialKind) -> Self {
+        self.kind = Some(kind);
+        self
+    }
+
+    /// Only match the given range of years.
+    pub fn years(mut self, years: YearRange) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    /// Generate the ``WHERE`` clause and parameters for the query.
+    fn where_clause(&self) -> (String, Vec<Box<dyn ToSql>>) {
+        let mut clauses: Vec<&str> = Vec::new();
+        let mut params: Vec<Box<dyn ToSql>> = Vec::new();
+
+        if let Some(x) = &self.paper_type {
+            clauses.push("type_id = ?");
+            params.push(Box::new(x.id().to_string()));
+        }
+        if let Some(x) = &self.examination {
+            clauses.push("exam_id = ?");
+            params.push(Box::new(x.id().to_string()));
+        }
+        if let Some(x) = &self.subject {
+            clauses.push("subject_id = ?");
+            params.push(Box::new(subject_id(x)));
+        }
+        if let Some(x) = &self.level {
+            clauses.push("level = ?");
+            params.push(Box::new(x.to_string()));
+        }
+        if let Some(x) = &self.language {
+            clauses.push("language = ?");
+            params.push(Box::new(x.to_string()));
+        }
+        if let Some(x) = &self.session {
+            clauses.push("session = ?");
+            params.push(Box::new(x.to_string()));
+        }
+        if let Some(x) = &self.kind {
+            clauses.push("kind = ?");
+            params.push(Box::new(x.to_string()));
+        }
+        if let Some(x) = &self.years {
+            clauses.push("year BETWEEN ? AND ?");
+            params.push(Box::new(x.start.0));
+            params.push(Box::new(x.end.0));
+        }
+
+        match clauses.is_empty() {
+            true => (String::new(), params),
+            false => (format!("WHERE {}", clauses.join(" AND ")), params),
+        }
+    }
+}
+
+/// Narrow a query using the criteria of a filter with a single value.
+///
+/// Criteria with several values can't be expressed as a query, so the
+/// results still need checking against the filter.
+impl From<&MaterialFilter> for CatalogQuery {
+    fn from(filter: &MaterialFilter) -> Self {
+        fn single<T: Clone>(values: &[T]) -> Option<T> {
+            match values {
+                [x] => Some(x.clone()),
+                _ => None,
+            }
+        }
+
+        Self {
+            paper_type: single(filter.types()),
+            examination: single(filter.examinations()),
+            subject: single(filter.subjects()),
+            level: single(filter.levels()),
+            language: single(filter.languages()),
+            session: single(filter.sessions()),
+            kind: single(filter.kinds()),
+            years: filter.year_range(),
+        }
+    }
+}
+
+/// The numeric ID of a subject.
+fn subject_id(subject: &Subject) -> u32 {
+    subject
+        .get_str("id")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or_default()
+}
+
+/// A SQLite catalogue of material.
+#[derive(Debug)]
+pub struct Catalog {
+    connection: Connection,
+}
+
+impl Catalog {
+    /// Open or create a catalogue at the given path.
+    pub fn open(path: impl AsRef<Path>) -> SecResult<Self> {
+        Self::from_connection(Connection::open(path)?)
+    }
+
+    /// Create a catalogue held in memory.
+    pub fn in_memory() -> SecResult<Self> {
+        Self::from_connection(Connection::open_in_memory()?)
+    }
+
+    /// Create the schema and seed the known metadata.
+    fn from_connection(connection: Connection) -> SecResult<Self> {
+        connection.execute_batch(SCHEMA)?;
+
+        for x in Type::iter() {
+            connection.execute(
+                "INSERT OR IGNORE INTO types (id, label) VALUES (?1, ?2)",
+                params![x.id(), x.label()],
+            )?;
+        }
+        for x in Examination::iter() {
+            connection.execute(
+                "INSERT OR IGNORE INTO examinations (id, label) VALUES (?1, ?2)",
+                params![x.id(), x.label()],
+            )?;
+        }
+        for x in Subject::iter() {
+            connection.execute(
+                "INSERT OR IGNORE INTO subjects (id, name) VALUES (?1, ?2)",
+                params![subject_id(&x), x.get_str("name").unwrap_or_default()],
+            )?;
+        }
+
+        Ok(Self { connection })
+    }
+
+    /// Ingest a stream of crawled material as a new crawl run.
+    ///
+    /// Material already in the catalogue is updated in place. The stream is
+    /// collected before the run is written in a single transaction, so a
+    /// run which fails or is dropped part way leaves the catalogue unchanged.
+    pub async fn ingest<S>(&mut self, stream: S) -> SecResult<CrawlRun>
+    where
+        S: Stream<Item = Material>,
+    {
+        let started = Utc::now().to_rfc3339();
+        let materials: Vec<Material> = stream.collect().await;
+        self.write_run(started, &materials)
+    }
+
+    /// Write a crawl run and its material in a single transaction.
+    fn write_run(&mut self, started: String, materials: &[Material]) -> SecResult<CrawlRun> {
+        let transaction = self.connection.transaction()?;
+        transaction.execute(
+            "INSERT INTO crawl_runs (started) VALUES (?1)",
+            params![started],
+        )?;
+        let run = transaction.last_insert_rowid();
+
+        for material in materials {
+            Self::insert(&transaction, material, run)?;
+        }
+        let count = materials.len() as u64;
+
+        // Finally, mark the run as finished.
+        let finished = Utc::now().to_rfc3339();
+        transaction.execute(
+            "UPDATE crawl_runs SET finished = ?1, materials = ?2 WHERE id = ?3",
+            params![finished, count, run],
+        )?;
+        transaction.commit()?;
+
+        Ok(CrawlRun {
+            id: run,
+            started,
+            finished: Some(finished),
+            materials: count,
+        })
+    }
+
+    /// Insert or update a single material.
+    fn insert(connection: &Connection, material: &Material, run: i64) -> SecResult<()> {
+        let extra = material.joined_extra();
+
+        connection.execute(
+            "INSERT OR IGNORE INTO years (type_id, year) VALUES (?1, ?2)",
+            params![material.type_id, material.year],
+        )?;
+        connection.execute(
+            "INSERT INTO materials (
+                link, type_id, year, exam_id, subject_id, name, level, language,
+                session, kind, format, extra, first_run, last_run
+            ) VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?13)
+            ON CONFLICT (link) DO UPDATE SET
+                name = excluded.name,
+                level = excluded.level,
+                language = excluded.language,
+                session = excluded.session,
+                kind = excluded.kind,
+                format = excluded.format,
+                extra = excluded.extra,
+                last_run = excluded.last_run",
+            params![
+                material.link.as_str(),
+                material.type_id,
+                material.year,
+                material.exam_id,
+                material.subject_id,
+                material.name,
+                material.level.to_string(),
+                material.language.to_string(),
+                material.session.to_string(),
+                material.kind.to_string(),
+                material.format.to_string(),
+                extra,
+                run,
+            ],
+        )?;
+
+        Ok(())
+    }
+
+    /// Find the material matching a query, ordered by year and subject.
+    pub fn query(&self, query: &CatalogQuery) -> SecResult<Vec<Material>> {
+        let (clause, params) = query.where_clause();
+        let sql = format!(
+            "SELECT link, type_id, year, exam_id, subject_id, name, level, language,
+                session, kind, format, extra
+            FROM materials {} ORDER BY year, exam_id, subject_id, name",
+            clause
+        );
+
+        let mut statement = self.connection.prepare(&sql)?;
+        let rows = statement.query_map(
+            rusqlite::params_from_iter(params.iter().map(|x| x.as_ref())),
+            material_from_row,
+        )?;
+
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Find the material matching a filter, ordered by year and subject.
+    pub fn filter(&self, filter: &MaterialFilter) -> SecResult<Vec<Material>> {
+        let mut materials = self.query(&CatalogQuery::from(filter))?;
+        materials.retain(|x| filter.matches(x));
+        Ok(materials)
+    }
+
+    /// The years recorded for a paper type, oldest first.
+    pub fn years(&self, paper_type: &Type) -> SecResult<Vec<u32>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT year FROM years WHERE type_id = ?1 ORDER BY year")?;
+        let rows = statement.query_map(params![paper_type.id()], |row| row.get(0))?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+
+    /// Every recorded crawl run, oldest first.
+    pub fn crawl_runs(&self) -> SecResult<Vec<CrawlRun>> {
+        let mut statement = self
+            .connection
+            .prepare("SELECT id, started, finished, materials FROM crawl_runs ORDER BY id")?;
+        let rows = statement.query_map([], |row| {
+            Ok(CrawlRun {
+                id: row.get(0)?,
+                started: row.get(1)?,
+                finished: row.get(2)?,
+                materials: row.get(3)?,
+            })
+        })?;
+        Ok(rows.collect::<Result<_, _>>()?)
+    }
+}
+
+/// Parse a stored value, reporting failures as a conversion error.
+fn parse_column<T: FromStr>(row: &Row, index: usize) -> rusqlite::Result<T> {
+    let raw: String = row.get(index)?;
+    raw.parse().map_err(|_| {
+        rusqlite::Error::FromSqlConversionFailure(
+            index,
+            rusqlite::types::Type::Text,
+            format!("invalid value: {}", raw).into(),
+        )
+    })
+}
+
+/// Convert a catalogue row back into a material.
+fn material_from_row(row: &Row) -> rusqlite::Result<Material> {
+    let link: Url = parse_column(row, 0)?;
+    let extra: String = row.get(11)?;
+
+    Ok(Material {
+        link,
+        type_id: row.get(1)?,
+        year: row.get(2)?,
+        exam_id: row.get(3)?,
+        subject_id: row.get(4)?,
+        name: row.get(5)?,
+        level: parse_column(row, 6)?,
+        language: parse_column(row, 7)?,
+        session: parse_column(row, 8)?,
+        kind: parse_column::<MaterialKind>(row, 9)?,
+        format: parse_column::<FileFormat>(row, 10)?,
+        extra: Material::split_extra(&extra),
+    })
+}
+
+#[cfg(test)]
+mod catalog_tests {
+    use super::*;
+    use futures_util::stream;
+
+    fn material(year: u32, subject_id: u32, name: &str, file: &str) -> Material {
+        let link = Url::parse("https://www.examinations.ie/archive/exampapers/")
+            .unwrap()
+            .join(file)
+            .unwrap();
+        Material::new("exampapers", year, "lc", subject_id, name.into(), link)
+    }
+
+    #[tokio::test]
+    async fn ingest_and_query() -> SecResult<()> {
+        let mut catalog = Catalog::in_memory()?;
+        let materials = vec![
+            material(2009, 3, "Mathematics / Higher Level (IV)", "a.pdf"),
+            material(2015, 3, "Mathematics / Higher Level (IV)", "b.pdf"),
+            material(2015, 3, "Mathematics / Higher Level (EV)", "c.pdf"),
+            material(2015, 3, "Mathematics / Ordinary Level (IV)", "d.pdf"),
+            material(2016, 22, "Chemistry / Higher Level (IV)", "e.pdf"),
+            material(2020, 3, "Mathematics / Higher Level (IV)", "f.pdf"),
+        ];
+        let run = catalog.ingest(stream::iter(materials.clone())).await?;
+        assert_eq!(6, run.materials);
+
+        // All Higher Level Irish-language maths papers from 2010 to 2020.
+        let query = CatalogQuery::new()
+            .examination(Examination::LeavingCertificate)
+            .subject(Subject::Mathematics)
+            .level(Level::HigherLevel)
+            .language(Language::Irish)
+            .kind(MaterialKind::Paper)
+            .years(YearRange::from(2010..=2020));
+        assert_eq!(
+            vec![materials[1].clone(), materials[5].clone()],
+            catalog.query(&query)?
+        );
+
+        // The same as a filter, with more than one subject.
+        let filter: MaterialFilter =
+            "exam:lc subject:maths,chemistry level:higher lang:IV kind:paper year:2010..2020"
+                .parse()?;
+        assert_eq!(
+            vec![
+                materials[1].clone(),
+                materials[4].clone(),
+                materials[5].clone()
+            ],
+            catalog.filter(&filter)?
+        );
+
+        assert_eq!(
+            vec![2009, 2015, 2016, 2020],
+            catalog.years(&Type::ExamPaper)?
+        );
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn repeated_ingest() -> SecResult<()> {
+        let mut catalog = Catalog::in_memory()?;
+        let mut materials = vec![material(2019, 3, "Mathematics (EV)", "a.pdf")];
+        materials[0].extra = vec!["1.2 MB".into(), "New".into()];
+
+        catalog.ingest(stream::iter(materials.clone())).await?;
+        catalog.ingest(stream::iter(materials.clone())).await?;
+
+        assert_eq!(materials, catalog.query(&CatalogQuery::new())?);
+        assert_eq!(2, catalog.crawl_runs()?.len());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn dropped_ingest() -> SecResult<()> {
+        let mut catalog = Catalog::in_memory()?;
+        let materials = vec![material(2019, 3, "Mathematics (EV)", "a.pdf")];
+
+        // A crawl which never finishes leaves no trace.
+        let endless = stream::iter(materials).chain(stream::pending());
+        let ingest = catalog.ingest(endless);
+        let timeout = tokio::time::timeout(std::time::Duration::from_millis(50), ingest);
+        assert!(timeout.await.is_err());
+
+        assert!(catalog.query(&CatalogQuery::new())?.is_empty());
+        assert!(catalog.crawl_runs()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn ingest_is_send() -> SecResult<()> {
+        fn assert_send<T: Send>(_: &T) {}
+
+        // Ingesting can be spawned onto a threaded runtime.
+        let mut catalog = Catalog::in_memory()?;
+        assert_send(&catalog.ingest(stream::empty()));
+        Ok(())
+    }
+}
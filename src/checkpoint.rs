@@ -0,0 +1,287 @@
+//! Checkpoints for resuming interrupted crawls.
+//!
+//! A [`Checkpoint`] records every subject the [`Crawler`](crate::crawler::Crawler)
+//! has finished, alongside the material it found. A crawl given the same
+//! checkpoint skips the finished subjects and retries the failed ones,
+//! until they reach the crawler's
+//! [`max_failures`](crate::crawler::Crawler::max_failures) limit.
+
+use crate::{error::SecResult, material::Papers};
+use std::{collections::BTreeMap, fmt};
+
+#[cfg(feature = "serde")]
+use chrono::{DateTime, Utc};
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+/// A subject of the archive, the final stage of a crawl.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct CrawlNode {
+    /// Paper type ID.
+    pub type_id: String,
+    /// Year of the examination.
+    pub year: u32,
+    /// Examination ID.
+    pub exam_id: String,
+    /// Subject ID.
+    pub subject_id: u32,
+}
+
+impl CrawlNode {
+    /// Create a new node.
+    pub fn new(type_id: &str, year: u32, exam_id: &str, subject_id: u32) -> Self {
+        Self {
+            type_id: type_id.into(),
+            year,
+            exam_id: exam_id.into(),
+            subject_id,
+        }
+    }
+}
+
+impl fmt::Display for CrawlNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{}/{}/{}/{}",
+            self.type_id, self.year, self.exam_id, self.subject_id
+        )
+    }
+}
+
+/// A node whose last crawl failed.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct FailedNode {
+    /// The node that failed.
+    pub node: CrawlNode,
+    /// Number of crawls which failed on the node.
+    pub failures: u32,
+    /// Why the last crawl failed.
+    pub error: String,
+}
+
+/// The progress of a crawl.
+#[derive(Debug, Clone, Default)]
+pub struct Checkpoint {
+    completed: BTreeMap<CrawlNode, Papers>,
+    failed: BTreeMap<CrawlNode, FailedNode>,
+    #[cfg(feature = "serde")]
+    autosave: Option<(PathBuf, usize)>,
+    #[cfg(feature = "serde")]
+    unsaved: usize,
+}
+
+impl Checkpoint {
+    /// Create an empty checkpoint.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The material found for a completed node.
+    pub fn completed(&self, node: &CrawlNode) -> Option<&Papers> {
+        self.completed.get(node)
+    }
+
+    /// The number of completed nodes.
+    pub fn len(&self) -> usize {
+        self.completed.len()
+    }
+
+    /// Check if no nodes have been completed.
+    pub fn is_empty(&self) -> bool {
+        self.completed.is_empty()
+    }
+
+    /// The failures of a node, if its last crawl failed.
+    pub fn failed(&self, node: &CrawlNode) -> Option<&FailedNode> {
+        self.failed.get(node)
+    }
+
+    /// Every node whose last crawl failed.
+    pub fn failures(&self) -> impl Iterator<Item = &FailedNode> {
+        self.failed.values()
+    }
+
+    /// Record the material found for a node.
+    pub fn complete(&mut self, node: CrawlNode, papers: Papers) {
+        self.failed.remove(&node);
+        self.completed.insert(node, papers);
+        #[cfg(feature = "serde")]
+        {
+            self.unsaved += 1;
+        }
+    }
+
+    /// Record a failure to crawl a node.
+    pub fn fail(&mut self, node: CrawlNode, error: impl ToString) {
+        let failures = self.failed.get(&node).map_or(0, |x| x.failures) + 1;
+        self.failed.insert(
+            node.clone(),
+            FailedNode {
+                node,
+                failures,
+                error: error.to_string(),
+            },
+        );
+        #[cfg(feature = "serde")]
+        {
+            self.unsaved += 1;
+        }
+    }
+
+    /// Save the checkpoint to a file every given number of completed or
+    /// failed nodes.
+    #[cfg(feature = "serde")]
+    pub fn autosave(mut self, path: impl Into<PathBuf>, every: usize) -> Self {
+        self.autosave = Some((path.into(), every.max(1)));
+        self
+    }
+
+    /// Save the checkpoint if enough nodes were recorded since the last save.
+    pub(crate) fn maybe_save(&mut self) -> SecResult<()> {
+        #[cfg(feature = "serde")]
+        {
+            if let Some((path, every)) = &self.autosave {
+                if self.unsaved >= *every {
+                    let path = path.clone();
+                    return self.save(path);
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Load a checkpoint from a JSON file.
+    #[cfg(feature = "serde")]
+    pub fn load(path: impl AsRef<Path>) -> SecResult<Self> {
+        let file: CheckpointFile = serde_json::from_str(&fs::read_to_string(path)?)?;
+        Ok(Self {
+            completed: file
+                .completed
+                .into_iter()
+                .map(|x| (x.node, x.papers))
+                .collect(),
+            failed: file
+                .failed
+                .into_iter()
+                .map(|x| (x.node.clone(), x))
+                .collect(),
+            ..Self::default()
+        })
+    }
+
+    /// Load a checkpoint if the file exists, otherwise start a new one.
+    ///
+    /// The checkpoint is saved back to the file every given number of
+    /// completed or failed nodes.
+    #[cfg(feature = "serde")]
+    pub fn open(path: impl AsRef<Path>, every: usize) -> SecResult<Self> {
+        let path = path.as_ref();
+        let checkpoint = if path.exists() {
+            Self::load(path)?
+        } else {
+            Self::new()
+        };
+        Ok(checkpoint.autosave(path, every))
+    }
+
+    /// Save the checkpoint as a JSON file.
+    ///
+    /// The file is written next to its destination then moved into place,
+    /// so an interrupted save never leaves a truncated checkpoint.
+    #[cfg(feature = "serde")]
+    pub fn save(&mut self, path: impl AsRef<Path>) -> SecResult<()> {
+        let file = CheckpointFile {
+            updated: Utc::now(),
+            completed: self
+                .completed
+                .iter()
+                .map(|(node, papers)| CompletedNode {
+                    node: node.clone(),
+                    papers: papers.clone(),
+                })
+                .collect(),
+            failed: self.failed.values().cloned().collect(),
+        };
+
+        let path = path.as_ref();
+        let partial = path.with_extension("partial");
+        fs::write(&partial, serde_json::to_string(&file)?)?;
+        fs::rename(partial, path)?;
+        self.unsaved = 0;
+        Ok(())
+    }
+}
+
+/// A completed node as stored in a checkpoint file.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct CompletedNode {
+    node: CrawlNode,
+    papers: Papers,
+}
+
+/// The layout of a checkpoint file.
+#[cfg(feature = "serde")]
+#[derive(Serialize, Deserialize)]
+struct CheckpointFile {
+    updated: DateTime<Utc>,
+    completed: Vec<CompletedNode>,
+    failed: Vec<FailedNode>,
+}
+
+#[cfg(test)]
+mod checkpoint_tests {
+    use super::*;
+
+    #[test]
+    fn failures() {
+        let mut checkpoint = Checkpoint::new();
+        let node = CrawlNode::new("exampapers", 2019, "lc", 3);
+        checkpoint.fail(node.clone(), "timed out");
+        checkpoint.fail(node.clone(), "timed out");
+        assert_eq!(2, checkpoint.failures().next().unwrap().failures);
+
+        // Completing a node clears its failures.
+        checkpoint.complete(node.clone(), Papers::default());
+        assert_eq!(0, checkpoint.failures().count());
+        assert_eq!(Some(&Papers::default()), checkpoint.completed(&node));
+        assert_eq!("exampapers/2019/lc/3", node.to_string());
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn autosave() -> SecResult<()> {
+        let path =
+            std::env::temp_dir().join(format!("resec-checkpoint-{}.json", std::process::id()));
+        let _ = fs::remove_file(&path);
+
+        let mut checkpoint = Checkpoint::open(&path, 2)?;
+        checkpoint.complete(
+            CrawlNode::new("exampapers", 2019, "lc", 3),
+            Papers::default(),
+        );
+        checkpoint.maybe_save()?;
+        assert!(!path.exists());
+
+        checkpoint.fail(CrawlNode::new("exampapers", 2019, "lc", 4), "timed out");
+        checkpoint.complete(
+            CrawlNode::new("exampapers", 2019, "lc", 2),
+            Papers::default(),
+        );
+        checkpoint.maybe_save()?;
+
+        let loaded = Checkpoint::open(&path, 2)?;
+        assert_eq!(2, loaded.len());
+        assert_eq!(1, loaded.failures().count());
+        fs::remove_file(path)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,232 @@
+//! The HTTP client used to talk to the SEC website.
+//!
+//! Every query made by the library goes through a [`SecClient`],
+//! which holds the connection pool and the archive URL to query.
+//! A client can also cache stage pages and limit the rate of requests,
+//! which is shared between all of its clones.
+
+use crate::{
+    cache::ResponseCache,
+    config::Config,
+    consts::EXAM_URL,
+    error::{SecError, SecResult},
+    limiter::RateLimiter,
+};
+use reqwest::{Certificate, Client, Proxy};
+use std::{fs, future::Future, path::Path, sync::Arc, time::Duration};
+use url::Url;
+
+/// ``User-Agent`` header sent unless configured otherwise.
+const DEFAULT_USER_AGENT: &str = concat!("resec/", env!("CARGO_PKG_VERSION"));
+
+/// Client for querying the examination archive.
+#[derive(Debug, Clone)]
+pub struct SecClient {
+    http: Client,
+    base_url: Url,
+    cache: Option<Arc<ResponseCache>>,
+    limiter: Option<Arc<RateLimiter>>,
+    read_timeout: Option<Duration>,
+    max_in_flight: usize,
+}
+
+impl Default for SecClient {
+    fn default() -> Self {
+        let http = Client::builder()
+            .user_agent(DEFAULT_USER_AGENT)
+            .build()
+            .expect("could not build HTTP client");
+        Self {
+            http,
+            base_url: Url::parse(EXAM_URL).expect("archive URL is valid"),
+            cache: None,
+            limiter: None,
+            read_timeout: None,
+            max_in_flight: 1,
+        }
+    }
+}
+
+impl SecClient {
+    /// Create a new client for the SEC website, using the built-in defaults.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new client configured by any ``RESEC_*`` environment variables,
+    /// see [`Config::from_env`].
+    pub fn from_env() -> SecResult<Self> {
+        Self::from_config(&Config::from_env()?)
+    }
+
+    /// Create a new client using the given configuration.
+    pub fn from_config(config: &Config) -> SecResult<Self> {
+        let user_agent = config.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT);
+        let mut http = Client::builder().user_agent(user_agent);
+        if let Some(timeout) = config.timeout {
+            http = http.timeout(timeout);
+        }
+        if let Some(timeout) = config.connect_timeout {
+            http = http.connect_timeout(timeout);
+        }
+        if let Some(url) = &config.proxy {
+            if url.scheme().starts_with("socks") && !cfg!(feature = "socks") {
+                return Err(SecError::FeatureDisabled("socks"));
+            }
+            let mut proxy = Proxy::all(url.as_str())?;
+            if let Some((username, password)) = &config.proxy_auth {
+                proxy = proxy.basic_auth(username, password);
+            }
+            http = http.proxy(proxy);
+        }
+        for path in &config.root_certificates {
+            http = http.add_root_certificate(load_certificate(path)?);
+        }
+
+        Ok(Self {
+            http: http.build()?,
+            base_url: config.base_url.clone(),
+            cache: config.response_cache().map(Arc::new),
+            limiter: config.rate_limiter().map(Arc::new),
+            read_timeout: config.read_timeout,
+            max_in_flight: config.max_in_flight,
+        })
+    }
+
+    /// Create a new client querying the archive at the given URL.
+    pub fn with_base_url(base_url: &str) -> SecResult<Self> {
+        Self::from_config(&Config::new().base_url(base_url)?)
+    }
+
+    /// The URL of the examination archive.
+    pub fn base_url(&self) -> &Url {
+        &self.base_url
+    }
+
+    /// Cache stage pages using the given cache.
+    pub fn cache(mut self, cache: ResponseCache) -> Self {
+        self.cache = Some(Arc::new(cache));
+        self
+    }
+
+    /// Limit the rate of requests using the given limiter.
+    pub fn rate_limit(mut self, limiter: RateLimiter) -> Self {
+        self.limiter = Some(Arc::new(limiter));
+        self
+    }
+
+    /// Set the number of queries the client may have in flight at once
+    /// when fanning out, such as in
+    /// [`papers_for_subject`](Self::papers_for_subject).
+    ///
+    /// Crawlers and downloaders using the client share the limit unless
+    /// they set their own. Defaults to one, querying sequentially.
+    pub fn max_in_flight(mut self, requests: usize) -> Self {
+        self.max_in_flight = requests;
+        self
+    }
+
+    /// The number of queries the client may have in flight at once.
+    pub(crate) fn in_flight_limit(&self) -> usize {
+        self.max_in_flight.max(1)
+    }
+
+    /// The underlying reqwest client.
+    pub fn http(&self) -> &Client {
+        &self.http
+    }
+
+    /// The stage page cache, if any.
+    pub fn response_cache(&self) -> Option<&ResponseCache> {
+        self.cache.as_deref()
+    }
+
+    /// Wait until the rate limiter allows another request.
+    pub async fn throttle(&self) {
+        if let Some(limiter) = &self.limiter {
+            limiter.wait().await;
+        }
+    }
+
+    /// Wait for part of a response, failing if the read timeout passes first.
+    pub async fn read<T>(&self, part: impl Future<Output = reqwest::Result<T>>) -> SecResult<T> {
+        match self.read_timeout {
+            Some(timeout) => Ok(tokio::time::timeout(timeout, part)
+                .await
+                .map_err(|_| SecError::Timeout)??),
+            None => Ok(part.await?),
+        }
+    }
+}
+
+/// Load a PEM or DER encoded certificate.
+fn load_certificate(path: &Path) -> SecResult<Certificate> {
+    let bytes = fs::read(path)?;
+    if bytes.starts_with(b"-----BEGIN") {
+        Ok(Certificate::from_pem(&bytes)?)
+    } else {
+        Ok(Certificate::from_der(&bytes)?)
+    }
+}
+
+#[cfg(test)]
+mod client_tests {
+    use super::*;
+    use crate::test_util::MockServer;
+
+    #[tokio::test]
+    async fn proxy_and_user_agent() -> SecResult<()> {
+        let server = MockServer::start();
+        let proxy = server.url().join("/").unwrap();
+        let config = Config::new()
+            .base_url("http://archive.invalid/exammaterialarchive/")?
+            .proxy(proxy.as_str())?
+            .proxy_auth("team", "secret")
+            .user_agent("team-crawler/1.0");
+        SecClient::from_config(&config)?.parse_types().await?;
+
+        let headers = server.request_headers().pop().unwrap();
+        assert_eq!("team-crawler/1.0", headers["user-agent"]);
+        assert_eq!("Basic dGVhbTpzZWNyZXQ=", headers["proxy-authorization"]);
+
+        // Clients identify themselves by default.
+        server.client().parse_types().await?;
+        let headers = server.request_headers().pop().unwrap();
+        assert_eq!(DEFAULT_USER_AGENT, headers["user-agent"]);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn read_timeout() -> SecResult<()> {
+        let server = MockServer::start();
+        server.set_delay(Duration::from_millis(300));
+        let config = Config::new()
+            .base_url(server.url().as_str())?
+            .read_timeout(Duration::from_millis(50));
+        let result = SecClient::from_config(&config)?.parse_types().await;
+        assert!(matches!(result, Err(SecError::Timeout)));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_options() {
+        let path =
+            std::env::temp_dir().join(format!("resec-invalid-cert-{}.pem", std::process::id()));
+        fs::write(&path, b"-----BEGIN CERTIFICATE-----\nnot base64\n").unwrap();
+        let config = Config::new().root_certificate(&path);
+        assert!(matches!(
+            SecClient::from_config(&config),
+            Err(SecError::Reqwest(_))
+        ));
+        fs::remove_file(path).unwrap();
+
+        #[cfg(not(feature = "socks"))]
+        {
+            let config = Config::new().proxy("socks5://localhost:1080").unwrap();
+            assert!(matches!(
+                SecClient::from_config(&config),
+                Err(SecError::FeatureDisabled("socks"))
+            ));
+        }
+    }
+}
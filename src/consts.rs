@@ -6,16 +6,38 @@ use lazy_static::lazy_static;
 /// Examination Material Archive URL
 pub(crate) static EXAM_URL: &str = "https://www.examinations.ie/exammaterialarchive/";
 
+/// Path of the material files on the SEC website.
+#[cfg(feature = "server")]
+pub(crate) static ARCHIVE_PATH: &str = "/archive/";
+
+/// Month in which the SEC publishes the current year's material.
+const PUBLISH_MONTH: u32 = 6;
+
+/// The latest year we expect to have published material.
+///
+/// Papers are published after the June sitting, so the current
+/// year is only included from then onwards.
+fn last_published_year() -> u32 {
+    let now = Utc::now();
+    if now.month() >= PUBLISH_MONTH {
+        now.year() as u32
+    } else {
+        now.year() as u32 - 1
+    }
+}
+
 lazy_static! {
     /// Examination Paper years offered on the SEC website.
-    pub static ref EXAM_PAPER_YEARS: Vec<u32> = {
-        let years: Vec<u32> = (1995..Utc::now().year()).map(|x| x as u32).collect();
-        years
-    };
+    ///
+    /// **NOTE**: This is an offline fallback which assumes every year
+    /// since 1995 exists, prefer [`available_years`](crate::years::available_years)
+    /// which asks the website.
+    pub static ref EXAM_PAPER_YEARS: Vec<u32> = (1995..=last_published_year()).collect();
 
     /// Examination Paper Marking Schemes years offered on the SEC website.
-    pub static ref MARKING_SCHEME_YEARS: Vec<u32> = {
-        let years: Vec<u32> = (2001..Utc::now().year()).map(|x| x as u32).collect();
-        years
-    };
+    ///
+    /// **NOTE**: This is an offline fallback which assumes every year
+    /// since 2001 exists, prefer [`available_years`](crate::years::available_years)
+    /// which asks the website.
+    pub static ref MARKING_SCHEME_YEARS: Vec<u32> = (2001..=last_published_year()).collect();
 }
@@ -0,0 +1,803 @@
+//! A crawler for walking the stages of the examination archive.
+//!
+//! The crawler visits every chosen paper type, year, examination
+//! and subject in turn, collecting the material on the final stage.
+//! Finished subjects can be recorded in a [`Checkpoint`] to resume
+//! an interrupted crawl.
+
+use crate::{
+    checkpoint::{Checkpoint, CrawlNode},
+    client::SecClient,
+    config::Config,
+    error::{SecError, SecResult},
+    filter::MaterialFilter,
+    limiter::InFlightLimit,
+    material::{Material, Papers},
+    planner::Planner,
+    progress::{Progress, ProgressEvent, Reporter, Stage},
+    retry::RetryPolicy,
+    schema::{
+        metadata::{Examination, Session, Type},
+        subjects::Subject,
+    },
+    years::YearRange,
+};
+use futures_util::future::try_join_all;
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+};
+use strum::{EnumProperty, IntoEnumIterator};
+use tracing::{debug, info_span, Instrument};
+
+/// Main archive crawler.
+#[derive(Debug, Clone)]
+pub struct Crawler {
+    client: SecClient,
+    types: Vec<Type>,
+    years: Option<YearRange>,
+    examinations: Vec<Examination>,
+    subjects: Option<Vec<Subject>>,
+    sessions: Vec<Session>,
+    filter: MaterialFilter,
+    max_in_flight: Option<usize>,
+    max_in_flight_per_host: Option<usize>,
+    retry: RetryPolicy,
+    checkpoint: Option<Arc<Mutex<Checkpoint>>>,
+    max_failures: Option<u32>,
+    progress: Reporter,
+}
+
+impl Default for Crawler {
+    fn default() -> Self {
+        Self {
+            client: SecClient::new(),
+            types: Type::iter().collect(),
+            years: None,
+            examinations: Examination::iter().collect(),
+            subjects: None,
+            sessions: Session::iter().collect(),
+            filter: MaterialFilter::new(),
+            max_in_flight: None,
+            max_in_flight_per_host: None,
+            retry: RetryPolicy::never(),
+            checkpoint: None,
+            max_failures: None,
+            progress: Reporter::default(),
+        }
+    }
+}
+
+impl Crawler {
+    /// Create a new crawler visiting the whole archive.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a new crawler visiting the whole archive using the given configuration.
+    pub fn from_config(config: &Config) -> SecResult<Self> {
+        Ok(Self::new().client(SecClient::from_config(config)?))
+    }
+
+    /// Set the client used to query the website.
+    pub fn client(mut self, client: SecClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Set the paper types to crawl.
+    pub fn paper_types(mut self, types: &[Type]) -> Self {
+        self.types = types.to_vec();
+        self
+    }
+
+    /// Set the range of years to crawl.
+    pub fn years(mut self, years: YearRange) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    /// Set the examinations to crawl.
+    pub fn examinations(mut self, examinations: &[Examination]) -> Self {
+        self.examinations = examinations.to_vec();
+        self
+    }
+
+    /// Set the subjects to crawl.
+    pub fn subjects(mut self, subjects: &[Subject]) -> Self {
+        self.subjects = Some(subjects.to_vec());
+        self
+    }
+
+    /// Set the examination sessions to include.
+    pub fn sessions(mut self, sessions: &[Session]) -> Self {
+        self.sessions = sessions.to_vec();
+        self
+    }
+
+    /// Exclude an examination session.
+    pub fn exclude_session(mut self, session: Session) -> Self {
+        self.sessions.retain(|x| *x != session);
+        self
+    }
+
+    /// Only collect material matching a filter.
+    ///
+    /// The paper types, years, examinations and subjects of the filter
+    /// replace the ones set on the crawler, so branches of the archive
+    /// which can't match are never fetched. Lists of options named by the
+    /// filter aren't fetched either, see [`Planner`].
+    pub fn filter(mut self, filter: MaterialFilter) -> Self {
+        if !filter.types().is_empty() {
+            self.types = filter.types().to_vec();
+        }
+        if let Some(years) = filter.year_range() {
+            self.years = Some(years);
+        }
+        if !filter.examinations().is_empty() {
+            self.examinations = filter.examinations().to_vec();
+        }
+        if !filter.subjects().is_empty() {
+            self.subjects = Some(filter.subjects().to_vec());
+        }
+        self.filter = filter;
+        self
+    }
+
+    /// Set the number of queries the crawler may have in flight at once.
+    ///
+    /// Defaults to the client's [`max_in_flight`](SecClient::max_in_flight)
+    /// limit. Queries still wait for the client's rate limiter, if any.
+    pub fn max_in_flight(mut self, requests: usize) -> Self {
+        self.max_in_flight = Some(requests);
+        self
+    }
+
+    /// The number of queries that may be in flight at once.
+    fn in_flight_limit(&self) -> usize {
+        self.max_in_flight
+            .map_or_else(|| self.client.in_flight_limit(), |x| x.max(1))
+    }
+
+    /// Set the number of queries the crawler may have in flight to one host.
+    ///
+    /// Defaults to the [`max_in_flight`](Self::max_in_flight) limit.
+    pub fn max_in_flight_per_host(mut self, requests: usize) -> Self {
+        self.max_in_flight_per_host = Some(requests);
+        self
+    }
+
+    /// Set how failed queries are retried.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Resume from and record finished subjects into a checkpoint.
+    ///
+    /// Subjects completed in the checkpoint aren't queried again, while
+    /// failed ones are retried. A subject which fails is recorded in the
+    /// checkpoint and the rest of the archive is still crawled, so the
+    /// failures should be checked once the crawl ends. The checkpoint is
+    /// shared, so it can be saved if the crawl is interrupted.
+    pub fn checkpoint(mut self, checkpoint: Arc<Mutex<Checkpoint>>) -> Self {
+        self.checkpoint = Some(checkpoint);
+        self
+    }
+
+    /// Give up on subjects which failed the given number of crawls.
+    ///
+    /// Subjects recorded in the checkpoint with as many failures are
+    /// skipped, instead of being retried. Defaults to always retrying.
+    pub fn max_failures(mut self, failures: u32) -> Self {
+        self.max_failures = Some(failures);
+        self
+    }
+
+    /// Report progress to the given sink.
+    pub fn progress(mut self, progress: impl Progress + 'static) -> Self {
+        self.progress = Reporter::new(progress);
+        self
+    }
+
+    /// Record a completed node in the checkpoint, if any.
+    fn record(&self, node: CrawlNode, papers: &Papers) -> SecResult<()> {
+        if let Some(checkpoint) = &self.checkpoint {
+            let mut checkpoint = checkpoint.lock().unwrap();
+            checkpoint.complete(node, papers.clone());
+            checkpoint.maybe_save()?;
+        }
+        Ok(())
+    }
+
+    /// Run a query within the in-flight limit, retrying transient failures.
+    ///
+    /// Retries and failures are reported as progress events.
+    async fn query<T, F, Fut>(
+        &self,
+        state: &CrawlState,
+        target: impl Fn() -> String,
+        mut query: F,
+    ) -> SecResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = SecResult<T>>,
+    {
+        let result = self
+            .retry
+            .run(
+                || state.limited(query()),
+                |attempt, e| {
+                    self.progress.emit(|| ProgressEvent::Retry {
+                        target: target(),
+                        attempt,
+                        error: e.to_string(),
+                    })
+                },
+            )
+            .await;
+
+        // A subject without material isn't a failure.
+        match &result {
+            Err(SecError::NoMaterial) | Ok(_) => {}
+            Err(e) => self.progress.emit(|| ProgressEvent::Error {
+                target: target(),
+                error: e.to_string(),
+            }),
+        }
+        result
+    }
+
+    /// Check if the crawler should visit the given subject ID.
+    fn wants_subject(&self, subject_id: u32) -> bool {
+        match &self.subjects {
+            Some(subjects) => subjects
+                .iter()
+                .any(|x| x.get_str("id") == Some(subject_id.to_string().as_str())),
+            None => true,
+        }
+    }
+
+    /// Check if the crawler should keep the given material.
+    fn wants_material(&self, material: &Material) -> bool {
+        self.sessions.contains(&material.session) && self.filter.matches(material)
+    }
+
+    /// Crawl the archive, collecting every material found.
+    ///
+    /// Years, examinations and subjects are crawled concurrently, up to the
+    /// [`max_in_flight`](Self::max_in_flight) limit. Material is returned in
+    /// the same order as a sequential crawl.
+    pub async fn crawl(&self) -> SecResult<Papers> {
+        self.crawl_archive()
+            .instrument(info_span!("crawl", archive = %self.client.base_url()))
+            .await
+    }
+
+    async fn crawl_archive(&self) -> SecResult<Papers> {
+        let state = CrawlState {
+            limit: InFlightLimit::new(self.in_flight_limit()).per_host(
+                self.max_in_flight_per_host
+                    .unwrap_or(self.in_flight_limit()),
+            ),
+            host: self.client.base_url().host_str().unwrap_or("").to_string(),
+            subjects: Mutex::new((0, 0)),
+            planner: Planner::new(self.filter.clone()).client(self.client.clone()),
+        };
+        let mut papers = Papers::default();
+
+        for paper_type in &self.types {
+            let type_id = paper_type.id();
+
+            // Discover the years offered for this type, unless planned.
+            let years = match state.planner.years(paper_type) {
+                Some(years) => years,
+                None => {
+                    self.query(
+                        &state,
+                        || format!("years of {}", type_id),
+                        || self.client.available_years(paper_type),
+                    )
+                    .await?
+                }
+            };
+            let years: Vec<_> = years
+                .into_iter()
+                .filter(|x| self.years.is_none_or(|range| range.contains(*x)))
+                .collect();
+            self.progress.emit(|| ProgressEvent::StageDiscovered {
+                stage: Stage::Years {
+                    type_id: type_id.into(),
+                },
+                options: years.len(),
+            });
+
+            let found =
+                try_join_all(years.iter().map(|x| self.crawl_year(type_id, x.0, &state))).await?;
+            papers.extend(found);
+        }
+
+        Ok(papers)
+    }
+
+    /// Crawl every chosen examination offered in a year.
+    async fn crawl_year(&self, type_id: &str, year: u32, state: &CrawlState) -> SecResult<Papers> {
+        let exams: Vec<String> = match state.planner.examinations(type_id, year).await? {
+            Some(exams) => exams,
+            None => self
+                .query(
+                    state,
+                    || format!("examinations of {} {}", type_id, year),
+                    || self.client.parse_exams(type_id, year),
+                )
+                .await?
+                .into_keys()
+                .collect(),
+        };
+
+        // Skip examinations not offered this year.
+        let offered: Vec<&str> = self
+            .examinations
+            .iter()
+            .map(|x| x.id())
+            .filter(|exam_id| {
+                let offered = exams.iter().any(|x| x == exam_id);
+                if !offered {
+                    debug!(
+                        paper_type = type_id,
+                        year,
+                        exam = exam_id,
+                        "examination not offered"
+                    );
+                }
+                offered
+            })
+            .collect();
+        self.progress.emit(|| ProgressEvent::StageDiscovered {
+            stage: Stage::Examinations {
+                type_id: type_id.into(),
+                year,
+            },
+            options: offered.len(),
+        });
+
+        let found = try_join_all(
+            offered
+                .iter()
+                .map(|x| self.crawl_examination(type_id, year, x, state)),
+        )
+        .await?;
+        Ok(found.into_iter().collect())
+    }
+
+    /// Crawl every chosen subject of an examination.
+    async fn crawl_examination(
+        &self,
+        type_id: &str,
+        year: u32,
+        exam_id: &str,
+        state: &CrawlState,
+    ) -> SecResult<Papers> {
+        let subjects = match state.planner.subjects() {
+            Some(subjects) => subjects,
+            None => self
+                .query(
+                    state,
+                    || format!("subjects of {} {} {}", type_id, year, exam_id),
+                    || self.client.parse_subjects(type_id, year, exam_id),
+                )
+                .await?
+                .into_keys()
+                .collect(),
+        };
+        let mut subjects: Vec<u32> = subjects
+            .into_iter()
+            .filter(|x| self.wants_subject(*x))
+            .collect();
+        subjects.sort_unstable();
+        state.subjects.lock().unwrap().1 += subjects.len();
+        self.progress.emit(|| ProgressEvent::StageDiscovered {
+            stage: Stage::Subjects {
+                type_id: type_id.into(),
+                year,
+                exam_id: exam_id.into(),
+            },
+            options: subjects.len(),
+        });
+
+        let found = try_join_all(
+            subjects
+                .iter()
+                .map(|x| self.crawl_subject(type_id, year, exam_id, *x, state)),
+        )
+        .await?;
+        Ok(found.into_iter().collect())
+    }
+
+    /// Collect the material of a subject.
+    async fn crawl_subject(
+        &self,
+        type_id: &str,
+        year: u32,
+        exam_id: &str,
+        subject_id: u32,
+        state: &CrawlState,
+    ) -> SecResult<Papers> {
+        let node = CrawlNode::new(type_id, year, exam_id, subject_id);
+        let (resumed, failures) = match &self.checkpoint {
+            Some(checkpoint) => {
+                let checkpoint = checkpoint.lock().unwrap();
+                let failures = checkpoint.failed(&node).map_or(0, |x| x.failures);
+                (checkpoint.completed(&node).cloned(), failures)
+            }
+            None => (None, 0),
+        };
+        if self.max_failures.is_some_and(|max| failures >= max) {
+            debug!(node = %node, failures, "gave up after repeated failures");
+            return Ok(Papers::default());
+        }
+
+        let found = match resumed {
+            Some(found) => {
+                debug!(node = %node, "resumed from checkpoint");
+                found
+            }
+            None => {
+                let result = self
+                    .query(
+                        state,
+                        || format!("papers of {}", node),
+                        || self.client.parse_papers(type_id, year, exam_id, subject_id),
+                    )
+                    .await;
+                let found = match result {
+                    Ok(found) => found,
+                    Err(SecError::NoMaterial) => {
+                        debug!(
+                            paper_type = type_id,
+                            year,
+                            exam = exam_id,
+                            subject = subject_id,
+                            "no material offered"
+                        );
+                        Papers::default()
+                    }
+                    // With a checkpoint, the failure is recorded and the crawl goes on.
+                    Err(e) => match &self.checkpoint {
+                        Some(checkpoint) => {
+                            let mut checkpoint = checkpoint.lock().unwrap();
+                            checkpoint.fail(node, &e);
+                            checkpoint.maybe_save()?;
+                            return Ok(Papers::default());
+                        }
+                        None => return Err(e),
+                    },
+                };
+                self.record(node, &found)?;
+                found
+            }
+        };
+
+        // Filter the material after the checkpoint, so it can be resumed with other filters.
+        let mut papers = Papers {
+            materials: Vec::new(),
+            warnings: found.warnings,
+        };
+        for material in found.materials {
+            if self.wants_material(&material) {
+                self.progress
+                    .emit(|| ProgressEvent::MaterialFound(material.clone()));
+                papers.materials.push(material);
+            }
+        }
+
+        let (completed, total) = {
+            let mut subjects = state.subjects.lock().unwrap();
+            subjects.0 += 1;
+            *subjects
+        };
+        self.progress.emit(|| ProgressEvent::SubjectCompleted {
+            type_id: type_id.into(),
+            year,
+            exam_id: exam_id.into(),
+            subject_id,
+            completed,
+            total,
+        });
+
+        Ok(papers)
+    }
+}
+
+/// State shared by the concurrent parts of a crawl.
+struct CrawlState {
+    limit: InFlightLimit,
+    host: String,
+    /// Subjects completed and discovered so far.
+    subjects: Mutex<(usize, usize)>,
+    /// Skips the lists which the filter makes unnecessary.
+    planner: Planner,
+}
+
+impl CrawlState {
+    /// Run a query once the in-flight limit allows it.
+    async fn limited<T>(&self, query: impl Future<Output = T>) -> T {
+        let _permit = self.limit.acquire(&self.host).await;
+        query.await
+    }
+}
+
+#[cfg(test)]
+mod crawler_tests {
+    use super::*;
+    use crate::{cache::ResponseCache, schema::metadata::Level, test_util::MockServer};
+    use std::time::Duration;
+
+    #[test]
+    fn session_filter() {
+        let crawler = Crawler::new().exclude_session(Session::Deferred);
+        let mut material = Material::new(
+            "exampapers",
+            2021,
+            "lc",
+            3,
+            "Mathematics / Higher Level / Paper 1 - Deferred (EV)".into(),
+            url::Url::parse("https://www.examinations.ie/archive/deferred.pdf").unwrap(),
+        );
+        assert!(!crawler.wants_material(&material));
+
+        material.session = Session::Main;
+        assert!(crawler.wants_material(&material));
+    }
+
+    #[tokio::test]
+    async fn crawl_subject() -> SecResult<()> {
+        let server = MockServer::start();
+        let papers = Crawler::new()
+            .client(server.client())
+            .paper_types(&[Type::ExamPaper])
+            .years(YearRange::from(2019..=2019))
+            .examinations(&[Examination::LeavingCertificate])
+            .subjects(&[Subject::Mathematics])
+            .crawl()
+            .await?;
+
+        assert_eq!(3, papers.materials.len());
+        assert!(papers
+            .materials
+            .iter()
+            .all(|x| x.subject_id == 3 && x.year == 2019));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn material_filter() -> SecResult<()> {
+        let server = MockServer::start();
+        let filter: MaterialFilter =
+            "type:exampapers exam:lc subject:maths level:higher year:2019".parse()?;
+        let papers = Crawler::new()
+            .client(server.client())
+            .filter(filter)
+            .crawl()
+            .await?;
+
+        assert_eq!(2, papers.materials.len());
+        assert!(papers
+            .materials
+            .iter()
+            .all(|x| x.subject_id == 3 && x.level == Level::HigherLevel));
+
+        // Only the matching branch of each stage is queried.
+        let requests = server.requests();
+        assert!(requests.iter().all(|x| {
+            let selected = |name: &str, value: &str| {
+                x.get(&format!("MaterialArchive__noTable__sbv__{}", name))
+                    .is_none_or(|x| x.is_empty() || x == value)
+            };
+            selected("ViewType", "exampapers")
+                && selected("YearSelect", "2019")
+                && selected("ExaminationSelect", "lc")
+                && selected("SubjectSelect", "3")
+        }));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn concurrent_years() -> SecResult<()> {
+        let server = MockServer::start();
+        let crawler = Crawler::new()
+            .client(server.client())
+            .paper_types(&[Type::ExamPaper])
+            .years(YearRange::from(2017..=2019))
+            .examinations(&[Examination::LeavingCertificate])
+            .subjects(&[Subject::Mathematics]);
+        let sequential = crawler.crawl().await?;
+
+        // Each year needs three queries, which overlap with the other years
+        // up to the client's limit.
+        server.set_delay(Duration::from_millis(50));
+        let concurrent = crawler
+            .client(server.client().max_in_flight(4))
+            .crawl()
+            .await?;
+        assert!(server.max_in_flight() > 1);
+        assert!(server.max_in_flight() <= 4);
+        assert_eq!(sequential, concurrent);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn resume_checkpoint() -> SecResult<()> {
+        let server = MockServer::start();
+        let checkpoint = Arc::new(Mutex::new(Checkpoint::new()));
+        let crawler = Crawler::new()
+            .client(server.client())
+            .paper_types(&[Type::ExamPaper])
+            .years(YearRange::from(2019..=2019))
+            .examinations(&[Examination::LeavingCertificate])
+            .subjects(&[Subject::Mathematics, Subject::Irish])
+            .checkpoint(checkpoint.clone());
+
+        let first = crawler.crawl().await?;
+        assert_eq!(2, checkpoint.lock().unwrap().len());
+
+        // Only the stages are queried again, not the completed subjects.
+        let queries = server.request_count();
+        let resumed = crawler.crawl().await?;
+        assert_eq!(first, resumed);
+        assert_eq!(queries + 2, server.request_count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_subjects() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server
+            .client()
+            .cache(ResponseCache::new(Duration::from_secs(60)));
+        client.available_years(&Type::ExamPaper).await?;
+        client.parse_exams("exampapers", 2019).await?;
+        client.parse_subjects("exampapers", 2019, "lc").await?;
+        let checkpoint = Arc::new(Mutex::new(Checkpoint::new()));
+        let crawler = Crawler::new()
+            .client(client)
+            .paper_types(&[Type::ExamPaper])
+            .years(YearRange::from(2019..=2019))
+            .examinations(&[Examination::LeavingCertificate])
+            .subjects(&[Subject::Irish, Subject::Mathematics])
+            .checkpoint(checkpoint.clone());
+
+        // Irish fails, but Mathematics is still crawled.
+        server.fail_next(1, 500);
+        let papers = crawler.crawl().await?;
+        assert!(papers.materials.iter().all(|x| x.subject_id == 3));
+        assert!(!papers.materials.is_empty());
+        let failed = CrawlNode::new("exampapers", 2019, "lc", 1);
+        assert_eq!(
+            1,
+            checkpoint.lock().unwrap().failed(&failed).unwrap().failures
+        );
+
+        // A subject which reached the limit isn't retried.
+        let queries = server.request_count();
+        crawler.clone().max_failures(1).crawl().await?;
+        assert_eq!(queries, server.request_count());
+
+        // Otherwise it is.
+        crawler.crawl().await?;
+        assert_eq!(queries + 1, server.request_count());
+        assert!(checkpoint.lock().unwrap().failed(&failed).is_none());
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[tokio::test]
+    async fn autosaved_failures() -> SecResult<()> {
+        let path = std::env::temp_dir().join(format!("resec-failures-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let server = MockServer::start();
+        let client = server
+            .client()
+            .cache(ResponseCache::new(Duration::from_secs(60)));
+        client.available_years(&Type::ExamPaper).await?;
+        client.parse_exams("exampapers", 2019).await?;
+        client.parse_subjects("exampapers", 2019, "lc").await?;
+        let crawler = Crawler::new()
+            .client(client)
+            .paper_types(&[Type::ExamPaper])
+            .years(YearRange::from(2019..=2019))
+            .examinations(&[Examination::LeavingCertificate])
+            .subjects(&[Subject::Irish])
+            .checkpoint(Arc::new(Mutex::new(Checkpoint::open(&path, 1)?)));
+
+        // The failure is saved without a later subject completing.
+        server.fail_next(1, 500);
+        crawler.crawl().await?;
+        let saved = Checkpoint::load(&path)?;
+        let failed = CrawlNode::new("exampapers", 2019, "lc", 1);
+        assert_eq!(1, saved.failed(&failed).unwrap().failures);
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retry_failures() -> SecResult<()> {
+        let server = MockServer::start();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let crawler = Crawler::new()
+            .client(server.client())
+            .paper_types(&[Type::ExamPaper])
+            .years(YearRange::from(2019..=2019))
+            .examinations(&[Examination::LeavingCertificate])
+            .subjects(&[Subject::Mathematics])
+            .progress(sender);
+
+        server.fail_next(1, 503);
+        let papers = crawler
+            .retry_policy(RetryPolicy::new(1).backoff(Duration::from_millis(1)))
+            .crawl()
+            .await?;
+        assert_eq!(3, papers.materials.len());
+        assert!(receiver
+            .try_iter()
+            .any(|x| matches!(x, ProgressEvent::Retry { attempt: 2, .. })));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn progress_events() -> SecResult<()> {
+        let server = MockServer::start();
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let papers = Crawler::new()
+            .client(server.client())
+            .paper_types(&[Type::ExamPaper])
+            .years(YearRange::from(2019..=2019))
+            .examinations(&[Examination::LeavingCertificate])
+            .subjects(&[Subject::Mathematics])
+            .progress(sender)
+            .crawl()
+            .await?;
+
+        let events: Vec<ProgressEvent> = receiver.try_iter().collect();
+        assert_eq!(
+            ProgressEvent::StageDiscovered {
+                stage: Stage::Years {
+                    type_id: "exampapers".into()
+                },
+                options: 1,
+            },
+            events[0]
+        );
+        let found = events
+            .iter()
+            .filter(|x| matches!(x, ProgressEvent::MaterialFound(_)))
+            .count();
+        assert_eq!(papers.materials.len(), found);
+        assert_eq!(
+            Some(&ProgressEvent::SubjectCompleted {
+                type_id: "exampapers".into(),
+                year: 2019,
+                exam_id: "lc".into(),
+                subject_id: 3,
+                completed: 1,
+                total: 1,
+            }),
+            events.last()
+        );
+
+        // Failed queries are reported before the error is returned.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        server.fail_next(1, 503);
+        let result = Crawler::new()
+            .client(server.client())
+            .paper_types(&[Type::ExamPaper])
+            .progress(sender)
+            .crawl()
+            .await;
+        assert!(result.is_err());
+        assert!(receiver
+            .try_iter()
+            .any(|x| matches!(x, ProgressEvent::Error { .. })));
+        Ok(())
+    }
+}
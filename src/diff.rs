@@ -0,0 +1,396 @@
+//! Comparison of two manifests.
+//!
+//! Materials are matched across manifests by their download link, then any
+//! left over are matched by subject and name, and the changes are grouped
+//! per subject. This picks up marking schemes which are republished with
+//! corrections under the same name, whether or not their link changed.
+
+use crate::{
+    manifest::{Manifest, ManifestEntry},
+    material::Material,
+};
+use std::{collections::BTreeMap, fmt};
+use strum::EnumProperty;
+use url::Url;
+
+#[cfg(feature = "serde")]
+use crate::error::SecResult;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A change to a single material.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    /// The material is new.
+    Added(Material),
+    /// The material is no longer offered.
+    Removed(Material),
+    /// The material's contents changed, possibly republished under a new link.
+    Modified {
+        material: Material,
+        old_sha256: String,
+        new_sha256: String,
+    },
+    /// The material has the same link but its description changed.
+    Renamed {
+        material: Material,
+        old_name: String,
+    },
+    /// The material has the same name and contents but a new link.
+    Moved { material: Material, old_link: Url },
+}
+
+impl Change {
+    /// The material the change applies to, as found in the newest manifest.
+    pub fn material(&self) -> &Material {
+        match self {
+            Change::Added(x) | Change::Removed(x) => x,
+            Change::Modified { material, .. }
+            | Change::Renamed { material, .. }
+            | Change::Moved { material, .. } => material,
+        }
+    }
+}
+
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Change::Added(x) => write!(f, "+ {} ({})", x.name, x.link),
+            Change::Removed(x) => write!(f, "- {} ({})", x.name, x.link),
+            Change::Modified {
+                material,
+                old_sha256,
+                new_sha256,
+            } => write!(
+                f,
+                "~ {} changed ({:.8} -> {:.8})",
+                material.name, old_sha256, new_sha256
+            ),
+            Change::Renamed { material, old_name } => {
+                write!(f, "~ {} renamed to {}", old_name, material.name)
+            }
+            Change::Moved { material, old_link } => {
+                write!(
+                    f,
+                    "~ {} moved ({} -> {})",
+                    material.name, old_link, material.link
+                )
+            }
+        }
+    }
+}
+
+/// The changes to a single subject.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubjectDiff {
+    pub type_id: String,
+    pub year: u32,
+    pub exam_id: String,
+    pub subject_id: u32,
+    pub changes: Vec<Change>,
+}
+
+/// The changes between two manifests.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DiffReport {
+    /// Subjects with at least one change, sorted by paper type, year,
+    /// examination and subject ID.
+    pub subjects: Vec<SubjectDiff>,
+}
+
+impl DiffReport {
+    /// Check if there were no changes.
+    pub fn is_empty(&self) -> bool {
+        self.subjects.is_empty()
+    }
+
+    /// Iterate through every change.
+    pub fn changes(&self) -> impl Iterator<Item = &Change> {
+        self.subjects.iter().flat_map(|x| x.changes.iter())
+    }
+
+    /// Render the report as JSON.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> SecResult<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+impl fmt::Display for DiffReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if self.is_empty() {
+            return writeln!(f, "No changes.");
+        }
+
+        for subject in &self.subjects {
+            let name = subject
+                .changes
+                .first()
+                .and_then(|x| x.material().subject())
+                .and_then(|x| x.get_str("label"))
+                .map(String::from)
+                .unwrap_or_else(|| subject.subject_id.to_string());
+            writeln!(
+                f,
+                "{} {} {} {}:",
+                subject.type_id, subject.year, subject.exam_id, name
+            )?;
+            for change in &subject.changes {
+                writeln!(f, "  {}", change)?;
+            }
+        }
+
+        // Finally, summarise the counts.
+        let count = |check: fn(&Change) -> bool| self.changes().filter(|x| check(x)).count();
+        writeln!(
+            f,
+            "{} added, {} removed, {} modified, {} renamed, {} moved.",
+            count(|x| matches!(x, Change::Added(_))),
+            count(|x| matches!(x, Change::Removed(_))),
+            count(|x| matches!(x, Change::Modified { .. })),
+            count(|x| matches!(x, Change::Renamed { .. })),
+            count(|x| matches!(x, Change::Moved { .. })),
+        )
+    }
+}
+
+/// Key used to group changes per subject.
+type SubjectKey = (String, u32, String, u32);
+
+/// Find the subject key of a material.
+fn subject_key(material: &Material) -> SubjectKey {
+    (
+        material.type_id.clone(),
+        material.year,
+        material.exam_id.clone(),
+        material.subject_id,
+    )
+}
+
+/// Compare an old manifest with a new one.
+pub fn diff(old: &Manifest, new: &Manifest) -> DiffReport {
+    let mut subjects: BTreeMap<SubjectKey, Vec<Change>> = BTreeMap::new();
+    let mut push = |change: Change| {
+        subjects
+            .entry(subject_key(change.material()))
+            .or_default()
+            .push(change)
+    };
+
+    // Index the old manifest by link.
+    let old_entries: BTreeMap<&str, &ManifestEntry> = old
+        .entries
+        .iter()
+        .map(|x| (x.material.link.as_str(), x))
+        .collect();
+    let new_entries: BTreeMap<&str, &ManifestEntry> = new
+        .entries
+        .iter()
+        .map(|x| (x.material.link.as_str(), x))
+        .collect();
+
+    // Entries without a match by link are matched by name afterwards.
+    let mut added = Vec::new();
+    let mut removed: Vec<Option<&ManifestEntry>> = old_entries
+        .iter()
+        .filter(|(link, _)| !new_entries.contains_key(*link))
+        .map(|(_, entry)| Some(*entry))
+        .collect();
+
+    for (link, entry) in &new_entries {
+        let material = entry.material.clone();
+        let previous = match old_entries.get(link) {
+            Some(x) => x,
+            None => {
+                added.push(*entry);
+                continue;
+            }
+        };
+
+        // Hashes are only compared when both manifests have them.
+        if let (Some(old_sha256), Some(new_sha256)) = (&previous.sha256, &entry.sha256) {
+            if old_sha256 != new_sha256 {
+                push(Change::Modified {
+                    material: material.clone(),
+                    old_sha256: old_sha256.clone(),
+                    new_sha256: new_sha256.clone(),
+                });
+            }
+        }
+
+        if previous.material.name != material.name {
+            push(Change::Renamed {
+                old_name: previous.material.name.clone(),
+                material,
+            });
+        }
+    }
+
+    // A material republished under a new link keeps its subject and name.
+    for entry in added {
+        let material = entry.material.clone();
+        let previous = removed.iter_mut().find(|x| {
+            x.is_some_and(|x| {
+                subject_key(&x.material) == subject_key(&material)
+                    && x.material.name == material.name
+            })
+        });
+        let previous = match previous.and_then(Option::take) {
+            Some(x) => x,
+            None => {
+                push(Change::Added(material));
+                continue;
+            }
+        };
+
+        match (&previous.sha256, &entry.sha256) {
+            (Some(old_sha256), Some(new_sha256)) if old_sha256 != new_sha256 => {
+                push(Change::Modified {
+                    material,
+                    old_sha256: old_sha256.clone(),
+                    new_sha256: new_sha256.clone(),
+                })
+            }
+            _ => push(Change::Moved {
+                material,
+                old_link: previous.material.link.clone(),
+            }),
+        }
+    }
+
+    for entry in removed.into_iter().flatten() {
+        push(Change::Removed(entry.material.clone()));
+    }
+
+    DiffReport {
+        subjects: subjects
+            .into_iter()
+            .map(
+                |((type_id, year, exam_id, subject_id), changes)| SubjectDiff {
+                    type_id,
+                    year,
+                    exam_id,
+                    subject_id,
+                    changes,
+                },
+            )
+            .collect(),
+    }
+}
+
+#[cfg(test)]
+mod diff_tests {
+    use super::*;
+    use url::Url;
+
+    fn entry(name: &str, file: &str, sha256: &str) -> ManifestEntry {
+        let link = Url::parse("https://www.examinations.ie/archive/markingschemes/2019/")
+            .unwrap()
+            .join(file)
+            .unwrap();
+        ManifestEntry {
+            material: Material::new("markingschemes", 2019, "lc", 22, name.into(), link),
+            size: Some(1024),
+            sha256: Some(sha256.into()),
+            validators: Default::default(),
+        }
+    }
+
+    fn manifest(entries: Vec<ManifestEntry>) -> Manifest {
+        Manifest {
+            created: chrono::Utc::now(),
+            entries,
+        }
+    }
+
+    #[test]
+    fn changes() {
+        let old = manifest(vec![
+            entry("Chemistry / Higher Level (EV)", "a.pdf", "aaaa"),
+            entry("Chemistry / Ordinary Level (EV)", "b.pdf", "bbbb"),
+            entry("Chemistry / Higher Level (IV)", "c.pdf", "cccc"),
+        ]);
+        let new = manifest(vec![
+            entry("Chemistry / Higher Level (EV)", "a.pdf", "aaab"),
+            entry("Chemistry / Ordinary Level (English)", "b.pdf", "bbbb"),
+            entry("Chemistry / Common Level (EV)", "d.pdf", "dddd"),
+        ]);
+        let report = diff(&old, &new);
+
+        assert_eq!(1, report.subjects.len());
+        let changes: Vec<&Change> = report.changes().collect();
+        assert_eq!(4, changes.len());
+        assert!(matches!(changes[0], Change::Modified { old_sha256, .. } if old_sha256 == "aaaa"));
+        assert!(matches!(changes[1], Change::Renamed { old_name, .. }
+            if old_name == "Chemistry / Ordinary Level (EV)"));
+        assert!(matches!(changes[2], Change::Added(x) if x.name.contains("Common")));
+        assert!(matches!(changes[3], Change::Removed(x) if x.name.contains("(IV)")));
+
+        let summary = report.to_string();
+        assert!(summary.starts_with("markingschemes 2019 lc Chemistry:"));
+        assert!(summary.ends_with("1 added, 1 removed, 1 modified, 1 renamed, 0 moved.\n"));
+    }
+
+    #[test]
+    fn republished() {
+        let old = manifest(vec![
+            entry("Chemistry / Higher Level (EV)", "a.pdf", "aaaa"),
+            entry("Chemistry / Ordinary Level (EV)", "b.pdf", "bbbb"),
+            entry("Chemistry / Higher Level (IV)", "c.pdf", "cccc"),
+        ]);
+        let new = manifest(vec![
+            entry("Chemistry / Higher Level (EV)", "a2.pdf", "aaab"),
+            entry("Chemistry / Ordinary Level (EV)", "b2.pdf", "bbbb"),
+            entry("Chemistry / Higher Level (IV)", "c.pdf", "cccc"),
+        ]);
+        let report = diff(&old, &new);
+
+        // Corrected under a new link, then moved without changes.
+        let changes: Vec<&Change> = report.changes().collect();
+        assert_eq!(2, changes.len());
+        assert!(
+            matches!(changes[0], Change::Modified { material, old_sha256, .. }
+            if old_sha256 == "aaaa" && material.link.as_str().ends_with("a2.pdf"))
+        );
+        assert!(matches!(changes[1], Change::Moved { old_link, .. }
+            if old_link.as_str().ends_with("/b.pdf")));
+        assert!(report
+            .to_string()
+            .ends_with("0 added, 0 removed, 1 modified, 0 renamed, 1 moved.\n"));
+    }
+
+    #[test]
+    fn unchanged() {
+        let old = manifest(vec![entry("Chemistry (EV)", "a.pdf", "aaaa")]);
+        let mut new = old.clone();
+        new.entries[0].sha256 = None;
+
+        let report = diff(&old, &new);
+        assert!(report.is_empty());
+        assert_eq!("No changes.\n", report.to_string());
+    }
+
+    #[test]
+    fn subject_labels() {
+        let mut added = entry("History (EV)", "a.pdf", "aaaa");
+        added.material.subject_id = 4;
+        let report = diff(&manifest(Vec::new()), &manifest(vec![added]));
+        assert!(report
+            .to_string()
+            .starts_with("markingschemes 2019 lc History – Later Modern:"));
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn json_report() -> SecResult<()> {
+        let old = manifest(vec![entry("Chemistry (EV)", "a.pdf", "aaaa")]);
+        let report = diff(&old, &manifest(Vec::new()));
+
+        let json = report.to_json()?;
+        assert_eq!(report, serde_json::from_str::<DiffReport>(&json)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,646 @@
+//! A downloader for saving material to disk.
+//!
+//! Material is laid out by paper type, year, examination and subject,
+//! with audio optionally routed into a separate directory.
+
+use crate::{
+    client::SecClient,
+    config::Config,
+    error::SecResult,
+    limiter::InFlightLimit,
+    manifest::{Manifest, ManifestEntry},
+    material::{FileFormat, Material, MaterialKind},
+    probe::Validators,
+    progress::{Progress, ProgressEvent, Reporter},
+    retry::RetryPolicy,
+    schema::metadata::Session,
+};
+use futures_util::stream::{self, StreamExt, TryStreamExt};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    fs::{self, File},
+    path::{Path, PathBuf},
+    time::Instant,
+};
+use strum::IntoEnumIterator;
+use tokio::io::AsyncWriteExt;
+use tracing::{debug, field, info_span, warn, Instrument, Span};
+use url::Url;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// A material saved to disk.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Download {
+    /// The material that was downloaded.
+    pub material: Material,
+    /// Where the material was saved.
+    pub path: PathBuf,
+    /// Size of the material in bytes.
+    pub size: u64,
+    /// Hex encoded SHA-256 hash of the material.
+    pub sha256: String,
+    /// The validators of the downloaded version.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub validators: Validators,
+}
+
+/// Main material downloader.
+#[derive(Debug, Clone)]
+pub struct Downloader {
+    client: SecClient,
+    directory: PathBuf,
+    audio_directory: Option<PathBuf>,
+    skip_archives: bool,
+    max_size: Option<u64>,
+    sessions: Vec<Session>,
+    retry: RetryPolicy,
+    max_in_flight: Option<usize>,
+    max_in_flight_per_host: Option<usize>,
+    progress: Reporter,
+    previous: HashMap<Url, ManifestEntry>,
+}
+
+impl Downloader {
+    /// Create a new downloader saving into the given directory.
+    pub fn new(directory: impl Into<PathBuf>) -> Self {
+        Self {
+            client: SecClient::new(),
+            directory: directory.into(),
+            audio_directory: None,
+            skip_archives: false,
+            max_size: None,
+            sessions: Session::iter().collect(),
+            retry: RetryPolicy::never(),
+            max_in_flight: None,
+            max_in_flight_per_host: None,
+            progress: Reporter::default(),
+            previous: HashMap::new(),
+        }
+    }
+
+    /// Create a new downloader using the given configuration.
+    ///
+    /// Material is saved into the configured download directory.
+    pub fn from_config(config: &Config) -> SecResult<Self> {
+        Ok(Self::new(&config.download_dir).client(SecClient::from_config(config)?))
+    }
+
+    /// Set the client used to download material.
+    pub fn client(mut self, client: SecClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Route audio files into a separate directory.
+    pub fn audio_directory(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.audio_directory = Some(directory.into());
+        self
+    }
+
+    /// Skip ZIP archives and other attachments.
+    pub fn skip_archives(mut self, flag: bool) -> Self {
+        self.skip_archives = flag;
+        self
+    }
+
+    /// Skip material larger than the given size in bytes.
+    pub fn max_size(mut self, bytes: u64) -> Self {
+        self.max_size = Some(bytes);
+        self
+    }
+
+    /// Set the examination sessions to download.
+    pub fn sessions(mut self, sessions: &[Session]) -> Self {
+        self.sessions = sessions.to_vec();
+        self
+    }
+
+    /// Exclude an examination session.
+    pub fn exclude_session(mut self, session: Session) -> Self {
+        self.sessions.retain(|x| *x != session);
+        self
+    }
+
+    /// Retry failed requests up to the given number of times.
+    ///
+    /// Connection errors, timeouts and server errors are retried with an
+    /// exponential backoff starting at half a second.
+    pub fn retries(self, retries: u32) -> Self {
+        self.retry_policy(RetryPolicy::new(retries))
+    }
+
+    /// Set how failed requests are retried.
+    pub fn retry_policy(mut self, policy: RetryPolicy) -> Self {
+        self.retry = policy;
+        self
+    }
+
+    /// Set the number of downloads that may be in flight at once.
+    ///
+    /// Defaults to the client's [`max_in_flight`](SecClient::max_in_flight)
+    /// limit. Downloads still wait for the client's rate limiter, if any.
+    pub fn max_in_flight(mut self, requests: usize) -> Self {
+        self.max_in_flight = Some(requests);
+        self
+    }
+
+    /// The number of downloads that may be in flight at once.
+    fn in_flight_limit(&self) -> usize {
+        self.max_in_flight
+            .map_or_else(|| self.client.in_flight_limit(), |x| x.max(1))
+    }
+
+    /// Set the number of downloads that may be in flight from one host.
+    ///
+    /// Defaults to the [`max_in_flight`](Self::max_in_flight) limit.
+    pub fn max_in_flight_per_host(mut self, requests: usize) -> Self {
+        self.max_in_flight_per_host = Some(requests);
+        self
+    }
+
+    /// Report progress to the given sink.
+    pub fn progress(mut self, progress: impl Progress + 'static) -> Self {
+        self.progress = Reporter::new(progress);
+        self
+    }
+
+    /// Skip material unchanged since an earlier download.
+    ///
+    /// Material still on disk with the size recorded in the manifest is
+    /// requested conditionally, and kept with its recorded hash if the
+    /// server reports it unchanged.
+    pub fn previous(mut self, manifest: &Manifest) -> Self {
+        self.previous = manifest
+            .entries
+            .iter()
+            .filter(|x| x.size.is_some() && x.sha256.is_some() && !x.validators.is_empty())
+            .map(|x| (x.material.link.clone(), x.clone()))
+            .collect();
+        self
+    }
+
+    /// Find the earlier download of a material, if it is still at the path.
+    fn previous_download(&self, material: &Material, path: &Path) -> Option<Download> {
+        let entry = self.previous.get(&material.link)?;
+        let size = fs::metadata(path).ok()?.len();
+        if Some(size) != entry.size {
+            debug!(path = %path.display(), "earlier download was changed on disk");
+            return None;
+        }
+
+        Some(Download {
+            material: material.clone(),
+            path: path.to_path_buf(),
+            size,
+            sha256: entry.sha256.clone()?,
+            validators: entry.validators.clone(),
+        })
+    }
+
+    /// Check if the downloader should fetch the given material.
+    fn wants_material(&self, material: &Material) -> bool {
+        let archive = material.kind == MaterialKind::Attachment
+            || material.format == FileFormat::Zip;
+        self.sessions.contains(&material.session) && !(self.skip_archives && archive)
+    }
+
+    /// Generate the path the material will be saved to.
+    pub fn path_for(&self, material: &Material) -> PathBuf {
+        // Route audio into its own directory if requested.
+        let root = match (&self.audio_directory, material.kind) {
+            (Some(audio), MaterialKind::AudioFile) => audio,
+            _ => &self.directory,
+        };
+
+        // Name the file after the last segment of the link.
+        let file_name = material
+            .link
+            .path_segments()
+            .and_then(|mut x| x.next_back())
+            .filter(|x| !x.is_empty())
+            .unwrap_or("material");
+
+        root.join(&material.type_id)
+            .join(material.year.to_string())
+            .join(&material.exam_id)
+            .join(material.subject_id.to_string())
+            .join(file_name)
+    }
+
+    /// Download a single material.
+    ///
+    /// Returns ``None`` if the material was skipped by a filter. Each download
+    /// runs in a ``download`` span recording the latency, status and size.
+    pub async fn download(&self, material: &Material) -> SecResult<Option<Download>> {
+        self.download_to(material, self.path_for(material)).await
+    }
+
+    /// Download a single material to the given path, rather than its
+    /// [`path_for`](Self::path_for).
+    pub(crate) async fn download_to(
+        &self,
+        material: &Material,
+        path: PathBuf,
+    ) -> SecResult<Option<Download>> {
+        let span = info_span!(
+            "download",
+            link = %material.link,
+            status = field::Empty,
+            bytes = field::Empty,
+            latency_ms = field::Empty,
+        );
+        let result = self
+            .fetch(material, path, &span)
+            .instrument(span.clone())
+            .await;
+        if let Err(e) = &result {
+            self.progress.emit(|| ProgressEvent::Error {
+                target: material.link.to_string(),
+                error: e.to_string(),
+            });
+        }
+        result
+    }
+
+    /// Send the request for a material, retrying transient failures.
+    ///
+    /// The request is conditional on the given validators.
+    async fn request(
+        &self,
+        material: &Material,
+        since: &Validators,
+        span: &Span,
+    ) -> SecResult<reqwest::Response> {
+        let send = || async {
+            self.client.throttle().await;
+            let request = since.apply(self.client.http().get(material.link.clone()));
+            let response = self.client.read(request.send()).await?;
+            span.record("status", response.status().as_u16());
+            Ok(response.error_for_status()?)
+        };
+
+        self.retry
+            .run(send, |attempt, error| {
+                warn!(attempt, %error, "retrying download");
+                self.progress.emit(|| ProgressEvent::Retry {
+                    target: material.link.to_string(),
+                    attempt,
+                    error: error.to_string(),
+                });
+            })
+            .await
+    }
+
+    /// Fetch a material to disk, recording the response in the span.
+    async fn fetch(
+        &self,
+        material: &Material,
+        path: PathBuf,
+        span: &Span,
+    ) -> SecResult<Option<Download>> {
+        if !self.wants_material(material) {
+            debug!(kind = %material.kind, session = %material.session, "skipped by filter");
+            return Ok(None);
+        }
+
+        let started = Instant::now();
+        let previous = self.previous_download(material, &path);
+        let since = previous
+            .as_ref()
+            .map(|x| x.validators.clone())
+            .unwrap_or_default();
+        let response = self.request(material, &since, span).await?;
+
+        // Keep the earlier download if the material is unchanged.
+        if let (StatusCode::NOT_MODIFIED, Some(previous)) = (response.status(), previous) {
+            debug!(path = %previous.path.display(), "unchanged since the last download");
+            span.record("latency_ms", started.elapsed().as_millis() as u64);
+            self.progress.emit(|| ProgressEvent::DownloadUnchanged {
+                link: material.link.clone(),
+                bytes: previous.size,
+            });
+            return Ok(Some(previous));
+        }
+
+        // Skip large material before reading the body if the size is known.
+        if let (Some(max), Some(size)) = (self.max_size, response.content_length()) {
+            if size > max {
+                debug!(size, max, "skipped material over the size limit");
+                return Ok(None);
+            }
+        }
+
+        // Prepare the output file.
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent).await?;
+        }
+        let total = response.content_length();
+        let validators = Validators::from_headers(response.headers());
+        self.progress.emit(|| ProgressEvent::DownloadStarted {
+            link: material.link.clone(),
+            size: total,
+        });
+
+        // Write into a partial file, so a failed download leaves any
+        // earlier one in place.
+        let part = partial_path(&path);
+        let (size, sha256) = match self.write_body(material, response, &part, total).await {
+            Ok(Some(written)) => written,
+            other => {
+                let _ = tokio::fs::remove_file(&part).await;
+                return other.map(|_| None);
+            }
+        };
+        tokio::fs::rename(&part, &path).await?;
+        span.record("bytes", size);
+        span.record("latency_ms", started.elapsed().as_millis() as u64);
+        self.progress.emit(|| ProgressEvent::DownloadCompleted {
+            link: material.link.clone(),
+            bytes: size,
+        });
+
+        Ok(Some(Download {
+            material: material.clone(),
+            path,
+            size,
+            sha256,
+            validators,
+        }))
+    }
+
+    /// Stream a response body to a file, hashing as we go.
+    ///
+    /// The file is written on the runtime's blocking pool, so concurrent
+    /// downloads don't stall each other. Returns the size and hash, or ``None`` if the body is over the size limit.
+    async fn write_body(
+        &self,
+        material: &Material,
+        mut response: reqwest::Response,
+        path: &Path,
+        total: Option<u64>,
+    ) -> SecResult<Option<(u64, String)>> {
+        let mut file = tokio::fs::File::create(path).await?;
+        let mut hasher = Sha256::new();
+        let mut size = 0;
+        while let Some(chunk) = self.client.read(response.chunk()).await? {
+            size += chunk.len() as u64;
+            // Responses without a length are only checked as they arrive.
+            if let Some(max) = self.max_size.filter(|max| size > *max) {
+                debug!(size, max, "stopped material over the size limit");
+                return Ok(None);
+            }
+            hasher.update(&chunk);
+            file.write_all(&chunk).await?;
+            self.progress.emit(|| ProgressEvent::BytesDownloaded {
+                link: material.link.clone(),
+                bytes: size,
+                size: total,
+            });
+        }
+        file.sync_all().await?;
+        Ok(Some((size, hex(&hasher.finalize()))))
+    }
+
+    /// Download every given material, skipping filtered ones.
+    ///
+    /// Material is downloaded concurrently up to the
+    /// [`max_in_flight`](Self::max_in_flight) limits, and returned in
+    /// the order given.
+    pub async fn download_all(&self, materials: &[Material]) -> SecResult<Vec<Download>> {
+        let limit = InFlightLimit::new(self.in_flight_limit()).per_host(
+            self.max_in_flight_per_host
+                .unwrap_or(self.in_flight_limit()),
+        );
+
+        let downloads: Vec<Option<Download>> = stream::iter(materials)
+            .map(|material| {
+                let limit = &limit;
+                async move {
+                    let _permit = limit.acquire(material.link.host_str().unwrap_or("")).await;
+                    self.download(material).await
+                }
+            })
+            .buffered(self.in_flight_limit())
+            .try_collect()
+            .await?;
+        Ok(downloads.into_iter().flatten().collect())
+    }
+}
+
+/// Hash a file on disk, returning the hex encoded SHA-256.
+pub fn hash_file(path: &Path) -> SecResult<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    std::io::copy(&mut file, &mut hasher)?;
+    Ok(hex(&hasher.finalize()))
+}
+
+/// The path a download is written to until it completes.
+fn partial_path(path: &Path) -> PathBuf {
+    let mut part = path.as_os_str().to_owned();
+    part.push(".part");
+    PathBuf::from(part)
+}
+
+/// Encode bytes as lowercase hex.
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{:02x}", x)).collect()
+}
+
+#[cfg(test)]
+mod downloader_tests {
+    use super::*;
+    use crate::test_util::{BodyMode, MockServer};
+
+    fn material(name: &str, link: &str) -> Material {
+        let link = Url::parse(link).unwrap();
+        Material::new("exampapers", 2019, "lc", 10, name.into(), link)
+    }
+
+    #[test]
+    fn audio_routing() {
+        let downloader = Downloader::new("papers").audio_directory("audio");
+        let paper = material(
+            "French / Higher Level (EV)",
+            "https://www.examinations.ie/archive/exampapers/2019/LC010ALP000EV.pdf",
+        );
+        let aural = material(
+            "French / Aural",
+            "https://www.examinations.ie/archive/exampapers/2019/LC010ALP000EV.mp3",
+        );
+
+        assert_eq!(
+            Path::new("papers/exampapers/2019/lc/10/LC010ALP000EV.pdf"),
+            downloader.path_for(&paper)
+        );
+        assert_eq!(
+            Path::new("audio/exampapers/2019/lc/10/LC010ALP000EV.mp3"),
+            downloader.path_for(&aural)
+        );
+    }
+
+    #[test]
+    fn skip_archives() {
+        let downloader = Downloader::new("papers").skip_archives(true);
+        let archive = material("Design / Files", "https://example.com/files.zip");
+        let paper = material("Design (EV)", "https://example.com/paper.pdf");
+
+        assert!(!downloader.wants_material(&archive));
+        assert!(downloader.wants_material(&paper));
+    }
+
+    #[test]
+    fn hashing() -> SecResult<()> {
+        let path = std::env::temp_dir().join(format!("resec-hash-{}.txt", std::process::id()));
+        fs::write(&path, b"abc")?;
+        assert_eq!(
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad",
+            hash_file(&path)?
+        );
+        fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn retries_and_progress() -> SecResult<()> {
+        let server = MockServer::start();
+        server.set_file("/archive/exampapers/2019/LC010ALP000EV.pdf", vec![7; 100]);
+        let mut paper = material("French / Higher Level (EV)", "https://example.com");
+        paper.link = server
+            .url()
+            .join("/archive/exampapers/2019/LC010ALP000EV.pdf")
+            .unwrap();
+        let directory = std::env::temp_dir().join(format!("resec-retry-{}", std::process::id()));
+
+        // Failures are reported without retries.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        server.fail_next(1, 503);
+        let downloader = Downloader::new(&directory).progress(sender);
+        assert!(downloader.download(&paper).await.is_err());
+        assert!(matches!(
+            receiver.try_recv(),
+            Ok(ProgressEvent::Error { .. })
+        ));
+
+        // A server error is retried.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        server.fail_next(1, 503);
+        let download = Downloader::new(&directory)
+            .retries(2)
+            .progress(sender)
+            .download(&paper)
+            .await?
+            .unwrap();
+        assert_eq!(100, download.size);
+
+        let events: Vec<ProgressEvent> = receiver.try_iter().collect();
+        assert!(matches!(
+            &events[0],
+            ProgressEvent::Retry { attempt: 2, .. }
+        ));
+        assert_eq!(
+            ProgressEvent::DownloadStarted {
+                link: paper.link.clone(),
+                size: Some(100),
+            },
+            events[1]
+        );
+        assert_eq!(
+            Some(&ProgressEvent::DownloadCompleted {
+                link: paper.link.clone(),
+                bytes: 100,
+            }),
+            events.last()
+        );
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn skip_unchanged() -> SecResult<()> {
+        let server = MockServer::start();
+        server.set_file("/archive/exampapers/2019/LC003ALP100EV.pdf", vec![3; 50]);
+        let mut paper = material(
+            "Mathematics / Higher Level / Paper 1 (EV)",
+            "https://example.com",
+        );
+        paper.link = server
+            .url()
+            .join("/archive/exampapers/2019/LC003ALP100EV.pdf")
+            .unwrap();
+        let directory =
+            std::env::temp_dir().join(format!("resec-unchanged-{}", std::process::id()));
+        let first = Downloader::new(&directory).download(&paper).await?.unwrap();
+        assert!(first.validators.etag.is_some());
+        let manifest = Manifest::from_downloads(std::slice::from_ref(&first));
+
+        // An unchanged material keeps the earlier download.
+        let (sender, receiver) = std::sync::mpsc::channel();
+        let downloader = Downloader::new(&directory)
+            .previous(&manifest)
+            .progress(sender);
+        assert_eq!(Some(first.clone()), downloader.download(&paper).await?);
+        assert_eq!(
+            vec![ProgressEvent::DownloadUnchanged {
+                link: paper.link.clone(),
+                bytes: 50,
+            }],
+            receiver.try_iter().collect::<Vec<_>>()
+        );
+
+        // A changed material is downloaded again.
+        server.set_file("/archive/exampapers/2019/LC003ALP100EV.pdf", vec![4; 60]);
+        let second = downloader.download(&paper).await?.unwrap();
+        assert_eq!(60, second.size);
+        assert_ne!(first.sha256, second.sha256);
+
+        // So is one changed on disk, without a conditional request.
+        fs::write(&second.path, b"truncated")?;
+        downloader.download(&paper).await?.unwrap();
+        assert!(!server
+            .request_headers()
+            .last()
+            .unwrap()
+            .contains_key("if-none-match"));
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn partial_bodies() -> SecResult<()> {
+        let server = MockServer::start();
+        server.set_file("/archive/exampapers/2019/LC010ALP000EV.pdf", vec![5; 100]);
+        let mut paper = material("French / Higher Level (EV)", "https://example.com");
+        paper.link = server
+            .url()
+            .join("/archive/exampapers/2019/LC010ALP000EV.pdf")
+            .unwrap();
+        let directory = std::env::temp_dir().join(format!("resec-partial-{}", std::process::id()));
+        let downloader = Downloader::new(&directory);
+        let first = downloader.download(&paper).await?.unwrap();
+        let part = partial_path(&first.path);
+
+        // A body without a length is still held to the size limit.
+        server.set_body_mode(BodyMode::Unsized);
+        let limited = Downloader::new(&directory).max_size(50);
+        assert_eq!(None, limited.download(&paper).await?);
+        assert!(!part.exists());
+
+        // A body cut short fails, keeping the earlier download.
+        server.set_file("/archive/exampapers/2019/LC010ALP000EV.pdf", vec![6; 100]);
+        server.set_body_mode(BodyMode::Truncated);
+        assert!(downloader.download(&paper).await.is_err());
+        assert_eq!(vec![5; 100], fs::read(&first.path)?);
+        assert!(!part.exists());
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+}
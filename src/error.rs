@@ -21,4 +21,41 @@ pub enum SecError {
     Value(&'static str),
     #[error("Query returned no material")]
     NoMaterial,
+    #[error("Invalid material link: {0}")]
+    InvalidLink(String),
+    #[error("Query returned the terms and conditions page")]
+    TermsNotAccepted,
+    #[error("Timed out waiting for a response")]
+    Timeout,
+    #[error("Invalid material filter: {0}")]
+    Filter(String),
+    #[error("The {0} feature is not enabled")]
+    FeatureDisabled(&'static str),
+    #[cfg(feature = "sqlite")]
+    #[error("SQLite failure")]
+    Sqlite(#[from] rusqlite::Error),
+    #[cfg(feature = "serde")]
+    #[error("JSON failure")]
+    Json(#[from] serde_json::Error),
+    #[cfg(feature = "pdf")]
+    #[error("PDF failure")]
+    Pdf(#[from] pdf_extract::OutputError),
+    #[cfg(feature = "pdf")]
+    #[error("PDF reader failed on a malformed file: {0}")]
+    MalformedPdf(String),
+    #[cfg(feature = "search")]
+    #[error("Search index failure")]
+    Search(#[from] tantivy::TantivyError),
+    #[cfg(feature = "server")]
+    #[error("HTTP server failure")]
+    Hyper(#[from] hyper::Error),
+    #[cfg(feature = "parquet")]
+    #[error("Parquet failure")]
+    Parquet(#[from] parquet::errors::ParquetError),
+    #[cfg(feature = "search")]
+    #[error("Invalid search query")]
+    SearchQuery(#[from] tantivy::query::QueryParserError),
+    #[cfg(feature = "toml")]
+    #[error("Invalid configuration file")]
+    Toml(#[from] toml::de::Error),
 }
@@ -0,0 +1,576 @@
+//! Flat file exports of material.
+//!
+//! Each material is written as a single row, with columns for its query,
+//! metadata and link, alongside its size and hash once downloaded.
+//! Rows are written as they arrive, so an export never holds the whole
+//! archive in memory.
+//!
+//! # Usage:
+//!
+//! ```
+//! use resec::export::{writer_for, ExportFormat, ExportRow};
+//!
+//! # fn main() -> Result<(), resec::error::SecError> {
+//! # let materials: Vec<resec::material::Material> = Vec::new();
+//! let mut writer = writer_for(ExportFormat::Csv, std::io::stdout())?;
+//! for material in &materials {
+//!     writer.write_row(&ExportRow::from(material))?;
+//! }
+//! writer.finish()?;
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    downloader::Download,
+    error::{SecError, SecResult},
+    manifest::ManifestEntry,
+    material::Material,
+};
+use futures_util::stream::{Stream, StreamExt};
+use std::io::Write;
+use strum::EnumProperty;
+use strum_macros::{Display, EnumIter, EnumString};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Names of the exported columns, in order.
+pub const COLUMNS: [&str; 11] = [
+    "type_id",
+    "exam_id",
+    "year",
+    "subject_id",
+    "subject",
+    "level",
+    "language",
+    "kind",
+    "url",
+    "size",
+    "sha256",
+];
+
+/// The formats material can be exported to.
+#[derive(Display, EnumString, EnumIter, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ExportFormat {
+    /// Comma separated values, with a header row.
+    #[strum(serialize = "csv")]
+    Csv,
+    /// A JSON object per line, requires the ``serde`` feature.
+    #[strum(serialize = "jsonl")]
+    JsonLines,
+    /// Apache Parquet, requires the ``parquet`` feature.
+    #[strum(serialize = "parquet")]
+    Parquet,
+}
+
+/// A single exported material.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExportRow {
+    pub type_id: String,
+    pub exam_id: String,
+    pub year: u32,
+    pub subject_id: u32,
+    /// Name of the subject, if known.
+    pub subject: Option<String>,
+    pub level: String,
+    pub language: String,
+    pub kind: String,
+    pub url: String,
+    /// Size of the material in bytes, if downloaded.
+    pub size: Option<u64>,
+    /// Hex encoded SHA-256 hash of the material, if downloaded.
+    pub sha256: Option<String>,
+}
+
+impl ExportRow {
+    /// Create a row for a material, with its size and hash if known.
+    pub fn new(material: &Material, size: Option<u64>, sha256: Option<String>) -> Self {
+        Self {
+            type_id: material.type_id.clone(),
+            exam_id: material.exam_id.clone(),
+            year: material.year,
+            subject_id: material.subject_id,
+            subject: material
+                .subject()
+                .and_then(|x| x.get_str("label"))
+                .map(String::from),
+            level: material.level.to_string(),
+            language: material.language.to_string(),
+            kind: material.kind.to_string(),
+            url: material.link.to_string(),
+            size,
+            sha256,
+        }
+    }
+
+    /// The value of every column, in order.
+    pub fn values(&self) -> Vec<Option<String>> {
+        vec![
+            Some(self.type_id.clone()),
+            Some(self.exam_id.clone()),
+            Some(self.year.to_string()),
+            Some(self.subject_id.to_string()),
+            self.subject.clone(),
+            Some(self.level.clone()),
+            Some(self.language.clone()),
+            Some(self.kind.clone()),
+            Some(self.url.clone()),
+            self.size.map(|x| x.to_string()),
+            self.sha256.clone(),
+        ]
+    }
+}
+
+impl From<&Material> for ExportRow {
+    fn from(material: &Material) -> Self {
+        Self::new(material, None, None)
+    }
+}
+
+impl From<&ManifestEntry> for ExportRow {
+    fn from(entry: &ManifestEntry) -> Self {
+        Self::new(&entry.material, entry.size, entry.sha256.clone())
+    }
+}
+
+impl From<&Download> for ExportRow {
+    fn from(download: &Download) -> Self {
+        Self::new(
+            &download.material,
+            Some(download.size),
+            Some(download.sha256.clone()),
+        )
+    }
+}
+
+/// A destination for exported rows.
+pub trait RowWriter {
+    /// Write a single row.
+    fn write_row(&mut self, row: &ExportRow) -> SecResult<()>;
+
+    /// Write any buffered rows and finish the file.
+    fn finish(&mut self) -> SecResult<()>;
+}
+
+/// Create a writer for the given format.
+///
+/// Fails if the format needs a feature which isn't enabled.
+pub fn writer_for<W: Write + Send + 'static>(
+    format: ExportFormat,
+    out: W,
+) -> SecResult<Box<dyn RowWriter>> {
+    match format {
+        ExportFormat::Csv => Ok(Box::new(CsvWriter::new(out))),
+        #[cfg(feature = "serde")]
+        ExportFormat::JsonLines => Ok(Box::new(JsonLinesWriter::new(out))),
+        #[cfg(feature = "parquet")]
+        ExportFormat::Parquet => Ok(Box::new(ParquetWriter::new(out)?)),
+        #[allow(unreachable_patterns)]
+        _ => Err(SecError::FeatureDisabled(match format {
+            ExportFormat::Parquet => "parquet",
+            _ => "serde",
+        })),
+    }
+}
+
+/// Write every row to a writer, returning how many were written.
+pub fn export<I>(rows: I, writer: &mut dyn RowWriter) -> SecResult<usize>
+where
+    I: IntoIterator<Item = ExportRow>,
+{
+    let mut count = 0;
+    for row in rows {
+        writer.write_row(&row)?;
+        count += 1;
+    }
+    writer.finish()?;
+    Ok(count)
+}
+
+/// Write a stream of rows to a writer as they arrive, returning how many were written.
+pub async fn export_stream<S>(stream: S, writer: &mut dyn RowWriter) -> SecResult<usize>
+where
+    S: Stream<Item = ExportRow>,
+{
+    let mut stream = Box::pin(stream);
+    let mut count = 0;
+    while let Some(row) = stream.next().await {
+        writer.write_row(&row)?;
+        count += 1;
+    }
+    writer.finish()?;
+    Ok(count)
+}
+
+/// Writes rows as comma separated values.
+#[derive(Debug)]
+pub struct CsvWriter<W: Write> {
+    out: W,
+    header: bool,
+}
+
+impl<W: Write> CsvWriter<W> {
+    /// Create a new CSV writer, the header is written with the first row.
+    pub fn new(out: W) -> Self {
+        Self { out, header: false }
+    }
+
+    /// Write the header if it hasn't been written yet.
+    fn write_header(&mut self) -> SecResult<()> {
+        if !self.header {
+            writeln!(self.out, "{}", COLUMNS.join(","))?;
+            self.header = true;
+        }
+        Ok(())
+    }
+}
+
+/// Quote a CSV field if needed.
+fn csv_field(value: &str) -> String {
+    match value.contains([',', '"', '\n', '\r']) {
+        true => format!("\"{}\"", value.replace('"', "\"\"")),
+        false => value.to_string(),
+    }
+}
+
+impl<W: Write> RowWriter for CsvWriter<W> {
+    fn write_row(&mut self, row: &ExportRow) -> SecResult<()> {
+        self.write_header()?;
+        let fields: Vec<String> = row
+            .values()
+            .iter()
+            .map(|x| csv_field(x.as_deref().unwrap_or_default()))
+            .collect();
+        writeln!(self.out, "{}", fields.join(","))?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> SecResult<()> {
+        self.write_header()?;
+        Ok(self.out.flush()?)
+    }
+}
+
+/// Writes rows as a JSON object per line.
+#[cfg(feature = "serde")]
+#[derive(Debug)]
+pub struct JsonLinesWriter<W: Write> {
+    out: W,
+}
+
+#[cfg(feature = "serde")]
+impl<W: Write> JsonLinesWriter<W> {
+    /// Create a new JSON Lines writer.
+    pub fn new(out: W) -> Self {
+        Self { out }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<W: Write> RowWriter for JsonLinesWriter<W> {
+    fn write_row(&mut self, row: &ExportRow) -> SecResult<()> {
+        serde_json::to_writer(&mut self.out, row)?;
+        writeln!(self.out)?;
+        Ok(())
+    }
+
+    fn finish(&mut self) -> SecResult<()> {
+        Ok(self.out.flush()?)
+    }
+}
+
+#[cfg(feature = "parquet")]
+pub use self::parquet_writer::ParquetWriter;
+
+#[cfg(feature = "parquet")]
+mod parquet_writer {
+    use super::*;
+    use parquet::{
+        column::writer::ColumnWriter,
+        data_type::ByteArray,
+        file::{properties::WriterProperties, writer::SerializedFileWriter},
+        schema::parser::parse_message_type,
+    };
+    use std::sync::Arc;
+
+    /// Schema of the exported file, matching [`COLUMNS`].
+    const SCHEMA: &str = "message material {
+        REQUIRED BINARY type_id (UTF8);
+        REQUIRED BINARY exam_id (UTF8);
+        REQUIRED INT32 year;
+        REQUIRED INT32 subject_id;
+        OPTIONAL BINARY subject (UTF8);
+        REQUIRED BINARY level (UTF8);
+        REQUIRED BINARY language (UTF8);
+        REQUIRED BINARY kind (UTF8);
+        REQUIRED BINARY url (UTF8);
+        OPTIONAL INT64 size;
+        OPTIONAL BINARY sha256 (UTF8);
+    }";
+
+    /// Number of rows buffered before a row group is written.
+    const ROW_GROUP_SIZE: usize = 8192;
+
+    /// Writes rows as an Apache Parquet file, a row group at a time.
+    pub struct ParquetWriter<W: Write + Send> {
+        writer: Option<SerializedFileWriter<W>>,
+        rows: Vec<ExportRow>,
+    }
+
+    impl<W: Write + Send> ParquetWriter<W> {
+        /// Create a new Parquet writer.
+        pub fn new(out: W) -> SecResult<Self> {
+            let schema = Arc::new(parse_message_type(SCHEMA)?);
+            let properties = Arc::new(WriterProperties::builder().build());
+            Ok(Self {
+                writer: Some(SerializedFileWriter::new(out, schema, properties)?),
+                rows: Vec::new(),
+            })
+        }
+
+        /// Write the buffered rows as a row group.
+        fn write_group(&mut self) -> SecResult<()> {
+            let writer = match &mut self.writer {
+                Some(writer) if !self.rows.is_empty() => writer,
+                _ => return Ok(()),
+            };
+
+            let rows = std::mem::take(&mut self.rows);
+            let values: Vec<Vec<Option<String>>> = rows.iter().map(|x| x.values()).collect();
+            let mut group = writer.next_row_group()?;
+            let mut index = 0;
+
+            while let Some(mut column) = group.next_column()? {
+                // Definition levels of optional columns mark which rows have a value.
+                let levels = |present: Vec<bool>| -> Vec<i16> {
+                    present.into_iter().map(i16::from).collect()
+                };
+
+                match column.untyped() {
+                    ColumnWriter::Int32ColumnWriter(writer) => {
+                        let numbers: Vec<i32> = rows
+                            .iter()
+                            .map(|x| match COLUMNS[index] {
+                                "year" => x.year as i32,
+                                _ => x.subject_id as i32,
+                            })
+                            .collect();
+                        writer.write_batch(&numbers, None, None)?;
+                    }
+                    ColumnWriter::Int64ColumnWriter(writer) => {
+                        let sizes: Vec<i64> = rows
+                            .iter()
+                            .filter_map(|x| x.size)
+                            .map(|x| x as i64)
+                            .collect();
+                        let present = levels(rows.iter().map(|x| x.size.is_some()).collect());
+                        writer.write_batch(&sizes, Some(&present), None)?;
+                    }
+                    ColumnWriter::ByteArrayColumnWriter(writer) => {
+                        let cells: Vec<&Option<String>> =
+                            values.iter().map(|x| &x[index]).collect();
+                        let text: Vec<ByteArray> = cells
+                            .iter()
+                            .filter_map(|x| x.as_deref())
+                            .map(ByteArray::from)
+                            .collect();
+
+                        match COLUMNS[index] {
+                            "subject" | "sha256" => {
+                                let present = levels(cells.iter().map(|x| x.is_some()).collect());
+                                writer.write_batch(&text, Some(&present), None)?;
+                            }
+                            _ => {
+                                writer.write_batch(&text, None, None)?;
+                            }
+                        }
+                    }
+                    _ => return Err(SecError::Value("parquet column")),
+                }
+
+                column.close()?;
+                index += 1;
+            }
+
+            group.close()?;
+            Ok(())
+        }
+    }
+
+    impl<W: Write + Send> RowWriter for ParquetWriter<W> {
+        fn write_row(&mut self, row: &ExportRow) -> SecResult<()> {
+            self.rows.push(row.clone());
+            if self.rows.len() >= ROW_GROUP_SIZE {
+                self.write_group()?;
+            }
+            Ok(())
+        }
+
+        fn finish(&mut self) -> SecResult<()> {
+            self.write_group()?;
+            if let Some(writer) = self.writer.take() {
+                writer.close()?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod export_tests {
+    use super::*;
+    use std::{
+        io,
+        sync::{Arc, Mutex},
+    };
+    use url::Url;
+
+    /// A writer which can be read back after being boxed.
+    #[derive(Clone, Default)]
+    struct Shared(Arc<Mutex<Vec<u8>>>);
+
+    impl Write for Shared {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.lock().unwrap().write(buf)
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    impl Shared {
+        fn bytes(&self) -> Vec<u8> {
+            self.0.lock().unwrap().clone()
+        }
+    }
+
+    fn rows() -> Vec<ExportRow> {
+        let material = |subject_id, name: &str, file| {
+            Material::new(
+                "exampapers",
+                2019,
+                "lc",
+                subject_id,
+                name.into(),
+                Url::parse("https://www.examinations.ie/archive/exampapers/2019/")
+                    .unwrap()
+                    .join(file)
+                    .unwrap(),
+            )
+        };
+
+        vec![
+            ExportRow::from(&material(
+                3,
+                "Mathematics / Higher Level / Paper 1 (EV)",
+                "LC003ALP100EV.pdf",
+            )),
+            ExportRow::new(
+                &material(4, "History, Later Modern (EV)", "LC004ALP000EV.pdf"),
+                Some(1024),
+                Some("abcd".into()),
+            ),
+        ]
+    }
+
+    #[test]
+    fn export_csv() -> SecResult<()> {
+        let out = Shared::default();
+        let mut writer = writer_for(ExportFormat::Csv, out.clone())?;
+        assert_eq!(2, export(rows(), writer.as_mut())?);
+
+        let csv = String::from_utf8(out.bytes()).unwrap();
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(COLUMNS.join(","), lines[0]);
+        assert_eq!(
+            "exampapers,lc,2019,3,Mathematics,Higher Level,EV,Paper,\
+             https://www.examinations.ie/archive/exampapers/2019/LC003ALP100EV.pdf,,",
+            lines[1]
+        );
+        assert!(lines[2].starts_with("exampapers,lc,2019,4,History – Later Modern,NoLevel,"));
+        assert!(lines[2].ends_with(",1024,abcd"));
+
+        // An empty export still has a header.
+        let out = Shared::default();
+        export(Vec::new(), &mut CsvWriter::new(out.clone()))?;
+        assert_eq!(format!("{}\n", COLUMNS.join(",")).into_bytes(), out.bytes());
+        Ok(())
+    }
+
+    #[test]
+    fn quote_csv() {
+        assert_eq!("plain", csv_field("plain"));
+        assert_eq!("\"a, \"\"b\"\"\"", csv_field("a, \"b\""));
+    }
+
+    #[tokio::test]
+    async fn export_streamed() -> SecResult<()> {
+        let out = Shared::default();
+        let stream = futures_util::stream::iter(rows());
+        assert_eq!(
+            2,
+            export_stream(stream, &mut CsvWriter::new(out.clone())).await?
+        );
+        assert_eq!(
+            3,
+            out.bytes()
+                .split(|x| *x == b'\n')
+                .filter(|x| !x.is_empty())
+                .count()
+        );
+        Ok(())
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn export_json_lines() -> SecResult<()> {
+        let out = Shared::default();
+        export(
+            rows(),
+            writer_for(ExportFormat::JsonLines, out.clone())?.as_mut(),
+        )?;
+
+        let json = String::from_utf8(out.bytes()).unwrap();
+        let parsed: Vec<ExportRow> = json
+            .lines()
+            .map(serde_json::from_str)
+            .collect::<Result<_, _>>()?;
+        assert_eq!(rows(), parsed);
+        Ok(())
+    }
+
+    #[cfg(feature = "parquet")]
+    #[test]
+    fn export_parquet() -> SecResult<()> {
+        use parquet::file::reader::{FileReader, SerializedFileReader};
+
+        let path =
+            std::env::temp_dir().join(format!("resec-export-{}.parquet", std::process::id()));
+        let file = std::fs::File::create(&path)?;
+        export(rows(), writer_for(ExportFormat::Parquet, file)?.as_mut())?;
+
+        let reader = SerializedFileReader::new(std::fs::File::open(&path)?)?;
+        assert_eq!(2, reader.metadata().file_metadata().num_rows());
+
+        let rows: Vec<_> = reader.get_row_iter(None)?.collect::<Result<_, _>>()?;
+        let second = rows[1].to_string();
+        assert!(second.contains("size: 1024"));
+        assert!(second.contains("subject: \"History – Later Modern\""));
+        assert!(rows[0].to_string().contains("size: null"));
+
+        std::fs::remove_file(path)?;
+        Ok(())
+    }
+
+    #[cfg(not(feature = "parquet"))]
+    #[test]
+    fn parquet_disabled() {
+        assert!(matches!(
+            writer_for(ExportFormat::Parquet, Vec::new()),
+            Err(SecError::FeatureDisabled("parquet"))
+        ));
+    }
+}
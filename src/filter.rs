@@ -0,0 +1,487 @@
+//! A small query language for selecting material.
+//!
+//! A filter is written as space separated ``key:value`` terms, such as
+//! ``exam:lc subject:maths level:higher lang:EV year:2010..2019``. Terms
+//! with different keys must all match, while values given for the same
+//! key, either repeated or separated by commas, match any of them.
+//!
+//! | Key | Values |
+//! |-----|--------|
+//! | ``type`` | ``exampapers``, ``markingschemes`` |
+//! | ``exam`` | ``lc``, ``jc``, ``lb`` or the examination name |
+//! | ``subject`` | Subject ID or name, such as ``3`` or ``maths`` |
+//! | ``level`` | ``higher``, ``ordinary``, ``foundation``, ``common`` |
+//! | ``lang`` | ``EV``, ``IV``, ``english``, ``irish`` |
+//! | ``session`` | ``main``, ``deferred``, ``supplementary`` |
+//! | ``kind`` | ``paper``, ``scheme``, ``audio``, ``attachment`` |
+//! | ``year`` | A year or range, such as ``2019`` or ``2010..2019`` |
+//!
+//! The [`Crawler`](crate::crawler::Crawler) uses a filter to skip branches of
+//! the archive which can't match, and [`MaterialFilter::matches`] can be used
+//! to filter material from any other source.
+
+use crate::{
+    error::{SecError, SecResult},
+    material::{Material, MaterialKind},
+    schema::{
+        metadata::{Examination, Language, Level, Session, Type},
+        subjects::Subject,
+    },
+    years::{Year, YearRange},
+};
+use std::{fmt, str::FromStr};
+use strum::{EnumProperty, IntoEnumIterator};
+
+/// Subject names commonly used in place of the ones on the website.
+const SUBJECT_ALIASES: &[(&str, &str)] = &[("maths", "3"), ("appliedmaths", "20")];
+
+/// A filter selecting material by its metadata.
+///
+/// Every criteria left empty matches all material.
+///
+/// ```
+/// # use resec::filter::MaterialFilter;
+/// let filter: MaterialFilter = "exam:lc subject:maths level:higher year:2015..2019"
+///     .parse()
+///     .unwrap();
+/// assert!(filter.matches_subject(3));
+/// assert!(!filter.matches_year(2020));
+/// assert_eq!("exam:lc subject:3 level:higher year:2015..2019", filter.to_string());
+/// ```
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MaterialFilter {
+    types: Vec<Type>,
+    examinations: Vec<Examination>,
+    subjects: Vec<Subject>,
+    levels: Vec<Level>,
+    languages: Vec<Language>,
+    sessions: Vec<Session>,
+    kinds: Vec<MaterialKind>,
+    years: Option<YearRange>,
+}
+
+impl MaterialFilter {
+    /// Create a new filter matching all material.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Also match the given paper type.
+    pub fn paper_type(mut self, paper_type: Type) -> Self {
+        push_unique(&mut self.types, paper_type);
+        self
+    }
+
+    /// Also match the given examination.
+    pub fn examination(mut self, examination: Examination) -> Self {
+        push_unique(&mut self.examinations, examination);
+        self
+    }
+
+    /// Also match the given subject.
+    pub fn subject(mut self, subject: Subject) -> Self {
+        push_unique(&mut self.subjects, subject);
+        self
+    }
+
+    /// Also match the given level.
+    pub fn level(mut self, level: Level) -> Self {
+        push_unique(&mut self.levels, level);
+        self
+    }
+
+    /// Also match the given language.
+    pub fn language(mut self, language: Language) -> Self {
+        push_unique(&mut self.languages, language);
+        self
+    }
+
+    /// Also match the given session.
+    pub fn session(mut self, session: Session) -> Self {
+        push_unique(&mut self.sessions, session);
+        self
+    }
+
+    /// Also match the given kind of material.
+    pub fn kind(mut self, kind: MaterialKind) -> Self {
+        push_unique(&mut self.kinds, kind);
+        self
+    }
+
+    /// Only match the given range of years.
+    pub fn years(mut self, years: YearRange) -> Self {
+        self.years = Some(years);
+        self
+    }
+
+    /// Add the criteria of another filter.
+    ///
+    /// The year range of the other filter replaces this one, if set.
+    pub fn merge(mut self, other: MaterialFilter) -> Self {
+        for x in other.types {
+            push_unique(&mut self.types, x);
+        }
+        for x in other.examinations {
+            push_unique(&mut self.examinations, x);
+        }
+        for x in other.subjects {
+            push_unique(&mut self.subjects, x);
+        }
+        for x in other.levels {
+            push_unique(&mut self.levels, x);
+        }
+        for x in other.languages {
+            push_unique(&mut self.languages, x);
+        }
+        for x in other.sessions {
+            push_unique(&mut self.sessions, x);
+        }
+        for x in other.kinds {
+            push_unique(&mut self.kinds, x);
+        }
+        self.years = other.years.or(self.years);
+        self
+    }
+
+    /// The paper types matched, empty if all are.
+    pub fn types(&self) -> &[Type] {
+        &self.types
+    }
+
+    /// The examinations matched, empty if all are.
+    pub fn examinations(&self) -> &[Examination] {
+        &self.examinations
+    }
+
+    /// The subjects matched, empty if all are.
+    pub fn subjects(&self) -> &[Subject] {
+        &self.subjects
+    }
+
+    /// The levels matched, empty if all are.
+    pub fn levels(&self) -> &[Level] {
+        &self.levels
+    }
+
+    /// The languages matched, empty if all are.
+    pub fn languages(&self) -> &[Language] {
+        &self.languages
+    }
+
+    /// The sessions matched, empty if all are.
+    pub fn sessions(&self) -> &[Session] {
+        &self.sessions
+    }
+
+    /// The kinds of material matched, empty if all are.
+    pub fn kinds(&self) -> &[MaterialKind] {
+        &self.kinds
+    }
+
+    /// The range of years matched, if limited.
+    pub fn year_range(&self) -> Option<YearRange> {
+        self.years
+    }
+
+    /// Check if the filter matches all material.
+    pub fn is_empty(&self) -> bool {
+        *self == Self::default()
+    }
+
+    /// Check if material of a paper type can match.
+    pub fn matches_type(&self, type_id: &str) -> bool {
+        self.types.is_empty() || self.types.iter().any(|x| x.id() == type_id)
+    }
+
+    /// Check if material of a year can match.
+    pub fn matches_year(&self, year: impl Into<Year>) -> bool {
+        let year = year.into();
+        self.years.is_none_or(|x| x.contains(year))
+    }
+
+    /// Check if material of an examination can match.
+    pub fn matches_examination(&self, exam_id: &str) -> bool {
+        self.examinations.is_empty() || self.examinations.iter().any(|x| x.id() == exam_id)
+    }
+
+    /// Check if material of a subject can match.
+    pub fn matches_subject(&self, subject_id: u32) -> bool {
+        let id = subject_id.to_string();
+        self.subjects.is_empty() || self.subjects.iter().any(|x| x.get_str("id") == Some(&id))
+    }
+
+    /// Check if a material matches every criteria.
+    pub fn matches(&self, material: &Material) -> bool {
+        fn any<T: PartialEq>(values: &[T], value: &T) -> bool {
+            values.is_empty() || values.contains(value)
+        }
+
+        self.matches_type(&material.type_id)
+            && self.matches_year(material.year)
+            && self.matches_examination(&material.exam_id)
+            && self.matches_subject(material.subject_id)
+            && any(&self.levels, &material.level)
+            && any(&self.languages, &material.language)
+            && any(&self.sessions, &material.session)
+            && any(&self.kinds, &material.kind)
+    }
+
+    /// Add a single ``key:value`` term to the filter.
+    fn add_term(mut self, term: &str) -> SecResult<Self> {
+        let (key, values) = term
+            .split_once(':')
+            .ok_or_else(|| SecError::Filter(format!("expected key:value, found {:?}", term)))?;
+
+        if normalise(key) == "year" {
+            self.years = Some(values.parse().map_err(|_| invalid(key, values))?);
+            return Ok(self);
+        }
+
+        for value in values.split(',').filter(|x| !x.is_empty()) {
+            let name = normalise(value);
+            self = match normalise(key).as_str() {
+                "type" => self.paper_type(find_by_id(&name).ok_or_else(|| invalid(key, value))?),
+                "exam" => self.examination(find_by_id(&name).ok_or_else(|| invalid(key, value))?),
+                "subject" => self.subject(find_subject(&name).ok_or_else(|| invalid(key, value))?),
+                "level" => self.level(
+                    find_named(Level::iter(), &name)
+                        .or_else(|| find_named(Level::iter(), &format!("{}level", name)))
+                        .ok_or_else(|| invalid(key, value))?,
+                ),
+                "lang" | "language" => self.language(
+                    find_named(Language::iter(), &name)
+                        .or_else(|| {
+                            Language::iter().find(|x| normalise(&format!("{:?}", x)) == name)
+                        })
+                        .ok_or_else(|| invalid(key, value))?,
+                ),
+                "session" => self.session(
+                    find_named(Session::iter(), &name).ok_or_else(|| invalid(key, value))?,
+                ),
+                "kind" => self.kind(
+                    find_named(MaterialKind::iter(), &name)
+                        .or(match name.as_str() {
+                            "scheme" => Some(MaterialKind::MarkingScheme),
+                            "audio" => Some(MaterialKind::AudioFile),
+                            _ => None,
+                        })
+                        .ok_or_else(|| invalid(key, value))?,
+                ),
+                _ => return Err(SecError::Filter(format!("unknown key {:?}", key))),
+            };
+        }
+        Ok(self)
+    }
+}
+
+/// Parse a filter from space separated ``key:value`` terms.
+impl FromStr for MaterialFilter {
+    type Err = SecError;
+
+    fn from_str(raw: &str) -> SecResult<Self> {
+        raw.split_whitespace()
+            .try_fold(Self::new(), |filter, term| filter.add_term(term))
+    }
+}
+
+/// Write the filter in the query language, using IDs where possible.
+impl fmt::Display for MaterialFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut terms = Vec::new();
+        let mut term = |key: &str, values: Vec<String>| {
+            if !values.is_empty() {
+                terms.push(format!("{}:{}", key, values.join(",")));
+            }
+        };
+
+        term("type", self.types.iter().map(|x| x.id().into()).collect());
+        term(
+            "exam",
+            self.examinations.iter().map(|x| x.id().into()).collect(),
+        );
+        term(
+            "subject",
+            self.subjects
+                .iter()
+                .filter_map(|x| x.get_str("id").map(String::from))
+                .collect(),
+        );
+        term(
+            "level",
+            self.levels
+                .iter()
+                .map(|x| normalise(&x.to_string()).trim_end_matches("level").into())
+                .collect(),
+        );
+        term(
+            "lang",
+            self.languages.iter().map(|x| x.to_string()).collect(),
+        );
+        term(
+            "session",
+            self.sessions
+                .iter()
+                .map(|x| normalise(&x.to_string()))
+                .collect(),
+        );
+        term(
+            "kind",
+            self.kinds
+                .iter()
+                .map(|x| normalise(&x.to_string()))
+                .collect(),
+        );
+        term("year", self.years.iter().map(|x| x.to_string()).collect());
+
+        write!(f, "{}", terms.join(" "))
+    }
+}
+
+/// Push a value unless it is already present.
+fn push_unique<T: PartialEq>(values: &mut Vec<T>, value: T) {
+    if !values.contains(&value) {
+        values.push(value);
+    }
+}
+
+/// Lowercase a name, dropping anything but letters and digits.
+fn normalise(raw: &str) -> String {
+    raw.chars()
+        .filter(|x| x.is_alphanumeric())
+        .flat_map(char::to_lowercase)
+        .collect()
+}
+
+/// The error for a value which couldn't be understood.
+fn invalid(key: &str, value: &str) -> SecError {
+    SecError::Filter(format!("unknown {} {:?}", key, value))
+}
+
+/// Find a variant by its displayed name.
+fn find_named<T: fmt::Display>(mut values: impl Iterator<Item = T>, name: &str) -> Option<T> {
+    values.find(|x| normalise(&x.to_string()) == name)
+}
+
+/// Find a variant by its ID or label.
+fn find_by_id<T: EnumProperty + IntoEnumIterator>(name: &str) -> Option<T> {
+    T::iter().find(|x| {
+        ["id", "label"]
+            .iter()
+            .filter_map(|prop| x.get_str(prop))
+            .any(|x| normalise(x) == name)
+    })
+}
+
+/// Find a subject by its ID, name or a common alias.
+fn find_subject(name: &str) -> Option<Subject> {
+    let name = SUBJECT_ALIASES
+        .iter()
+        .find(|(alias, _)| *alias == name)
+        .map_or(name, |(_, id)| id);
+    find_by_id(name)
+}
+
+#[cfg(test)]
+mod filter_tests {
+    use super::*;
+    use url::Url;
+
+    fn material(type_id: &str, year: u32, subject_id: u32, name: &str) -> Material {
+        let link = Url::parse("https://www.examinations.ie/archive/paper.pdf").unwrap();
+        Material::new(type_id, year, "lc", subject_id, name.into(), link)
+    }
+
+    #[test]
+    fn parsing() -> SecResult<()> {
+        let filter: MaterialFilter =
+            "exam:lc subject:maths level:higher lang:EV year:2010..2019 type:markingschemes kind:paper"
+                .parse()?;
+        assert_eq!(
+            MaterialFilter::new()
+                .examination(Examination::LeavingCertificate)
+                .subject(Subject::Mathematics)
+                .level(Level::HigherLevel)
+                .language(Language::English)
+                .years(YearRange::from(2010..=2019))
+                .paper_type(Type::MarkingScheme)
+                .kind(MaterialKind::Paper),
+            filter
+        );
+
+        // Names, repeated keys and lists are accepted.
+        let filter: MaterialFilter =
+            "exam:leaving_certificate subject:Applied-Maths,chemistry subject:3 lang:irish kind:scheme,audio"
+                .parse()?;
+        assert_eq!(
+            vec![
+                Subject::AppliedMathematics,
+                Subject::Chemistry,
+                Subject::Mathematics
+            ],
+            filter.subjects
+        );
+        assert_eq!(vec![Language::Irish], filter.languages);
+        assert_eq!(
+            vec![MaterialKind::MarkingScheme, MaterialKind::AudioFile],
+            filter.kinds
+        );
+        assert_eq!(
+            "exam:lc subject:20,22,3 lang:IV kind:markingscheme,audiofile",
+            filter.to_string()
+        );
+        assert_eq!(filter, filter.to_string().parse()?);
+
+        for invalid in &[
+            "exam",
+            "colour:red",
+            "level:highest",
+            "year:soon",
+            "subject:99999",
+        ] {
+            assert!(matches!(
+                invalid.parse::<MaterialFilter>(),
+                Err(SecError::Filter(_))
+            ));
+        }
+        assert!("".parse::<MaterialFilter>()?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn matching() -> SecResult<()> {
+        let filter: MaterialFilter = "subject:3 level:higher lang:EV year:2015..2019".parse()?;
+        let paper = material(
+            "exampapers",
+            2019,
+            3,
+            "Mathematics / Higher Level / Paper 1 (EV)",
+        );
+        assert!(filter.matches(&paper));
+        assert!(!filter.matches(&material(
+            "exampapers",
+            2019,
+            3,
+            "Mathematics / Ordinary Level (EV)"
+        )));
+        assert!(!filter.matches(&material(
+            "exampapers",
+            2014,
+            3,
+            "Mathematics / Higher Level (EV)"
+        )));
+        assert!(!filter.matches(&material(
+            "exampapers",
+            2019,
+            22,
+            "Chemistry / Higher Level (EV)"
+        )));
+
+        // Planning only looks at the stages of the archive.
+        assert!(filter.matches_type("markingschemes"));
+        assert!(filter.matches_examination("jc"));
+        assert!(!filter.matches_year(2020));
+        assert!(!filter.matches_subject(22));
+        assert!(MaterialFilter::new().matches(&paper));
+        Ok(())
+    }
+}
@@ -3,11 +3,54 @@
 //! A library dedicated to reverse engineering techniques for the [SEC](https://examinations.ie).
 //!
 //! **Note**: This library can stop working at any time if a website change occurs!
+//!
+//! ## Features
+//!
+//! - ``serde``: ``Serialize`` and ``Deserialize`` for all public types, keyed on the SEC IDs.
+//! - ``sqlite``: A SQLite catalogue of crawled material.
+//! - ``parquet``: Export of material to Apache Parquet files.
+//! - ``socks``: SOCKS5 proxies for outbound requests.
+//! - ``toml``: Loading a [`Config`](config::Config) from a TOML file, implies ``serde``.
+//! - ``cli``: The ``resec`` command line interface, implies ``toml``.
+//! - ``server``: The ``resec-server`` REST API, implies ``toml``.
+//! - ``pdf``: Text extraction from downloaded PDFs, and segmentation into questions.
+//! - ``search``: A full-text search index over downloaded PDFs, implies ``pdf``.
+//! - ``test-util``: A local mock of the SEC website for testing without a network connection.
 
+pub mod cache;
+#[cfg(feature = "sqlite")]
+pub mod catalog;
+pub mod checkpoint;
+pub mod client;
+pub mod config;
 mod consts;
+pub mod crawler;
+pub mod diff;
+pub mod downloader;
 pub mod error;
+pub mod export;
+pub mod filter;
+pub mod limiter;
+pub mod manifest;
+pub mod material;
 pub mod parser;
+pub mod planner;
+pub mod probe;
+pub mod progress;
+pub mod retry;
+#[cfg(feature = "search")]
+pub mod search;
+#[cfg(feature = "pdf")]
+pub mod segment;
+#[cfg(feature = "server")]
+pub mod server;
 pub mod stages;
+pub mod subject_papers;
+#[cfg(any(test, feature = "test-util"))]
+pub mod test_util;
+#[cfg(feature = "pdf")]
+pub mod text;
+pub mod years;
 pub mod schema {
     //! The reverse engineered schema's for ``asec``.
     //!
@@ -31,13 +74,28 @@ pub mod prelude {
 
     // SEC Prelude
     pub use crate::{
+        checkpoint::Checkpoint,
+        client::SecClient,
+        config::Config,
         consts::{EXAM_PAPER_YEARS, MARKING_SCHEME_YEARS},
+        crawler::Crawler,
+        diff::{diff, DiffReport},
+        downloader::{Download, Downloader},
         error::SecError,
+        filter::MaterialFilter,
+        manifest::Manifest,
+        material::{FileFormat, Material, MaterialKind, Papers, ParseWarning},
         parser::*,
+        planner::{CrawlPlan, Planner},
+        probe::{Probe, Validators},
+        progress::{Progress, ProgressEvent},
+        retry::RetryPolicy,
         schema::{
-            metadata::{Examination, Language, Level, Type},
+            metadata::{Examination, Language, Level, Session, Type},
             subjects::Subject,
         },
-        stages::StageBuilder,
+        stages::{StageBuilder, Terms},
+        subject_papers::{papers_for_subject, SubjectPapers},
+        years::{available_years, fallback_years, year_range, Year, YearRange},
     };
 }
@@ -0,0 +1,157 @@
+//! Rate and concurrency limiters for requests to the SEC website.
+//!
+//! Requests are spaced out evenly, so a burst of queries from many
+//! callers sharing a client doesn't overload the website, and the
+//! number of requests in flight at once can be capped per host.
+
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Limits requests to a number per period.
+#[derive(Debug)]
+pub struct RateLimiter {
+    interval: Duration,
+    next: Mutex<Instant>,
+}
+
+impl RateLimiter {
+    /// Create a limiter allowing the given number of requests per period.
+    pub fn new(requests: u32, period: Duration) -> Self {
+        Self {
+            interval: period / requests.max(1),
+            next: Mutex::new(Instant::now()),
+        }
+    }
+
+    /// Create a limiter allowing the given number of requests per second.
+    pub fn per_second(requests: u32) -> Self {
+        Self::new(requests, Duration::from_secs(1))
+    }
+
+    /// Reserve the next slot, returning how long to wait for it.
+    fn reserve(&self) -> Duration {
+        let mut next = self.next.lock().unwrap();
+        let now = Instant::now();
+        let slot = (*next).max(now);
+        *next = slot + self.interval;
+        slot - now
+    }
+
+    /// Wait until the next request may be sent.
+    pub async fn wait(&self) {
+        let delay = self.reserve();
+        if delay > Duration::from_millis(0) {
+            tokio::time::delay_for(delay).await;
+        }
+    }
+}
+
+/// Limits the number of requests in flight, globally and per host.
+#[derive(Debug)]
+pub struct InFlightLimit {
+    global: Arc<Semaphore>,
+    per_host: usize,
+    hosts: Mutex<HashMap<String, Arc<Semaphore>>>,
+}
+
+/// Permission to send a request, released when dropped.
+#[derive(Debug)]
+pub struct InFlightPermit {
+    _host: OwnedSemaphorePermit,
+    _global: OwnedSemaphorePermit,
+}
+
+impl InFlightLimit {
+    /// Create a limit allowing the given number of requests in flight.
+    ///
+    /// The same number is allowed per host unless changed with
+    /// [`per_host`](Self::per_host).
+    pub fn new(requests: usize) -> Self {
+        let requests = requests.max(1);
+        Self {
+            global: Arc::new(Semaphore::new(requests)),
+            per_host: requests,
+            hosts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Set the number of requests allowed in flight to a single host.
+    pub fn per_host(mut self, requests: usize) -> Self {
+        self.per_host = requests.max(1);
+        self
+    }
+
+    /// Wait until a request may be sent to the given host.
+    pub async fn acquire(&self, host: &str) -> InFlightPermit {
+        let semaphore = self
+            .hosts
+            .lock()
+            .unwrap()
+            .entry(host.to_string())
+            .or_insert_with(|| Arc::new(Semaphore::new(self.per_host)))
+            .clone();
+
+        // Wait for the host first, so a busy host doesn't hold up the others.
+        let host = semaphore.acquire_owned().await;
+        let global = self.global.clone().acquire_owned().await;
+        InFlightPermit {
+            _host: host,
+            _global: global,
+        }
+    }
+}
+
+#[cfg(test)]
+mod limiter_tests {
+    use super::*;
+
+    #[test]
+    fn spacing() {
+        let limiter = RateLimiter::new(4, Duration::from_secs(1));
+        assert_eq!(Duration::from_millis(0), limiter.reserve());
+
+        // Each following slot is a quarter of a second later.
+        let second = limiter.reserve();
+        let third = limiter.reserve();
+        assert!(second > Duration::from_millis(200) && second <= Duration::from_millis(250));
+        assert!(third > Duration::from_millis(450) && third <= Duration::from_millis(500));
+    }
+
+    #[tokio::test]
+    async fn waits() {
+        let limiter = RateLimiter::per_second(20);
+        let start = Instant::now();
+        for _ in 0..3 {
+            limiter.wait().await;
+        }
+        assert!(start.elapsed() >= Duration::from_millis(100));
+    }
+
+    #[tokio::test]
+    async fn in_flight() {
+        let limit = InFlightLimit::new(2).per_host(1);
+        let short = Duration::from_millis(50);
+
+        // A second request to the same host waits for the first.
+        let first = limit.acquire("example.com").await;
+        assert!(tokio::time::timeout(short, limit.acquire("example.com"))
+            .await
+            .is_err());
+
+        // Other hosts are limited globally.
+        let second = limit.acquire("example.org").await;
+        assert!(tokio::time::timeout(short, limit.acquire("example.net"))
+            .await
+            .is_err());
+
+        drop(first);
+        drop(second);
+        assert!(tokio::time::timeout(short, limit.acquire("example.com"))
+            .await
+            .is_ok());
+    }
+}
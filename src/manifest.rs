@@ -0,0 +1,91 @@
+//! Snapshots of the material offered or downloaded at a point in time.
+//!
+//! A manifest can be built from a crawl or from the downloads of a mirror,
+//! and compared with an older one using [`diff`](crate::diff).
+
+use crate::{downloader::Download, material::Material, probe::Validators};
+use chrono::{DateTime, Utc};
+
+#[cfg(feature = "serde")]
+use crate::error::SecResult;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+#[cfg(feature = "serde")]
+use std::{fs, path::Path};
+
+/// A single material in a manifest.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ManifestEntry {
+    /// The material described by the entry.
+    pub material: Material,
+    /// Size of the material in bytes, if downloaded.
+    pub size: Option<u64>,
+    /// Hex encoded SHA-256 hash of the material, if downloaded.
+    pub sha256: Option<String>,
+    /// The validators of the downloaded version, used to skip unchanged material.
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub validators: Validators,
+}
+
+/// A snapshot of material.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Manifest {
+    /// When the snapshot was taken.
+    pub created: DateTime<Utc>,
+    /// Every material in the snapshot.
+    pub entries: Vec<ManifestEntry>,
+}
+
+impl Manifest {
+    /// Create a manifest from crawled material, without hashes.
+    pub fn from_materials(materials: &[Material]) -> Self {
+        Self {
+            created: Utc::now(),
+            entries: materials
+                .iter()
+                .map(|x| ManifestEntry {
+                    material: x.clone(),
+                    size: None,
+                    sha256: None,
+                    validators: Validators::default(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Create a manifest from downloaded material, including hashes.
+    pub fn from_downloads(downloads: &[Download]) -> Self {
+        Self {
+            created: Utc::now(),
+            entries: downloads
+                .iter()
+                .map(|x| ManifestEntry {
+                    material: x.material.clone(),
+                    size: Some(x.size),
+                    sha256: Some(x.sha256.clone()),
+                    validators: x.validators.clone(),
+                })
+                .collect(),
+        }
+    }
+
+    /// Find the entry for a material link.
+    pub fn get(&self, link: &url::Url) -> Option<&ManifestEntry> {
+        self.entries.iter().find(|x| &x.material.link == link)
+    }
+
+    /// Load a manifest from a JSON file.
+    #[cfg(feature = "serde")]
+    pub fn load(path: impl AsRef<Path>) -> SecResult<Self> {
+        Ok(serde_json::from_str(&fs::read_to_string(path)?)?)
+    }
+
+    /// Save the manifest as a JSON file.
+    #[cfg(feature = "serde")]
+    pub fn save(&self, path: impl AsRef<Path>) -> SecResult<()> {
+        fs::write(path, serde_json::to_string_pretty(self)?)?;
+        Ok(())
+    }
+}
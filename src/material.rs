@@ -0,0 +1,317 @@
+//! The examination material model.
+//!
+//! Each material is a single document listed on the final stage
+//! of the archive, alongside the query that produced it.
+
+use crate::{
+    checkpoint::CrawlNode,
+    client::SecClient,
+    error::SecResult,
+    schema::{
+        metadata::{Examination, Language, Level, Session, Type},
+        subjects::Subject,
+    },
+};
+use std::iter::FromIterator;
+use strum::{EnumProperty, IntoEnumIterator};
+use strum_macros::{Display, EnumIter, EnumString};
+use url::Url;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Separator used to store the extra columns of a material as one string.
+#[cfg(any(feature = "sqlite", feature = "search"))]
+pub(crate) const EXTRA_SEPARATOR: char = '\u{1f}';
+
+/// The different kinds of material offered on the SEC website.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Display, EnumString, EnumIter, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MaterialKind {
+    /// An examination paper.
+    Paper,
+    /// A marking scheme for an examination paper.
+    MarkingScheme,
+    /// An aural recording, such as a language listening test.
+    AudioFile,
+    /// Any other file shipped with a paper, such as a ZIP of source files.
+    Attachment,
+}
+
+/// The file formats offered on the SEC website.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Display, EnumString, EnumIter, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum FileFormat {
+    Pdf,
+    Mp3,
+    Wav,
+    Zip,
+    Word,
+    Unknown,
+}
+
+impl FileFormat {
+    /// Guess the format from the extension of a link.
+    pub fn from_link(link: &Url) -> Self {
+        let extension = match link.path().rsplit('/').next().and_then(|x| x.rsplit_once('.')) {
+            Some((_, extension)) => extension.to_lowercase(),
+            None => return FileFormat::Unknown,
+        };
+
+        match extension.as_str() {
+            "pdf" => FileFormat::Pdf,
+            "mp3" => FileFormat::Mp3,
+            "wav" => FileFormat::Wav,
+            "zip" => FileFormat::Zip,
+            "doc" | "docx" => FileFormat::Word,
+            _ => FileFormat::Unknown,
+        }
+    }
+
+    /// Guess the format from a ``Content-Type`` header value.
+    pub fn from_content_type(content_type: &str) -> Self {
+        let mime = content_type.split(';').next().unwrap_or("").trim();
+        match mime.to_lowercase().as_str() {
+            "application/pdf" => FileFormat::Pdf,
+            "audio/mpeg" | "audio/mp3" => FileFormat::Mp3,
+            "audio/wav" | "audio/x-wav" | "audio/wave" => FileFormat::Wav,
+            "application/zip" | "application/x-zip-compressed" => FileFormat::Zip,
+            "application/msword"
+            | "application/vnd.openxmlformats-officedocument.wordprocessingml.document" => {
+                FileFormat::Word
+            }
+            _ => FileFormat::Unknown,
+        }
+    }
+
+    /// The ``Content-Type`` header value for the format.
+    pub fn content_type(self) -> &'static str {
+        match self {
+            FileFormat::Pdf => "application/pdf",
+            FileFormat::Mp3 => "audio/mpeg",
+            FileFormat::Wav => "audio/wav",
+            FileFormat::Zip => "application/zip",
+            FileFormat::Word => "application/msword",
+            FileFormat::Unknown => "application/octet-stream",
+        }
+    }
+
+    /// Check if the format is an audio recording.
+    pub fn is_audio(self) -> bool {
+        matches!(self, FileFormat::Mp3 | FileFormat::Wav)
+    }
+}
+
+impl MaterialKind {
+    /// Classify a material from its paper type, name and file format.
+    pub fn classify(type_id: &str, name: &str, format: FileFormat) -> Self {
+        let name = name.to_lowercase();
+        if format.is_audio() || (format == FileFormat::Unknown && name.contains("aural")) {
+            MaterialKind::AudioFile
+        } else if format != FileFormat::Pdf && format != FileFormat::Unknown {
+            MaterialKind::Attachment
+        } else if type_id == "markingschemes" {
+            MaterialKind::MarkingScheme
+        } else {
+            MaterialKind::Paper
+        }
+    }
+}
+
+/// A document offered on the SEC website.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    /// Paper type ID used in the query.
+    pub type_id: String,
+    /// Year used in the query.
+    pub year: u32,
+    /// Examination ID used in the query.
+    pub exam_id: String,
+    /// Subject ID used in the query.
+    pub subject_id: u32,
+    /// Raw name of the material as shown on the website.
+    pub name: String,
+    /// Download link for the material.
+    pub link: Url,
+    /// Level parsed from the name.
+    pub level: Level,
+    /// Language parsed from the name.
+    pub language: Language,
+    /// Session parsed from the name.
+    pub session: Session,
+    /// Kind of material, classified from the name and format.
+    pub kind: MaterialKind,
+    /// File format guessed from the link.
+    pub format: FileFormat,
+    /// Any unknown extra columns listed alongside the material.
+    pub extra: Vec<String>,
+}
+
+impl Material {
+    /// Create a new material, parsing the metadata from its name.
+    pub fn new(
+        type_id: &str,
+        year: u32,
+        exam_id: &str,
+        subject_id: u32,
+        name: String,
+        link: Url,
+    ) -> Self {
+        let format = FileFormat::from_link(&link);
+        Self {
+            kind: MaterialKind::classify(type_id, &name, format),
+            format,
+            type_id: type_id.into(),
+            year,
+            exam_id: exam_id.into(),
+            subject_id,
+            level: Level::from(name.clone()),
+            language: Language::from(name.clone()),
+            session: Session::from(name.clone()),
+            name,
+            link,
+            extra: Vec::new(),
+        }
+    }
+
+    /// Refine the file format using a ``HEAD`` request.
+    ///
+    /// This is useful for links without an extension, the kind
+    /// of material is reclassified using the new format.
+    pub async fn detect_format(&mut self, client: &SecClient) -> SecResult<FileFormat> {
+        // Only replace the guess if the server gave a known format.
+        let format = client.probe(self).await?.format;
+        if format != FileFormat::Unknown {
+            self.format = format;
+            self.kind = MaterialKind::classify(&self.type_id, &self.name, format);
+        }
+
+        Ok(self.format)
+    }
+
+    /// The paper type of the material, if known.
+    pub fn paper_type(&self) -> Option<Type> {
+        Type::iter().find(|x| x.get_str("id") == Some(self.type_id.as_str()))
+    }
+
+    /// The examination of the material, if known.
+    pub fn examination(&self) -> Option<Examination> {
+        Examination::iter().find(|x| x.get_str("id") == Some(self.exam_id.as_str()))
+    }
+
+    /// The subject of the material, if known.
+    pub fn subject(&self) -> Option<Subject> {
+        let id = self.subject_id.to_string();
+        Subject::iter().find(|x| x.get_str("id") == Some(id.as_str()))
+    }
+
+    /// The extra columns joined into one string, for storage.
+    #[cfg(any(feature = "sqlite", feature = "search"))]
+    pub(crate) fn joined_extra(&self) -> String {
+        self.extra.join(&EXTRA_SEPARATOR.to_string())
+    }
+
+    /// Split extra columns stored with [`joined_extra`](Self::joined_extra).
+    #[cfg(any(feature = "sqlite", feature = "search"))]
+    pub(crate) fn split_extra(joined: &str) -> Vec<String> {
+        match joined.is_empty() {
+            true => Vec::new(),
+            false => joined.split(EXTRA_SEPARATOR).map(String::from).collect(),
+        }
+    }
+}
+
+/// A row of the material table which couldn't be parsed.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct ParseWarning {
+    /// The query whose material table contained the row.
+    pub node: CrawlNode,
+    /// Index of the row in the material table.
+    pub row: usize,
+    /// Raw text of each cell in the row.
+    pub cells: Vec<String>,
+    /// Why the row was skipped.
+    pub reason: String,
+}
+
+/// The material parsed from a query, alongside any rows that were skipped.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Papers {
+    pub materials: Vec<Material>,
+    pub warnings: Vec<ParseWarning>,
+}
+
+impl Extend<Papers> for Papers {
+    fn extend<T: IntoIterator<Item = Papers>>(&mut self, iter: T) {
+        for papers in iter {
+            self.materials.extend(papers.materials);
+            self.warnings.extend(papers.warnings);
+        }
+    }
+}
+
+impl FromIterator<Papers> for Papers {
+    fn from_iter<T: IntoIterator<Item = Papers>>(iter: T) -> Self {
+        let mut papers = Papers::default();
+        papers.extend(iter);
+        papers
+    }
+}
+
+#[cfg(test)]
+mod material_tests {
+    use super::*;
+
+    #[test]
+    fn new_material() {
+        let material = Material::new(
+            "exampapers",
+            2019,
+            "lc",
+            3,
+            "Mathematics / Higher Level / Paper 1 (IV)".into(),
+            Url::parse("https://www.examinations.ie/archive/exampapers/2019/LC003ALP100IV.pdf")
+                .unwrap(),
+        );
+
+        assert_eq!(Level::HigherLevel, material.level);
+        assert_eq!(Language::Irish, material.language);
+        assert_eq!(Session::Main, material.session);
+        assert_eq!(MaterialKind::Paper, material.kind);
+        assert_eq!(FileFormat::Pdf, material.format);
+        assert_eq!(Some(Type::ExamPaper), material.paper_type());
+        assert_eq!(Some(Examination::LeavingCertificate), material.examination());
+        assert_eq!(Some(Subject::Mathematics), material.subject());
+    }
+
+    #[test]
+    fn classify_material() {
+        let link = |x| Url::parse(x).unwrap();
+        assert_eq!(
+            FileFormat::Mp3,
+            FileFormat::from_link(&link("https://a.ie/b/LC010ALP000EV.mp3?x=1"))
+        );
+        assert_eq!(FileFormat::Zip, FileFormat::from_link(&link("https://a.ie/b/files.ZIP")));
+        assert_eq!(FileFormat::Unknown, FileFormat::from_link(&link("https://a.ie/b/download")));
+        assert_eq!(
+            FileFormat::Pdf,
+            FileFormat::from_content_type("application/pdf; charset=binary")
+        );
+
+        assert_eq!(
+            MaterialKind::AudioFile,
+            MaterialKind::classify("exampapers", "French / Aural", FileFormat::Mp3)
+        );
+        assert_eq!(
+            MaterialKind::Attachment,
+            MaterialKind::classify("exampapers", "Computer Science", FileFormat::Zip)
+        );
+        assert_eq!(
+            MaterialKind::MarkingScheme,
+            MaterialKind::classify("markingschemes", "Biology", FileFormat::Pdf)
+        );
+    }
+}
@@ -1,160 +1,58 @@
 //! A collection of parser functions to allow scraping of
 //! data from the website.
+//!
+//! Each parser is offered as a free function using a default
+//! [`SecClient`], and as a method for querying with a given client.
 
 use crate::{
-    consts::EXAM_URL,
+    checkpoint::CrawlNode,
+    client::SecClient,
     error::{SecError, SecResult},
-    stages::StageBuilder,
+    material::{Material, Papers, ParseWarning},
+    schema::metadata::{Examination, Type},
+    stages::{StageBuilder, Terms},
 };
 use select::{
     document::Document,
     node::Node,
-    predicate::{Attr, Class, Name},
+    predicate::{Attr, Class, Name, Predicate},
 };
 use std::collections::HashMap;
+use tracing::{debug, instrument, warn};
+use url::Url;
+
+/// Scrape the terms and conditions from the stage one HTML.
+pub async fn parse_terms() -> SecResult<Terms> {
+    SecClient::new().parse_terms().await
+}
 
 /// Scrape paper types from generated HTML.
 pub async fn parse_types() -> SecResult<HashMap<String, String>> {
-    // Fetch the stage two HTML.
-    let html = StageBuilder::new().agree_flag(true).query().await?;
-
-    // Parse the HTML into objects.
-    let document = Document::from(html.as_str());
-
-    // Find the table containing the paper types.
-    let items: Vec<Node> = document
-        .find(Attr("name", "MaterialArchive__noTable__sbv__ViewType"))
-        .collect();
-
-    // Grab the options.
-    match items.get(0) {
-        Some(i) => {
-            // Create an empty hashmap.
-            let mut map = HashMap::new();
-
-            // Loop through the option fields.
-            for item in i.find(Name("option")) {
-                // Check if the value attribute is empty.
-                let value = match item.attr("value") {
-                    Some(x) => {
-                        if !x.is_empty() {
-                            x
-                        } else {
-                            continue;
-                        }
-                    }
-                    None => continue,
-                };
-
-                // Finally, merge the result.
-                map.insert(value.into(), item.text());
-            }
+    SecClient::new().parse_types().await
+}
 
-            // Return the map.
-            Ok(map)
-        }
-        None => Err(SecError::Value("could not get paper type field")),
-    }
+/// Scrape paper types from generated HTML, in the order offered by the website.
+///
+/// Types not known to the library are returned as [`Type::Unknown`].
+pub async fn parse_types_typed() -> SecResult<Vec<Type>> {
+    SecClient::new().parse_types_typed().await
 }
 
 /// Scrape paper years from generated HTML.
 pub async fn parse_years(type_id: &str) -> SecResult<Vec<u32>> {
-    // Fetch the stage three HTML.
-    let html = StageBuilder::new()
-        .agree_flag(true)
-        .paper_type(type_id)
-        .query()
-        .await?;
-
-    // Parse the HTML into objects.
-    let document = Document::from(html.as_str());
-
-    // Find the table containing the paper types.
-    let items: Vec<Node> = document
-        .find(Attr("name", "MaterialArchive__noTable__sbv__YearSelect"))
-        .collect();
-
-    // Grab the options.
-    match items.get(0) {
-        Some(i) => {
-            // Create an empty vec.
-            let mut map = Vec::new();
-
-            // Loop through the option fields.
-            for item in i.find(Name("option")) {
-                // Check if the value attribute is empty.
-                let value: u32 = match item.attr("value") {
-                    Some(x) => {
-                        if !x.is_empty() {
-                            x.parse()?
-                        } else {
-                            continue;
-                        }
-                    }
-                    None => continue,
-                };
-
-                // Finally, merge the result.
-                map.push(value);
-            }
-
-            // Return the map.
-            Ok(map)
-        }
-        None => Err(SecError::Value("could not get paper year field")),
-    }
+    SecClient::new().parse_years(type_id).await
 }
 
 /// Scrape examinations from generated HTML.
 pub async fn parse_exams(type_id: &str, year: u32) -> SecResult<HashMap<String, String>> {
-    // Fetch the stage two HTML.
-    let html = StageBuilder::new()
-        .agree_flag(true)
-        .paper_type(type_id)
-        .year(year)
-        .query()
-        .await?;
-
-    // Parse the HTML into objects.
-    let document = Document::from(html.as_str());
-
-    // Find the table containing the paper types.
-    let items: Vec<Node> = document
-        .find(Attr(
-            "name",
-            "MaterialArchive__noTable__sbv__ExaminationSelect",
-        ))
-        .collect();
-
-    // Grab the options.
-    match items.get(0) {
-        Some(i) => {
-            // Create an empty hashmap.
-            let mut map = HashMap::new();
-
-            // Loop through the option fields.
-            for item in i.find(Name("option")) {
-                // Check if the value attribute is empty.
-                let value = match item.attr("value") {
-                    Some(x) => {
-                        if !x.is_empty() {
-                            x
-                        } else {
-                            continue;
-                        }
-                    }
-                    None => continue,
-                };
-
-                // Finally, merge the result.
-                map.insert(value.into(), item.text());
-            }
+    SecClient::new().parse_exams(type_id, year).await
+}
 
-            // Return the map.
-            Ok(map)
-        }
-        None => Err(SecError::Value("could not get exam field")),
-    }
+/// Scrape examinations from generated HTML, in the order offered by the website.
+///
+/// Examinations not known to the library are returned as [`Examination::Unknown`].
+pub async fn parse_exams_typed(type_id: &str, year: u32) -> SecResult<Vec<Examination>> {
+    SecClient::new().parse_exams_typed(type_id, year).await
 }
 
 /// Scrape exam subjects from generated HTML.
@@ -163,103 +61,348 @@ pub async fn parse_subjects(
     year: u32,
     exam_id: &str,
 ) -> SecResult<HashMap<u32, String>> {
-    // Fetch the stage two HTML.
-    let html = StageBuilder::new()
-        .agree_flag(true)
-        .paper_type(type_id)
-        .year(year)
-        .examination(exam_id)
-        .query()
-        .await?;
-
-    // Parse the HTML into objects.
-    let document = Document::from(html.as_str());
-
-    // Find the table containing the paper types.
-    let items: Vec<Node> = document
-        .find(Attr("name", "MaterialArchive__noTable__sbv__SubjectSelect"))
-        .collect();
+    SecClient::new()
+        .parse_subjects(type_id, year, exam_id)
+        .await
+}
 
-    // Grab the options.
-    match items.get(0) {
-        Some(i) => {
-            // Create an empty hashmap.
-            let mut map = HashMap::new();
-
-            // Loop through the option fields.
-            for item in i.find(Name("option")) {
-                // Check if the value attribute is empty.
-                let value = match item.attr("value") {
-                    Some(x) => {
-                        if !x.is_empty() {
-                            x.parse()?
-                        } else {
-                            continue;
-                        }
-                    }
-                    None => continue,
-                };
+/// Scrape exam papers from the generated HTML.
+pub async fn parse_papers(
+    type_id: &str,
+    year: u32,
+    exam_id: &str,
+    subject: u32,
+) -> SecResult<Papers> {
+    SecClient::new()
+        .parse_papers(type_id, year, exam_id, subject)
+        .await
+}
 
-                // Finally, merge the result.
-                map.insert(value, item.text());
-            }
+impl SecClient {
+    /// Scrape the terms and conditions from the stage one HTML.
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_terms(&self) -> SecResult<Terms> {
+        // Fetch the stage one HTML.
+        let html = StageBuilder::new()
+            .agree_flag(false)
+            .query_with(self)
+            .await?;
+
+        // Parse the HTML into objects.
+        let document = Document::from(html.as_str());
+
+        // Extract the terms.
+        Terms::from_document(&document).ok_or(SecError::Value("could not get terms field"))
+    }
+
+    /// Scrape paper types from generated HTML.
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_types(&self) -> SecResult<HashMap<String, String>> {
+        Ok(self.type_options().await?.into_iter().collect())
+    }
+
+    /// Scrape paper types from generated HTML, in the order offered by the website.
+    ///
+    /// Types not known to the library are returned as [`Type::Unknown`].
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_types_typed(&self) -> SecResult<Vec<Type>> {
+        Ok(self
+            .type_options()
+            .await?
+            .into_iter()
+            .map(|(id, label)| Type::from_id(&id, &label))
+            .collect())
+    }
+
+    /// Scrape the paper type options from the stage two HTML.
+    async fn type_options(&self) -> SecResult<Vec<(String, String)>> {
+        // Fetch the stage two HTML.
+        let html = StageBuilder::new()
+            .agree_flag(true)
+            .query_with(self)
+            .await?;
+
+        // Parse the HTML into objects.
+        let document = Document::from(html.as_str());
+
+        // Grab the options.
+        select_options(&document, "MaterialArchive__noTable__sbv__ViewType")
+            .ok_or(SecError::Value("could not get paper type field"))
+    }
+
+    /// Scrape paper years from generated HTML.
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_years(&self, type_id: &str) -> SecResult<Vec<u32>> {
+        // Fetch the stage three HTML.
+        let html = StageBuilder::new()
+            .agree_flag(true)
+            .paper_type(type_id)
+            .query_with(self)
+            .await?;
+
+        // Parse the HTML into objects.
+        let document = Document::from(html.as_str());
+
+        // Grab the options.
+        let options = select_options(&document, "MaterialArchive__noTable__sbv__YearSelect")
+            .ok_or(SecError::Value("could not get paper year field"))?;
+
+        // Finally, parse the years.
+        Ok(options
+            .into_iter()
+            .map(|(value, _)| value.parse())
+            .collect::<Result<_, _>>()?)
+    }
+
+    /// Scrape examinations from generated HTML.
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_exams(
+        &self,
+        type_id: &str,
+        year: u32,
+    ) -> SecResult<HashMap<String, String>> {
+        Ok(self
+            .exam_options(type_id, year)
+            .await?
+            .into_iter()
+            .collect())
+    }
+
+    /// Scrape examinations from generated HTML, in the order offered by the website.
+    ///
+    /// Examinations not known to the library are returned as [`Examination::Unknown`].
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_exams_typed(&self, type_id: &str, year: u32) -> SecResult<Vec<Examination>> {
+        Ok(self
+            .exam_options(type_id, year)
+            .await?
+            .into_iter()
+            .map(|(id, label)| Examination::from_id(&id, &label))
+            .collect())
+    }
+
+    /// Scrape the examination options from the stage four HTML.
+    async fn exam_options(&self, type_id: &str, year: u32) -> SecResult<Vec<(String, String)>> {
+        // Fetch the stage four HTML.
+        let html = StageBuilder::new()
+            .agree_flag(true)
+            .paper_type(type_id)
+            .year(year)
+            .query_with(self)
+            .await?;
+
+        // Parse the HTML into objects.
+        let document = Document::from(html.as_str());
+
+        // Grab the options.
+        select_options(
+            &document,
+            "MaterialArchive__noTable__sbv__ExaminationSelect",
+        )
+        .ok_or(SecError::Value("could not get exam field"))
+    }
 
-            // Return the map.
-            Ok(map)
+    /// Scrape exam subjects from generated HTML.
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_subjects(
+        &self,
+        type_id: &str,
+        year: u32,
+        exam_id: &str,
+    ) -> SecResult<HashMap<u32, String>> {
+        // Fetch the stage five HTML.
+        let html = StageBuilder::new()
+            .agree_flag(true)
+            .paper_type(type_id)
+            .year(year)
+            .examination(exam_id)
+            .query_with(self)
+            .await?;
+
+        // Parse the HTML into objects.
+        let document = Document::from(html.as_str());
+
+        // Grab the options.
+        let options = select_options(&document, "MaterialArchive__noTable__sbv__SubjectSelect")
+            .ok_or(SecError::Value("could not get subject field"))?;
+
+        // Finally, parse the subject IDs.
+        let mut map = HashMap::new();
+        for (value, label) in options {
+            map.insert(value.parse()?, label);
         }
-        None => Err(SecError::Value("could not get subject field")),
+
+        Ok(map)
+    }
+
+    /// Scrape exam papers from the generated HTML.
+    #[instrument(level = "debug", skip(self), err(level = "debug"))]
+    pub async fn parse_papers(
+        &self,
+        type_id: &str,
+        year: u32,
+        exam_id: &str,
+        subject: u32,
+    ) -> SecResult<Papers> {
+        // Fetch the stage six HTML.
+        let html = StageBuilder::new()
+            .agree_flag(true)
+            .paper_type(type_id)
+            .year(year)
+            .examination(exam_id)
+            .subject(subject)
+            .query_with(self)
+            .await?;
+
+        // Parse the HTML into objects.
+        let document = Document::from(html.as_str());
+
+        // Resolve links against the page's base URL.
+        let base = page_base(&document, self.base_url())?;
+
+        // Parse the material table.
+        material_table(&document, &base, type_id, year, exam_id, subject)
     }
 }
 
-/// Scrape exam papers from the generated HTML.
-pub async fn parse_papers(
+/// Find the options of a select field, in the order they are listed.
+///
+/// Options with an empty value, such as the placeholder, are skipped.
+fn select_options(document: &Document, field: &str) -> Option<Vec<(String, String)>> {
+    // Find the select field.
+    let select = document.find(Attr("name", field)).next()?;
+
+    // Loop through the option fields.
+    Some(
+        select
+            .find(Name("option"))
+            .filter_map(|item| match item.attr("value") {
+                Some(x) if !x.is_empty() => Some((x.to_string(), item.text())),
+                _ => {
+                    debug!(field, label = %item.text(), "skipped option without a value");
+                    None
+                }
+            })
+            .collect(),
+    )
+}
+
+/// The material cells of a row.
+///
+/// Only the row's own cells are used, as layout rows may contain nested
+/// tables of material.
+fn material_cells<'a>(row: &Node<'a>) -> Vec<Node<'a>> {
+    row.children()
+        .filter(|x| Class("materialbody").matches(x))
+        .collect()
+}
+
+/// Parse the material table row by row.
+///
+/// Each row is expected to contain a name and a download link, any other
+/// columns are kept as extra fields. Rows which can't be understood are
+/// reported as warnings instead of failing the whole table.
+fn material_table(
+    document: &Document,
+    base: &Url,
     type_id: &str,
     year: u32,
     exam_id: &str,
     subject: u32,
-) -> SecResult<Vec<(String, String)>> {
-    // Fetch the stage two HTML.
-    let html = StageBuilder::new()
-        .agree_flag(true)
-        .paper_type(type_id)
-        .year(year)
-        .examination(exam_id)
-        .subject(subject)
-        .query()
-        .await?;
-
-    // Parse the HTML into objects.
-    let document = Document::from(html.as_str());
-
-    // Generate the inner contents of each document block.
-    let contents: Vec<String> = document
-        .find(Class("materialbody"))
-        .map(|node| filter_node(node))
+) -> SecResult<Papers> {
+    // Find the rows containing material.
+    let rows: Vec<Node> = document
+        .find(Name("tr"))
+        .filter(|row| !material_cells(row).is_empty())
         .collect();
 
     // Check if there is material.
-    if contents.is_empty() {
+    if rows.is_empty() {
         // Return a query failure.
-        Err(SecError::NoMaterial)
-    } else {
-        // Return the parsed material.
-        Ok(contents
-            .as_slice()
-            .chunks(2)
-            .map(|chunk| {
-                // Split chunk into name and link.
-                (
-                    chunk
-                        .get(0)
-                        .expect("could not get material value 0")
-                        .to_owned(),
-                    chunk
-                        .get(1)
-                        .expect("could not get material value 1")
-                        .to_owned(),
-                )
-            })
-            .collect())
+        return Err(SecError::NoMaterial);
+    }
+
+    let mut papers = Papers::default();
+    for (index, row) in rows.into_iter().enumerate() {
+        let nodes = material_cells(&row);
+        let cells: Vec<String> = nodes.iter().map(|x| x.text().trim().to_string()).collect();
+
+        // Sort the cells into the name, link and any extra columns.
+        let mut name = None;
+        let mut link = None;
+        let mut extra = Vec::new();
+        let mut reason = None;
+        for node in nodes {
+            match filter_node(node, base) {
+                Ok(MaterialCell::Text(x)) if name.is_none() => name = Some(x),
+                Ok(MaterialCell::Link(x)) if link.is_none() => link = Some(x),
+                Ok(MaterialCell::Text(x)) => extra.push(x),
+                Ok(MaterialCell::Link(x)) => extra.push(x.into()),
+                Err(e) => reason = Some(e.to_string()),
+            }
+        }
+
+        // Finally, either merge the material or report the row.
+        match (name, link, reason) {
+            (Some(name), Some(link), None) => {
+                let mut material = Material::new(type_id, year, exam_id, subject, name, link);
+                material.extra = extra;
+                papers.materials.push(material);
+            }
+            (name, link, reason) => {
+                let reason = reason.unwrap_or_else(|| {
+                    match (name, link) {
+                        (None, _) => "missing material name",
+                        _ => "missing material link",
+                    }
+                    .into()
+                });
+                warn!(row = index, cells = ?cells, reason = %reason, "skipped material row");
+                papers.warnings.push(ParseWarning {
+                    node: CrawlNode::new(type_id, year, exam_id, subject),
+                    row: index,
+                    cells,
+                    reason,
+                });
+            }
+        }
+    }
+
+    Ok(papers)
+}
+
+/// A single cell of the material table.
+#[derive(Debug, Clone, PartialEq)]
+enum MaterialCell {
+    /// The raw query name for the material.
+    Text(String),
+    /// The download link for the material.
+    Link(Url),
+}
+
+/// Find the base URL used to resolve links on a page.
+///
+/// This is the archive URL, unless the page overrides it with a ``<base>`` tag.
+fn page_base(document: &Document, archive: &Url) -> SecResult<Url> {
+    match document.find(Name("base")).find_map(|x| x.attr("href")) {
+        Some(href) => {
+            debug!(href, "resolving links against the page's base tag");
+            resolve_link(archive, href)
+        }
+        None => Ok(archive.clone()),
+    }
+}
+
+/// Resolve a link found on a page against its base URL.
+fn resolve_link(base: &Url, href: &str) -> SecResult<Url> {
+    let link = base
+        .join(href.trim())
+        .map_err(|_| SecError::InvalidLink(href.into()))?;
+
+    // Only web links can be downloaded.
+    match link.scheme() {
+        "http" | "https" if link.has_host() => Ok(link),
+        _ => Err(SecError::InvalidLink(href.into())),
     }
 }
 
@@ -267,38 +410,32 @@ pub async fn parse_papers(
 ///
 /// This function produces either the raw query name
 /// for the material or the download link for the material.
-fn filter_node(node: Node) -> String {
+fn filter_node(node: Node, base: &Url) -> SecResult<MaterialCell> {
     // Check if the node contains "Click Here".
     if !node.text().trim().contains("Click Here") {
-        node.text().trim().to_string()
-    } else {
-        // Loop through and filter by hyperlink.
-        for x in node.children().filter(|x| x.name() == Some("a")) {
-            // Check if it contains a link.
-            if x.attr("href").is_some() {
-                let x = match x.attr("href") {
-                    Some(x) => x,
-                    None => return String::new(),
-                };
-
-                // If the link contains the EXAM_URL, return the string.
-                if x.contains("https://www.examinations.ie") {
-                    return x.to_string();
-                } else {
-                    return format!("{}/{}", EXAM_URL, x.to_string());
-                }
-            }
-        }
+        return Ok(MaterialCell::Text(node.text().trim().to_string()));
+    }
 
-        // If all other branches fail.
-        String::new()
+    // Find the first hyperlink and resolve it.
+    match node.find(Name("a")).find_map(|x| x.attr("href")) {
+        Some(href) => Ok(MaterialCell::Link(resolve_link(base, href)?)),
+        None => Err(SecError::InvalidLink(node.text().trim().into())),
     }
 }
 
 #[cfg(test)]
 mod parser_tests {
     use super::*;
-    use crate::consts::EXAM_PAPER_YEARS;
+    use crate::test_util::MockServer;
+
+    #[tokio::test]
+    async fn terms() -> SecResult<()> {
+        // Parse the terms and conditions.
+        let server = MockServer::start();
+        let output = server.client().parse_terms().await?;
+        assert!(!output.text.is_empty());
+        Ok(())
+    }
 
     #[tokio::test]
     async fn paper_type() -> SecResult<()> {
@@ -308,16 +445,20 @@ mod parser_tests {
         result.insert("markingschemes".into(), "Marking Schemes".into());
 
         // Parse the paper types.
-        let output = parse_types().await?;
-        Ok(assert_eq!(output, result))
+        let server = MockServer::start();
+        let output = server.client().parse_types().await?;
+        assert_eq!(output, result);
+        Ok(())
     }
 
     #[tokio::test]
     async fn paper_years() -> SecResult<()> {
-        // Parse the paper years.
-        let output = parse_years("exampapers").await?;
-        let output: Vec<u32> = output.into_iter().rev().collect();
-        Ok(assert_eq!(output, *EXAM_PAPER_YEARS))
+        // Parse the paper years, which are listed newest first.
+        let server = MockServer::start();
+        let output = server.client().parse_years("exampapers").await?;
+        assert!(output.contains(&1995));
+        assert!(output.windows(2).all(|x| x[0] > x[1]));
+        Ok(())
     }
 
     #[tokio::test]
@@ -329,8 +470,10 @@ mod parser_tests {
         result.insert("jc".into(), "Junior Certificate / Cycle".into());
 
         // Parse the examinations.
-        let output = parse_exams("exampapers", 2019).await?;
-        Ok(assert_eq!(output, result))
+        let server = MockServer::start();
+        let output = server.client().parse_exams("exampapers", 2019).await?;
+        assert_eq!(output, result);
+        Ok(())
     }
 
     #[tokio::test]
@@ -344,7 +487,192 @@ mod parser_tests {
         result.insert(1, "Irish".into());
 
         // Parse the exam subjects.
-        let output = parse_subjects("exampapers", 1995, "lc").await?;
-        Ok(assert_eq!(output, result))
+        let server = MockServer::start();
+        let output = server
+            .client()
+            .parse_subjects("exampapers", 1995, "lc")
+            .await?;
+        assert_eq!(output, result);
+        Ok(())
+    }
+
+    #[test]
+    fn link_resolution() -> SecResult<()> {
+        let base = SecClient::new().base_url().clone();
+        let cases = [
+            (
+                "https://www.examinations.ie/archive/exampapers/2019/LC003ALP100EV.pdf",
+                "https://www.examinations.ie/archive/exampapers/2019/LC003ALP100EV.pdf",
+            ),
+            (
+                "../archive/exampapers/2019/LC003ALP100EV.pdf",
+                "https://www.examinations.ie/archive/exampapers/2019/LC003ALP100EV.pdf",
+            ),
+            (
+                "index.cfm?fa=download&id=1",
+                "https://www.examinations.ie/exammaterialarchive/index.cfm?fa=download&id=1",
+            ),
+            (
+                "/archive/markingschemes/2019/LC003ALP000EV.pdf",
+                "https://www.examinations.ie/archive/markingschemes/2019/LC003ALP000EV.pdf",
+            ),
+        ];
+
+        for (href, expected) in cases.iter() {
+            assert_eq!(*expected, resolve_link(&base, href)?.as_str());
+        }
+
+        assert!(matches!(
+            resolve_link(&base, "javascript:void(0)"),
+            Err(SecError::InvalidLink(_))
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn material_cells() -> SecResult<()> {
+        let html = r#"<table><tr>
+            <td class="materialbody">Mathematics / Higher Level (EV)</td>
+            <td class="materialbody"><a href="../archive/paper.pdf">Click Here</a></td>
+            <td class="materialbody"><a>Click Here</a></td>
+        </tr></table>"#;
+        let document = Document::from(html);
+        let base = page_base(&document, SecClient::new().base_url())?;
+        let cells: Vec<SecResult<MaterialCell>> = document
+            .find(Class("materialbody"))
+            .map(|node| filter_node(node, &base))
+            .collect();
+
+        assert_eq!(
+            MaterialCell::Text("Mathematics / Higher Level (EV)".into()),
+            *cells[0].as_ref().unwrap()
+        );
+        assert_eq!(
+            MaterialCell::Link(
+                Url::parse("https://www.examinations.ie/archive/paper.pdf").unwrap()
+            ),
+            *cells[1].as_ref().unwrap()
+        );
+        assert!(matches!(cells[2], Err(SecError::InvalidLink(_))));
+        Ok(())
+    }
+
+    #[test]
+    fn material_rows() -> SecResult<()> {
+        let html = r#"<table>
+            <tr><th>Name</th><th>Link</th></tr>
+            <tr>
+                <td class="materialbody">Mathematics / Higher Level / Paper 1 (EV)</td>
+                <td class="materialbody"><a href="../archive/p1.pdf">Click Here</a></td>
+            </tr>
+            <tr>
+                <td class="materialbody">Mathematics / Higher Level / Paper 2 (EV)</td>
+                <td class="materialbody"><a href="../archive/p2.pdf">Click Here</a></td>
+                <td class="materialbody">2.1 MB</td>
+            </tr>
+            <tr>
+                <td class="materialbody">Mathematics / Ordinary Level (EV)</td>
+            </tr>
+        </table>"#;
+        let base = SecClient::new().base_url().clone();
+        let papers = material_table(&Document::from(html), &base, "exampapers", 2019, "lc", 3)?;
+
+        assert_eq!(2, papers.materials.len());
+        assert!(papers.materials[0].extra.is_empty());
+        assert_eq!(vec![String::from("2.1 MB")], papers.materials[1].extra);
+        assert_eq!(1, papers.warnings.len());
+        assert_eq!(2, papers.warnings[0].row);
+        assert_eq!(
+            CrawlNode::new("exampapers", 2019, "lc", 3),
+            papers.warnings[0].node
+        );
+        assert_eq!("missing material link", papers.warnings[0].reason);
+
+        assert!(matches!(
+            material_table(
+                &Document::from("<p>No material</p>"),
+                &base,
+                "exampapers",
+                2019,
+                "lc",
+                3
+            ),
+            Err(SecError::NoMaterial)
+        ));
+        Ok(())
+    }
+
+    #[test]
+    fn nested_tables() -> SecResult<()> {
+        let html = r#"<table class="layout">
+            <tr><td class="menu">Menu</td><td>
+                <table>
+                    <tr>
+                        <td class="materialbody">Mathematics / Higher Level / Paper 1 (EV)</td>
+                        <td class="materialbody"><a href="../archive/p1.pdf">Click Here</a></td>
+                    </tr>
+                    <tr>
+                        <td class="materialbody">Mathematics / Higher Level / Paper 2 (EV)</td>
+                        <td class="materialbody"><a href="../archive/p2.pdf">Click Here</a></td>
+                    </tr>
+                </table>
+            </td></tr>
+        </table>"#;
+        let base = SecClient::new().base_url().clone();
+        let papers = material_table(&Document::from(html), &base, "exampapers", 2019, "lc", 3)?;
+
+        // Only the inner rows are material.
+        let names: Vec<&str> = papers.materials.iter().map(|x| x.name.as_str()).collect();
+        assert_eq!(
+            vec![
+                "Mathematics / Higher Level / Paper 1 (EV)",
+                "Mathematics / Higher Level / Paper 2 (EV)"
+            ],
+            names
+        );
+        assert!(papers.materials.iter().all(|x| x.extra.is_empty()));
+        assert!(papers.warnings.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn option_order() {
+        let html = r#"<select name="MaterialArchive__noTable__sbv__ExaminationSelect">
+            <option value="">Select Examination</option>
+            <option value="lc">Leaving Certificate</option>
+            <option value="jc">Junior Certificate / Cycle</option>
+            <option value="xx">Some Examination</option>
+        </select>"#;
+        let options = select_options(
+            &Document::from(html),
+            "MaterialArchive__noTable__sbv__ExaminationSelect",
+        )
+        .unwrap();
+        let exams: Vec<Examination> = options
+            .into_iter()
+            .map(|(id, label)| Examination::from_id(&id, &label))
+            .collect();
+
+        assert_eq!(
+            vec![
+                Examination::LeavingCertificate,
+                Examination::JuniorCertificate,
+                Examination::Unknown {
+                    id: "xx".into(),
+                    label: "Some Examination".into()
+                },
+            ],
+            exams
+        );
+        assert!(select_options(&Document::from("<p></p>"), "missing").is_none());
+    }
+
+    #[tokio::test]
+    async fn paper_type_typed() -> SecResult<()> {
+        // Parse the paper types.
+        let server = MockServer::start();
+        let output = server.client().parse_types_typed().await?;
+        assert_eq!(vec![Type::ExamPaper, Type::MarkingScheme], output);
+        Ok(())
     }
 }
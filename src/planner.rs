@@ -0,0 +1,474 @@
+//! Planning the stage queries needed for a filtered crawl.
+//!
+//! Walking the whole stage tree means listing the years, examinations and
+//! subjects offered before any material is found. Some of these lists
+//! aren't needed:
+//!
+//! - The years of a paper type are taken from the year cache once they
+//!   have been discovered.
+//! - The examinations offered in a year are taken from the client's
+//!   response cache when their page is cached.
+//! - The subjects named by a [`MaterialFilter`] are queried directly, as
+//!   they are known from the schema.
+//!
+//! The years and examinations are never guessed, as the live lists skip
+//! what isn't offered. A [`Planner`] can list the queries a crawl would
+//! make without sending any of them, or while only fetching the lists,
+//! see [`Planner::dry_run`].
+
+use crate::{
+    checkpoint::CrawlNode,
+    client::SecClient,
+    error::SecResult,
+    filter::MaterialFilter,
+    schema::metadata::{Examination, Type},
+    stages::StageBuilder,
+    years::Year,
+};
+use std::fmt;
+use strum::{EnumProperty, IntoEnumIterator};
+
+/// A query of the archive's stages.
+#[derive(Debug, Clone, PartialEq)]
+pub enum StageQuery {
+    /// List the years offered for a paper type.
+    Years {
+        /// Paper type ID.
+        type_id: String,
+    },
+    /// List the examinations offered in a year.
+    Examinations {
+        /// Paper type ID.
+        type_id: String,
+        /// Year of the examinations.
+        year: u32,
+    },
+    /// List the subjects offered for an examination.
+    Subjects {
+        /// Paper type ID.
+        type_id: String,
+        /// Year of the examination.
+        year: u32,
+        /// Examination ID.
+        exam_id: String,
+    },
+    /// List the material of a subject.
+    Papers(CrawlNode),
+}
+
+impl StageQuery {
+    /// The stage builder sending the query.
+    pub fn builder(&self) -> StageBuilder {
+        let builder = StageBuilder::new().agree_flag(true);
+        match self {
+            StageQuery::Years { type_id } => builder.paper_type(type_id),
+            StageQuery::Examinations { type_id, year } => builder.paper_type(type_id).year(*year),
+            StageQuery::Subjects {
+                type_id,
+                year,
+                exam_id,
+            } => builder.paper_type(type_id).year(*year).examination(exam_id),
+            StageQuery::Papers(node) => builder
+                .paper_type(&node.type_id)
+                .year(node.year)
+                .examination(&node.exam_id)
+                .subject(node.subject_id),
+        }
+    }
+}
+
+impl fmt::Display for StageQuery {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StageQuery::Years { type_id } => write!(f, "years of {}", type_id),
+            StageQuery::Examinations { type_id, year } => {
+                write!(f, "examinations of {} {}", type_id, year)
+            }
+            StageQuery::Subjects {
+                type_id,
+                year,
+                exam_id,
+            } => write!(f, "subjects of {} {} {}", type_id, year, exam_id),
+            StageQuery::Papers(node) => write!(f, "papers of {}", node),
+        }
+    }
+}
+
+/// A query a crawl would make.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PlannedQuery {
+    /// The query.
+    pub query: StageQuery,
+    /// Whether the response is already cached, so no request is sent.
+    pub cached: bool,
+}
+
+/// The queries a crawl would make, in the order they would be made.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct CrawlPlan {
+    /// Every query of the crawl that could be planned.
+    pub queries: Vec<PlannedQuery>,
+    /// Whether every query was planned.
+    ///
+    /// The options of a list which isn't cached are only known once it
+    /// is fetched, so the queries for them are left out of the plan.
+    pub complete: bool,
+}
+
+impl CrawlPlan {
+    /// The queries which would send a request to the website.
+    pub fn requests(&self) -> impl Iterator<Item = &StageQuery> {
+        self.queries.iter().filter(|x| !x.cached).map(|x| &x.query)
+    }
+
+    /// Add a query to the plan.
+    fn push(&mut self, query: StageQuery, client: &SecClient) -> bool {
+        let cached = query.builder().is_cached(client);
+        self.queries.push(PlannedQuery { query, cached });
+        cached
+    }
+
+    /// Add a list query to the plan, returning if its options will be known.
+    ///
+    /// The options of a list which isn't cached are known if it is fetched.
+    fn push_list(&mut self, query: StageQuery, client: &SecClient, fetch: bool) -> bool {
+        let known = self.push(query, client) || fetch;
+        self.complete &= known;
+        known
+    }
+}
+
+/// Plans the stage queries needed to find the material matching a filter.
+#[derive(Debug, Clone)]
+pub struct Planner {
+    client: SecClient,
+    filter: MaterialFilter,
+    fetch_lists: bool,
+}
+
+impl Planner {
+    /// Create a new planner for the given filter.
+    pub fn new(filter: MaterialFilter) -> Self {
+        Self {
+            client: SecClient::new(),
+            filter,
+            fetch_lists: false,
+        }
+    }
+
+    /// Set the client whose caches are used.
+    pub fn client(mut self, client: SecClient) -> Self {
+        self.client = client;
+        self
+    }
+
+    /// Fetch the year, examination and subject lists which aren't cached
+    /// while planning, so a [`dry_run`](Self::dry_run) plans every query
+    /// and only withholds the paper queries.
+    pub fn fetch_lists(mut self, flag: bool) -> Self {
+        self.fetch_lists = flag;
+        self
+    }
+
+    /// The paper types to crawl.
+    pub(crate) fn types(&self) -> Vec<Type> {
+        match self.filter.types() {
+            [] => Type::iter().collect(),
+            types => types.to_vec(),
+        }
+    }
+
+    /// The years to crawl for a paper type, if they have been cached.
+    pub(crate) fn years(&self, paper_type: &Type) -> Option<Vec<Year>> {
+        let years = self.client.cached_years(paper_type)?;
+        Some(
+            years
+                .into_iter()
+                .filter(|x| self.filter.matches_year(*x))
+                .collect(),
+        )
+    }
+
+    /// The examination IDs to crawl in a year, if their list is cached.
+    pub(crate) async fn examinations(
+        &self,
+        type_id: &str,
+        year: u32,
+    ) -> SecResult<Option<Vec<String>>> {
+        let query = StageQuery::Examinations {
+            type_id: type_id.into(),
+            year,
+        };
+        if !query.builder().is_cached(&self.client) {
+            return Ok(None);
+        }
+        self.list_examinations(type_id, year).await.map(Some)
+    }
+
+    /// List the examination IDs to crawl in a year, querying them if needed.
+    async fn list_examinations(&self, type_id: &str, year: u32) -> SecResult<Vec<String>> {
+        let offered = self.client.parse_exams(type_id, year).await?;
+        Ok(Examination::iter()
+            .map(|x| x.id().to_string())
+            .filter(|x| offered.contains_key(x) && self.filter.matches_examination(x))
+            .collect())
+    }
+
+    /// The subject IDs to crawl, if they are known without a query.
+    pub(crate) fn subjects(&self) -> Option<Vec<u32>> {
+        match self.filter.subjects() {
+            [] => None,
+            subjects => Some(
+                subjects
+                    .iter()
+                    .filter_map(|x| x.get_str("id").and_then(|x| x.parse().ok()))
+                    .collect(),
+            ),
+        }
+    }
+
+    /// List the queries a crawl would make, without sending any requests.
+    ///
+    /// Lists which have to be fetched are included in the plan, but the
+    /// queries for their options can't be planned until they are cached,
+    /// unless the planner [fetches the lists](Self::fetch_lists).
+    ///
+    /// ```no_run
+    /// # use resec::{filter::MaterialFilter, planner::Planner};
+    /// # async fn run() -> Result<(), resec::error::SecError> {
+    /// let filter: MaterialFilter = "exam:lc subject:french level:higher year:2015..2020".parse()?;
+    /// for query in Planner::new(filter).dry_run().await?.requests() {
+    ///     println!("{}", query);
+    /// }
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn dry_run(&self) -> SecResult<CrawlPlan> {
+        let client = &self.client;
+        let mut plan = CrawlPlan {
+            queries: Vec::new(),
+            complete: true,
+        };
+
+        for paper_type in self.types() {
+            let type_id = paper_type.id();
+            let years = match self.years(&paper_type) {
+                Some(years) => years,
+                None => {
+                    let query = StageQuery::Years {
+                        type_id: type_id.into(),
+                    };
+                    if !plan.push_list(query, client, self.fetch_lists) {
+                        continue;
+                    }
+                    client
+                        .available_years(&paper_type)
+                        .await?
+                        .into_iter()
+                        .filter(|x| self.filter.matches_year(*x))
+                        .collect()
+                }
+            };
+
+            for Year(year) in years {
+                let query = StageQuery::Examinations {
+                    type_id: type_id.into(),
+                    year,
+                };
+                if !plan.push_list(query, client, self.fetch_lists) {
+                    continue;
+                }
+                let exams = self.list_examinations(type_id, year).await?;
+
+                for exam_id in exams {
+                    let subjects = match self.subjects() {
+                        Some(subjects) => subjects,
+                        None => {
+                            let query = StageQuery::Subjects {
+                                type_id: type_id.into(),
+                                year,
+                                exam_id: exam_id.clone(),
+                            };
+                            if !plan.push_list(query, client, self.fetch_lists) {
+                                continue;
+                            }
+                            let mut subjects: Vec<u32> = client
+                                .parse_subjects(type_id, year, &exam_id)
+                                .await?
+                                .into_keys()
+                                .collect();
+                            subjects.sort_unstable();
+                            subjects
+                        }
+                    };
+
+                    for subject_id in subjects {
+                        let node = CrawlNode::new(type_id, year, &exam_id, subject_id);
+                        plan.push(StageQuery::Papers(node), client);
+                    }
+                }
+            }
+        }
+
+        Ok(plan)
+    }
+}
+
+#[cfg(test)]
+mod planner_tests {
+    use super::*;
+    use crate::{
+        cache::ResponseCache,
+        crawler::Crawler,
+        test_util::{MockArchive, MockServer},
+    };
+    use std::time::Duration;
+
+    #[tokio::test]
+    async fn pruned_stages() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server
+            .client()
+            .cache(ResponseCache::new(Duration::from_secs(60)));
+        let filter: MaterialFilter =
+            "type:exampapers exam:lc subject:french level:higher year:2015..2020".parse()?;
+
+        // Nothing is cached, so only the years can be planned.
+        let plan = Planner::new(filter.clone())
+            .client(client.clone())
+            .dry_run()
+            .await?;
+        assert!(!plan.complete);
+        assert_eq!(
+            vec![&StageQuery::Years {
+                type_id: "exampapers".into()
+            }],
+            plan.requests().collect::<Vec<_>>()
+        );
+        assert_eq!(0, server.request_count());
+
+        // The named subject is queried without listing the subjects.
+        let papers = Crawler::new()
+            .client(client.clone())
+            .filter(filter)
+            .crawl()
+            .await?;
+        assert_eq!(1 + 6 + 6, server.request_count());
+        assert_eq!(18, papers.materials.len());
+
+        // Once the lists are cached, only the papers are queried.
+        let filter: MaterialFilter = "type:exampapers exam:lc subject:3 year:2015..2020".parse()?;
+        let plan = Planner::new(filter.clone())
+            .client(client.clone())
+            .dry_run()
+            .await?;
+        assert!(plan.complete);
+        assert_eq!(6, plan.requests().count());
+        assert_eq!(
+            Some(&StageQuery::Papers(CrawlNode::new(
+                "exampapers",
+                2015,
+                "lc",
+                3
+            ))),
+            plan.requests().next()
+        );
+        Crawler::new().client(client).filter(filter).crawl().await?;
+        assert_eq!(1 + 6 + 6 + 6, server.request_count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn exams_not_offered() -> SecResult<()> {
+        let mut archive = MockArchive::default();
+        archive.year_exams.insert(2019, vec!["lc".into()]);
+        let server = MockServer::with_archive(archive);
+        let filter: MaterialFilter = "type:exampapers exam:jc year:2019".parse()?;
+
+        // The examinations are listed, so the subjects of one not offered aren't.
+        let papers = Crawler::new()
+            .client(server.client())
+            .filter(filter)
+            .crawl()
+            .await?;
+        assert!(papers.materials.is_empty());
+        assert_eq!(2, server.request_count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn cached_lists() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server
+            .client()
+            .cache(ResponseCache::new(Duration::from_secs(60)));
+        let filter: MaterialFilter = "type:markingschemes year:2019 subject:3".parse()?;
+        let planner = Planner::new(filter).client(client.clone());
+
+        // The years have to be listed first.
+        let plan = planner.dry_run().await?;
+        assert!(!plan.complete);
+        assert_eq!(
+            vec![&StageQuery::Years {
+                type_id: "markingschemes".into()
+            }],
+            plan.requests().collect::<Vec<_>>()
+        );
+
+        // Then the examinations of 2019.
+        client.available_years(&Type::MarkingScheme).await?;
+        let plan = planner.dry_run().await?;
+        assert!(!plan.complete);
+        assert_eq!(
+            vec![&StageQuery::Examinations {
+                type_id: "markingschemes".into(),
+                year: 2019,
+            }],
+            plan.requests().collect::<Vec<_>>()
+        );
+
+        // Once cached, the rest of the crawl can be planned.
+        client.parse_exams("markingschemes", 2019).await?;
+        let plan = planner.dry_run().await?;
+        assert!(plan.complete);
+        assert!(plan.queries[0].cached);
+        let requests: Vec<String> = plan.requests().map(|x| x.to_string()).collect();
+        assert_eq!(
+            vec![
+                "papers of markingschemes/2019/lb/3",
+                "papers of markingschemes/2019/lc/3",
+                "papers of markingschemes/2019/jc/3",
+            ],
+            requests
+        );
+        assert_eq!(2, server.request_count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn fetched_lists() -> SecResult<()> {
+        let server = MockServer::start();
+        let filter: MaterialFilter = "type:markingschemes year:2019 subject:3".parse()?;
+
+        // Without a cache, the lists are fetched to plan the papers.
+        let plan = Planner::new(filter)
+            .client(server.client())
+            .fetch_lists(true)
+            .dry_run()
+            .await?;
+        assert!(plan.complete);
+        let requests: Vec<String> = plan.requests().map(|x| x.to_string()).collect();
+        assert_eq!(
+            vec![
+                "years of markingschemes",
+                "examinations of markingschemes 2019",
+                "papers of markingschemes/2019/lb/3",
+                "papers of markingschemes/2019/lc/3",
+                "papers of markingschemes/2019/jc/3",
+            ],
+            requests
+        );
+        assert_eq!(2, server.request_count());
+        Ok(())
+    }
+}
@@ -0,0 +1,235 @@
+//! Probing material without downloading it.
+//!
+//! A probe asks the server for the size, format and version of a material
+//! with a ``HEAD`` request. Given the [`Validators`] of an earlier download,
+//! the request is made conditional so the server can report whether the
+//! material changed since.
+
+use crate::{
+    client::SecClient,
+    error::SecResult,
+    material::{FileFormat, Material},
+};
+use chrono::{DateTime, Utc};
+use reqwest::{
+    header::{
+        HeaderMap, CONTENT_LENGTH, CONTENT_TYPE, ETAG, IF_MODIFIED_SINCE, IF_NONE_MATCH,
+        LAST_MODIFIED,
+    },
+    RequestBuilder, StatusCode,
+};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The headers identifying a version of a material.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(default))]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Validators {
+    /// The ``ETag`` header.
+    pub etag: Option<String>,
+    /// The ``Last-Modified`` header, as sent by the server.
+    pub last_modified: Option<String>,
+}
+
+impl Validators {
+    /// Read the validators from response headers.
+    pub(crate) fn from_headers(headers: &HeaderMap) -> Self {
+        let header = |name| {
+            headers
+                .get(name)
+                .and_then(|x| x.to_str().ok())
+                .map(String::from)
+        };
+        Self {
+            etag: header(ETAG),
+            last_modified: header(LAST_MODIFIED),
+        }
+    }
+
+    /// Check if there are no validators to send.
+    pub fn is_empty(&self) -> bool {
+        self.etag.is_none() && self.last_modified.is_none()
+    }
+
+    /// Make a request conditional on the material having changed.
+    ///
+    /// The entity tag is preferred, as servers ignore ``If-Modified-Since``
+    /// when ``If-None-Match`` is present.
+    pub(crate) fn apply(&self, request: RequestBuilder) -> RequestBuilder {
+        match (&self.etag, &self.last_modified) {
+            (Some(etag), _) => request.header(IF_NONE_MATCH, etag.as_str()),
+            (None, Some(date)) => request.header(IF_MODIFIED_SINCE, date.as_str()),
+            (None, None) => request,
+        }
+    }
+}
+
+/// What the server reported about a material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Probe {
+    /// Whether the material changed since the validators given, always
+    /// ``true`` for an unconditional probe.
+    pub modified: bool,
+    /// Size of the material in bytes, if reported.
+    pub size: Option<u64>,
+    /// The ``Content-Type`` header, if reported.
+    pub content_type: Option<String>,
+    /// The format given by the content type.
+    pub format: FileFormat,
+    /// When the material was last modified, if reported.
+    pub last_modified: Option<DateTime<Utc>>,
+    /// The validators of the current version.
+    pub validators: Validators,
+}
+
+impl Probe {
+    /// Build a probe from a response to a conditional request.
+    fn from_response(status: StatusCode, headers: &HeaderMap, since: &Validators) -> Self {
+        let mut validators = Validators::from_headers(headers);
+        let not_modified = status == StatusCode::NOT_MODIFIED;
+        if not_modified {
+            // A 304 may leave out headers which didn't change.
+            validators.etag = validators.etag.or_else(|| since.etag.clone());
+            validators.last_modified = validators
+                .last_modified
+                .or_else(|| since.last_modified.clone());
+        }
+
+        // Some servers ignore conditional HEAD requests, so compare the
+        // entity tags as well.
+        let same_etag = since.etag.is_some() && since.etag == validators.etag;
+        let content_type = headers
+            .get(CONTENT_TYPE)
+            .and_then(|x| x.to_str().ok())
+            .map(String::from);
+
+        Self {
+            modified: !(not_modified || same_etag),
+            size: headers
+                .get(CONTENT_LENGTH)
+                .filter(|_| !not_modified)
+                .and_then(|x| x.to_str().ok())
+                .and_then(|x| x.parse().ok()),
+            format: content_type
+                .as_deref()
+                .map_or(FileFormat::Unknown, FileFormat::from_content_type),
+            content_type,
+            last_modified: validators
+                .last_modified
+                .as_deref()
+                .and_then(|x| DateTime::parse_from_rfc2822(x).ok())
+                .map(|x| x.with_timezone(&Utc)),
+            validators,
+        }
+    }
+}
+
+impl SecClient {
+    /// Probe a material with a ``HEAD`` request.
+    ///
+    /// ```no_run
+    /// # use resec::{client::SecClient, material::Material};
+    /// # async fn run(material: Material) -> Result<(), resec::error::SecError> {
+    /// let probe = SecClient::new().probe(&material).await?;
+    /// println!("{:?} bytes, {}", probe.size, probe.format);
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub async fn probe(&self, material: &Material) -> SecResult<Probe> {
+        self.probe_since(material, &Validators::default()).await
+    }
+
+    /// Probe a material, checking if it changed since the given validators.
+    ///
+    /// Servers which don't allow ``HEAD`` are sent a conditional ``GET``
+    /// instead, whose body is never read.
+    pub async fn probe_since(&self, material: &Material, since: &Validators) -> SecResult<Probe> {
+        let mut response = self
+            .send_probe(self.http().head(material.link.clone()), since)
+            .await?;
+        if matches!(
+            response.status(),
+            StatusCode::METHOD_NOT_ALLOWED | StatusCode::NOT_IMPLEMENTED
+        ) {
+            response = self
+                .send_probe(self.http().get(material.link.clone()), since)
+                .await?;
+        }
+
+        let response = response.error_for_status()?;
+        Ok(Probe::from_response(
+            response.status(),
+            response.headers(),
+            since,
+        ))
+    }
+
+    /// Send a conditional probe request.
+    async fn send_probe(
+        &self,
+        request: RequestBuilder,
+        since: &Validators,
+    ) -> SecResult<reqwest::Response> {
+        self.throttle().await;
+        self.read(since.apply(request).send()).await
+    }
+}
+
+#[cfg(test)]
+mod probe_tests {
+    use super::*;
+    use crate::test_util::MockServer;
+    use chrono::TimeZone;
+
+    #[tokio::test]
+    async fn conditional() -> SecResult<()> {
+        let server = MockServer::start();
+        server.set_file("/archive/exampapers/2019/LC003ALP100EV.pdf", vec![1; 64]);
+        let link = server
+            .url()
+            .join("/archive/exampapers/2019/LC003ALP100EV.pdf")
+            .unwrap();
+        let material = Material::new(
+            "exampapers",
+            2019,
+            "lc",
+            3,
+            "Mathematics / Higher Level / Paper 1 (EV)".into(),
+            link,
+        );
+        let client = server.client();
+
+        let probe = client.probe(&material).await?;
+        assert!(probe.modified);
+        assert_eq!(Some(64), probe.size);
+        assert_eq!(FileFormat::Pdf, probe.format);
+        assert_eq!(
+            Some(Utc.with_ymd_and_hms(2019, 7, 1, 9, 0, 0).unwrap()),
+            probe.last_modified
+        );
+        assert!(probe.validators.etag.is_some());
+
+        // The same version isn't modified.
+        let unchanged = client.probe_since(&material, &probe.validators).await?;
+        assert!(!unchanged.modified);
+        assert_eq!(probe.validators, unchanged.validators);
+        assert_eq!(
+            Some(&probe.validators.etag.clone().unwrap()),
+            server
+                .request_headers()
+                .last()
+                .unwrap()
+                .get("if-none-match")
+        );
+
+        // A new version is.
+        server.set_file("/archive/exampapers/2019/LC003ALP100EV.pdf", vec![2; 80]);
+        let changed = client.probe_since(&material, &probe.validators).await?;
+        assert!(changed.modified);
+        assert_eq!(Some(80), changed.size);
+        assert_ne!(probe.validators, changed.validators);
+        Ok(())
+    }
+}
@@ -0,0 +1,191 @@
+//! Progress reporting for long running crawls and downloads.
+//!
+//! The [`Crawler`](crate::crawler::Crawler) and
+//! [`Downloader`](crate::downloader::Downloader) emit a [`ProgressEvent`]
+//! to a [`Progress`] sink as they work. A sink can be a closure, a
+//! [`Sender`] to receive events on another thread, or any type
+//! implementing the trait.
+
+use crate::material::Material;
+use std::{
+    fmt,
+    sync::{mpsc::Sender, Arc},
+};
+use url::Url;
+
+/// A stage of the archive whose options were discovered.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Stage {
+    /// The years offered for a paper type.
+    Years {
+        /// Paper type ID.
+        type_id: String,
+    },
+    /// The examinations offered for a year.
+    Examinations {
+        /// Paper type ID.
+        type_id: String,
+        /// Year of the examinations.
+        year: u32,
+    },
+    /// The subjects offered for an examination.
+    Subjects {
+        /// Paper type ID.
+        type_id: String,
+        /// Year of the examination.
+        year: u32,
+        /// Examination ID.
+        exam_id: String,
+    },
+}
+
+/// An event emitted while crawling or downloading.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ProgressEvent {
+    /// The options of a stage were discovered.
+    StageDiscovered {
+        /// The stage that was queried.
+        stage: Stage,
+        /// Number of options the crawler will visit.
+        options: usize,
+    },
+    /// Every paper of a subject was listed.
+    SubjectCompleted {
+        /// Paper type ID.
+        type_id: String,
+        /// Year of the examination.
+        year: u32,
+        /// Examination ID.
+        exam_id: String,
+        /// Subject ID.
+        subject_id: u32,
+        /// Number of subjects completed so far.
+        completed: usize,
+        /// Number of subjects discovered so far.
+        total: usize,
+    },
+    /// A material was found by the crawler.
+    MaterialFound(Material),
+    /// A download was started.
+    DownloadStarted {
+        /// Link to the material.
+        link: Url,
+        /// Size of the material in bytes, if known.
+        size: Option<u64>,
+    },
+    /// Part of a material was downloaded.
+    BytesDownloaded {
+        /// Link to the material.
+        link: Url,
+        /// Bytes downloaded so far.
+        bytes: u64,
+        /// Size of the material in bytes, if known.
+        size: Option<u64>,
+    },
+    /// A download was completed.
+    DownloadCompleted {
+        /// Link to the material.
+        link: Url,
+        /// Size of the material in bytes.
+        bytes: u64,
+    },
+    /// A material was unchanged since it was last downloaded.
+    DownloadUnchanged {
+        /// Link to the material.
+        link: Url,
+        /// Size of the material in bytes.
+        bytes: u64,
+    },
+    /// A failed request is being retried.
+    Retry {
+        /// What was being requested.
+        target: String,
+        /// The attempt about to be made, starting from 2.
+        attempt: u32,
+        /// Why the previous attempt failed.
+        error: String,
+    },
+    /// A request failed.
+    Error {
+        /// What was being requested.
+        target: String,
+        /// Why the request failed.
+        error: String,
+    },
+}
+
+/// A sink for progress events.
+pub trait Progress: Send + Sync {
+    /// Handle an event.
+    fn event(&self, event: &ProgressEvent);
+}
+
+impl<F> Progress for F
+where
+    F: Fn(&ProgressEvent) + Send + Sync,
+{
+    fn event(&self, event: &ProgressEvent) {
+        self(event)
+    }
+}
+
+impl Progress for Sender<ProgressEvent> {
+    fn event(&self, event: &ProgressEvent) {
+        // The receiver may have hung up, progress is best effort.
+        let _ = self.send(event.clone());
+    }
+}
+
+/// An optional progress sink shared by clones of a crawler or downloader.
+#[derive(Clone, Default)]
+pub(crate) struct Reporter(Option<Arc<dyn Progress>>);
+
+impl Reporter {
+    /// Report to the given sink.
+    pub(crate) fn new(progress: impl Progress + 'static) -> Self {
+        Self(Some(Arc::new(progress)))
+    }
+
+    /// Emit an event if a sink is set.
+    pub(crate) fn emit(&self, event: impl FnOnce() -> ProgressEvent) {
+        if let Some(progress) = &self.0 {
+            progress.event(&event());
+        }
+    }
+}
+
+impl fmt::Debug for Reporter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("Reporter")
+            .field(&self.0.as_ref().map(|_| ".."))
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod progress_tests {
+    use super::*;
+    use std::sync::{mpsc, Mutex};
+
+    #[test]
+    fn sinks() {
+        let seen = Arc::new(Mutex::new(Vec::new()));
+        let recorder = seen.clone();
+        let reporter = Reporter::new(move |event: &ProgressEvent| {
+            recorder.lock().unwrap().push(event.clone());
+        });
+        let event = ProgressEvent::Error {
+            target: "types".into(),
+            error: "timed out".into(),
+        };
+        reporter.emit(|| event.clone());
+        assert_eq!(vec![event.clone()], *seen.lock().unwrap());
+
+        let (sender, receiver) = mpsc::channel();
+        Reporter::new(sender).emit(|| event.clone());
+        assert_eq!(event, receiver.recv().unwrap());
+
+        // Events aren't built without a sink.
+        Reporter::default().emit(|| panic!("no sink"));
+    }
+}
@@ -0,0 +1,151 @@
+//! Retry policies for failed requests.
+//!
+//! Connection errors, timeouts and server errors are usually transient,
+//! so the [`Crawler`](crate::crawler::Crawler) and
+//! [`Downloader`](crate::downloader::Downloader) can retry them with an
+//! exponential backoff. Other errors are returned straight away.
+
+use crate::error::{SecError, SecResult};
+use std::{future::Future, time::Duration};
+
+/// How failed requests are retried.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct RetryPolicy {
+    retries: u32,
+    backoff: Duration,
+    max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            retries: 0,
+            backoff: Duration::from_millis(500),
+            max_backoff: Duration::from_secs(30),
+        }
+    }
+}
+
+impl RetryPolicy {
+    /// Create a policy retrying a request up to the given number of times.
+    pub fn new(retries: u32) -> Self {
+        Self {
+            retries,
+            ..Self::default()
+        }
+    }
+
+    /// Create a policy which never retries.
+    pub fn never() -> Self {
+        Self::default()
+    }
+
+    /// Set the delay before the first retry, doubled for each following one.
+    pub fn backoff(mut self, delay: Duration) -> Self {
+        self.backoff = delay;
+        self
+    }
+
+    /// Set the longest delay between retries.
+    pub fn max_backoff(mut self, delay: Duration) -> Self {
+        self.max_backoff = delay;
+        self
+    }
+
+    /// The number of times a request may be retried.
+    pub fn retries(&self) -> u32 {
+        self.retries
+    }
+
+    /// The delay before the given retry, starting from 1.
+    pub fn delay(&self, retry: u32) -> Duration {
+        let factor = 2u32.saturating_pow(retry.saturating_sub(1));
+        self.backoff
+            .checked_mul(factor)
+            .map_or(self.max_backoff, |x| x.min(self.max_backoff))
+    }
+
+    /// Run an operation, retrying transient failures.
+    ///
+    /// ``on_retry`` is called with the attempt about to be made and the
+    /// error of the previous one.
+    pub(crate) async fn run<T, F, Fut>(
+        &self,
+        mut operation: F,
+        mut on_retry: impl FnMut(u32, &SecError),
+    ) -> SecResult<T>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = SecResult<T>>,
+    {
+        let mut attempt = 1;
+        loop {
+            match operation().await {
+                Err(e) if attempt <= self.retries && is_transient(&e) => {
+                    on_retry(attempt + 1, &e);
+                    tokio::time::delay_for(self.delay(attempt)).await;
+                    attempt += 1;
+                }
+                result => return result,
+            }
+        }
+    }
+}
+
+/// Check if an error is worth retrying.
+pub fn is_transient(error: &SecError) -> bool {
+    match error {
+        SecError::Reqwest(e) => {
+            e.is_timeout() || e.is_connect() || e.status().is_some_and(|x| x.is_server_error())
+        }
+        SecError::Timeout => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod retry_tests {
+    use super::*;
+    use crate::test_util::MockServer;
+    use std::cell::Cell;
+
+    #[test]
+    fn delays() {
+        let policy = RetryPolicy::new(5)
+            .backoff(Duration::from_millis(100))
+            .max_backoff(Duration::from_millis(300));
+        assert_eq!(Duration::from_millis(100), policy.delay(1));
+        assert_eq!(Duration::from_millis(200), policy.delay(2));
+        assert_eq!(Duration::from_millis(300), policy.delay(3));
+        assert_eq!(Duration::from_millis(300), policy.delay(40));
+    }
+
+    #[tokio::test]
+    async fn transient_errors() {
+        let server = MockServer::start();
+        let client = server.client();
+        let policy = RetryPolicy::new(2).backoff(Duration::from_millis(1));
+        let retries = Cell::new(0);
+
+        // Server errors are retried.
+        server.fail_next(2, 503);
+        let types = policy
+            .run(
+                || client.parse_types(),
+                |_, _| retries.set(retries.get() + 1),
+            )
+            .await;
+        assert!(types.is_ok());
+        assert_eq!(2, retries.get());
+
+        // Other errors aren't.
+        let papers = policy
+            .run(
+                || client.parse_papers("exampapers", 2019, "lc", 99),
+                |_, _| retries.set(retries.get() + 1),
+            )
+            .await;
+        assert!(matches!(papers, Err(SecError::NoMaterial)));
+        assert_eq!(2, retries.get());
+    }
+}
@@ -1,30 +1,84 @@
 //! The examination paper query metadata.
 
 use std::str::FromStr;
+use strum::{EnumProperty, IntoEnumIterator};
 use strum_macros::{Display, EnumIter, EnumProperty, EnumString};
 
 /// The different document types offered on the SEC website.
 /// Each type contains its name and ID that can be used to generate a query.
 #[derive(EnumProperty, EnumIter, Debug, Clone, PartialEq)]
 pub enum Type {
-    #[strum(props(name = "Exam_Papers", id = "exampapers"))]
+    #[strum(props(name = "Exam_Papers", id = "exampapers", label = "Exam Papers"))]
     ExamPaper,
-    #[strum(props(name = "Marking_Schemes", id = "markingschemes"))]
+    #[strum(props(
+        name = "Marking_Schemes",
+        id = "markingschemes",
+        label = "Marking Schemes"
+    ))]
     MarkingScheme,
+    /// A type offered by the website which isn't known to this library.
+    #[strum(disabled = "true")]
+    Unknown { id: String, label: String },
 }
 
 /// The different examinations offered on the SEC website.
 /// Each examination contains its name and ID that can be used to generate a query.
 #[derive(EnumProperty, EnumIter, Debug, Clone, PartialEq)]
 pub enum Examination {
-    #[strum(props(name = "Leaving_Certificate_Applied", id = "lb"))]
+    #[strum(props(
+        name = "Leaving_Certificate_Applied",
+        id = "lb",
+        label = "Leaving Certificate Applied"
+    ))]
     LeavingCertificateApplied,
-    #[strum(props(name = "Leaving_Certificate", id = "lc"))]
+    #[strum(props(name = "Leaving_Certificate", id = "lc", label = "Leaving Certificate"))]
     LeavingCertificate,
-    #[strum(props(name = "Junior_Certificate/Cycle", id = "jc"))]
+    #[strum(props(
+        name = "Junior_Certificate/Cycle",
+        id = "jc",
+        label = "Junior Certificate / Cycle"
+    ))]
     JuniorCertificate,
+    /// An examination offered by the website which isn't known to this library.
+    #[strum(disabled = "true")]
+    Unknown { id: String, label: String },
 }
 
+/// Implement ID and label lookups for enums with an ``Unknown`` variant.
+macro_rules! impl_id_lookup {
+    ($($ty:ident),*) => {$(
+        impl $ty {
+            /// Find the variant for an ID, falling back to ``Unknown``.
+            pub fn from_id(id: &str, label: &str) -> Self {
+                $ty::iter()
+                    .find(|x| x.get_str("id") == Some(id))
+                    .unwrap_or_else(|| $ty::Unknown {
+                        id: id.into(),
+                        label: label.into(),
+                    })
+            }
+
+            /// The ID used to generate a query.
+            pub fn id(&self) -> &str {
+                match self {
+                    $ty::Unknown { id, .. } => id,
+                    x => x.get_str("id").unwrap_or_default(),
+                }
+            }
+
+            /// The label shown on the website.
+            pub fn label(&self) -> &str {
+                match self {
+                    $ty::Unknown { label, .. } => label,
+                    x => x.get_str("label").unwrap_or_default(),
+                }
+            }
+        }
+    )*};
+}
+
+impl_id_lookup!(Type, Examination);
+
 /// The different examination languages offered on the SEC website.
 #[derive(EnumString, EnumIter, Display, Debug, Clone, PartialEq)]
 pub enum Language {
@@ -39,9 +93,12 @@ pub enum Language {
 impl From<String> for Language {
     fn from(raw_name: String) -> Self {
         let split_name: Vec<&str> = raw_name.split('(').collect();
-        match Language::from_str(split_name[1].trim_end_matches(')')) {
-            Ok(x) => x,
-            Err(_) => Language::NoLanguage,
+        match split_name.get(1) {
+            Some(x) => match Language::from_str(x.trim().trim_end_matches(')')) {
+                Ok(x) => x,
+                Err(_) => Language::NoLanguage,
+            },
+            None => Language::NoLanguage,
         }
     }
 }
@@ -80,10 +137,113 @@ impl From<String> for Level {
     }
 }
 
+/// The different examination sessions offered on the SEC website.
+///
+/// Since 2020 deferred and supplementary sittings have been held,
+/// their material is listed alongside the main session.
+#[derive(EnumString, EnumIter, Display, Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Session {
+    #[strum(serialize = "Main")]
+    Main,
+    #[strum(serialize = "Deferred")]
+    Deferred,
+    #[strum(serialize = "Supplementary")]
+    Supplementary,
+}
+
+/// Implementation for converting a string name parsed from a query into a session.
+impl From<String> for Session {
+    fn from(raw_name: String) -> Self {
+        let name = raw_name.to_lowercase();
+        if name.contains("deferred") {
+            Session::Deferred
+        } else if name.contains("supplementary") || name.contains("late sitting") {
+            Session::Supplementary
+        } else {
+            Session::Main
+        }
+    }
+}
+
+/// Serde support for the metadata and subject enums.
+///
+/// Values are represented by their SEC IDs (``"lc"``, ``"exampapers"``, ``3``)
+/// rather than their variant names, so serialized data stays stable
+/// and can be fed straight back into a query.
+#[cfg(feature = "serde")]
+mod serde_impls {
+    use super::*;
+    use crate::schema::subjects::Subject;
+    use serde::{de::Error, Deserialize, Deserializer, Serialize, Serializer};
+
+    /// Find the variant of an enum with the given ``id`` property.
+    fn find_by_id<T: EnumProperty + IntoEnumIterator>(id: &str) -> Option<T> {
+        T::iter().find(|x| x.get_str("id") == Some(id))
+    }
+
+    /// Implement serde using the ID of each variant.
+    ///
+    /// IDs which aren't known are kept as the ``Unknown`` variant.
+    macro_rules! impl_id_serde {
+        ($($ty:ident),*) => {$(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.serialize_str(self.id())
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let id = String::deserialize(deserializer)?;
+                    Ok($ty::from_id(&id, ""))
+                }
+            }
+        )*};
+    }
+
+    /// Implement serde using the ``Display`` and ``FromStr`` implementations.
+    macro_rules! impl_display_serde {
+        ($($ty:ident),*) => {$(
+            impl Serialize for $ty {
+                fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                    serializer.collect_str(self)
+                }
+            }
+
+            impl<'de> Deserialize<'de> for $ty {
+                fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                    let raw = String::deserialize(deserializer)?;
+                    $ty::from_str(&raw).map_err(D::Error::custom)
+                }
+            }
+        )*};
+    }
+
+    impl_id_serde!(Type, Examination);
+    impl_display_serde!(Language, Level, Session);
+
+    impl Serialize for Subject {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            match self.get_str("id").and_then(|x| x.parse().ok()) {
+                Some(id) => serializer.serialize_u32(id),
+                None => Err(serde::ser::Error::custom("subject has no id")),
+            }
+        }
+    }
+
+    impl<'de> Deserialize<'de> for Subject {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            let id = u32::deserialize(deserializer)?;
+            find_by_id(&id.to_string())
+                .ok_or_else(|| D::Error::custom(format!("unknown Subject id: {}", id)))
+        }
+    }
+}
+
 #[cfg(test)]
 mod metadata_tests {
     use super::*;
-    use strum::EnumProperty;
+    use crate::schema::subjects::Subject;
 
     #[test]
     fn parse_type() {
@@ -95,6 +255,17 @@ mod metadata_tests {
         assert_eq!("exampapers", paper_type.get_str("id").unwrap());
     }
 
+    #[test]
+    fn unknown_ids() {
+        assert_eq!(Type::MarkingScheme, Type::from_id("markingschemes", ""));
+        assert_eq!("Marking Schemes", Type::MarkingScheme.label());
+
+        let unknown = Examination::from_id("xx", "Some Examination");
+        assert_eq!("xx", unknown.id());
+        assert_eq!("Some Examination", unknown.label());
+        assert_eq!(3, Examination::iter().count());
+    }
+
     #[test]
     fn parse_language() {
         let language = Language::Irish;
@@ -109,6 +280,18 @@ mod metadata_tests {
         assert_eq!("Ordinary Level", level.to_string());
     }
 
+    #[test]
+    fn parse_name() {
+        let name = String::from("Mathematics / Higher Level / Paper 1 (EV)");
+        assert_eq!(Level::HigherLevel, Level::from(name.clone()));
+        assert_eq!(Language::English, Language::from(name.clone()));
+        assert_eq!(Session::Main, Session::from(name));
+
+        let name = String::from("Irish / Ordinary Level - Deferred");
+        assert_eq!(Language::NoLanguage, Language::from(name.clone()));
+        assert_eq!(Session::Deferred, Session::from(name));
+    }
+
     #[test]
     fn parse_examination() {
         let examination = Examination::LeavingCertificate;
@@ -118,4 +301,49 @@ mod metadata_tests {
         );
         assert_eq!("lc", examination.get_str("id").unwrap());
     }
+
+    #[test]
+    fn subject_properties() {
+        let subject = Subject::Mathematics;
+        assert_eq!("Mathematics", subject.get_str("name").unwrap());
+        assert_eq!("3", subject.get_str("id").unwrap());
+        assert_eq!(
+            "History – Later Modern",
+            Subject::History_LaterModern.get_str("label").unwrap()
+        );
+    }
+
+    #[cfg(feature = "serde")]
+    #[test]
+    fn serde_ids() {
+        assert_eq!(
+            "\"lc\"",
+            serde_json::to_string(&Examination::LeavingCertificate).unwrap()
+        );
+        assert_eq!(
+            "\"exampapers\"",
+            serde_json::to_string(&Type::ExamPaper).unwrap()
+        );
+        assert_eq!("3", serde_json::to_string(&Subject::Mathematics).unwrap());
+        assert_eq!(
+            "\"Higher Level\"",
+            serde_json::to_string(&Level::HigherLevel).unwrap()
+        );
+        assert_eq!(
+            Language::Irish,
+            serde_json::from_str::<Language>("\"IV\"").unwrap()
+        );
+        assert_eq!(
+            Type::MarkingScheme,
+            serde_json::from_str::<Type>("\"markingschemes\"").unwrap()
+        );
+        assert_eq!(
+            Examination::Unknown {
+                id: "xx".into(),
+                label: String::new()
+            },
+            serde_json::from_str::<Examination>("\"xx\"").unwrap()
+        );
+        assert!(serde_json::from_str::<Subject>("9999").is_err());
+    }
 }
@@ -3,7 +3,6 @@
 //! **NOTE**: All values in this schema have been reverse
 //! engineered. They could be subject to change in the future!
 
-use serde::{Serialize, Deserialize};
 use strum_macros::{EnumIter, EnumProperty};
 use resec_macros::make_schema;
 
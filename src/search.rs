@@ -0,0 +1,424 @@
+//! Full-text search over downloaded material.
+//!
+//! Text extracted with [`text`](crate::text) is indexed page by page
+//! with [tantivy](https://docs.rs/tantivy), and every hit links back to
+//! the [`Material`] and page it was found on. The index is stored on
+//! disk next to the mirror, so searching needs no network connection.
+//!
+//! # Usage:
+//!
+//! ```no_run
+//! use resec::{search::SearchIndex, text::MaterialText};
+//!
+//! # fn main() -> Result<(), resec::error::SecError> {
+//! # let downloads: Vec<resec::downloader::Download> = Vec::new();
+//! let mut index = SearchIndex::open("papers/.index")?;
+//! let (_, failures) = index.index_downloads(&downloads)?;
+//! for (path, error) in failures {
+//!     eprintln!("skipped {}: {}", path.display(), error);
+//! }
+//! index.commit()?;
+//!
+//! for hit in index.search("\"integration by parts\" AND subject_id:3", 20)? {
+//!     println!("{} {} page {}", hit.material.year, hit.material.name, hit.page);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    downloader::Download,
+    error::{SecError, SecResult},
+    material::{FileFormat, Material},
+    text::MaterialText,
+};
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tantivy::{
+    collector::TopDocs,
+    directory::MmapDirectory,
+    doc,
+    query::QueryParser,
+    schema::{Field, Schema, Value, INDEXED, STORED, STRING, TEXT},
+    snippet::SnippetGenerator,
+    Index, IndexReader, IndexWriter, ReloadPolicy, TantivyDocument, Term,
+};
+use url::Url;
+
+/// Memory used by the index writer before flushing to disk.
+const WRITER_MEMORY: usize = 50_000_000;
+
+/// A page matching a search query.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SearchHit {
+    /// The material the page belongs to.
+    pub material: Material,
+    /// Page number, starting from 1.
+    pub page: u32,
+    /// Relevance of the page to the query.
+    pub score: f32,
+    /// Text surrounding the match, with matched terms in ``<b>`` tags.
+    pub snippet: String,
+}
+
+/// The fields of each indexed page.
+#[derive(Debug, Clone, Copy)]
+struct Fields {
+    link: Field,
+    type_id: Field,
+    year: Field,
+    exam_id: Field,
+    subject_id: Field,
+    name: Field,
+    level: Field,
+    language: Field,
+    session: Field,
+    kind: Field,
+    format: Field,
+    extra: Field,
+    page: Field,
+    text: Field,
+}
+
+impl Fields {
+    /// Build the index schema.
+    fn schema() -> (Schema, Self) {
+        let mut builder = Schema::builder();
+        let fields = Self {
+            link: builder.add_text_field("link", STRING | STORED),
+            type_id: builder.add_text_field("type_id", STRING | STORED),
+            year: builder.add_u64_field("year", INDEXED | STORED),
+            exam_id: builder.add_text_field("exam_id", STRING | STORED),
+            subject_id: builder.add_u64_field("subject_id", INDEXED | STORED),
+            name: builder.add_text_field("name", TEXT | STORED),
+            level: builder.add_text_field("level", STRING | STORED),
+            language: builder.add_text_field("language", STRING | STORED),
+            session: builder.add_text_field("session", STRING | STORED),
+            kind: builder.add_text_field("kind", STRING | STORED),
+            format: builder.add_text_field("format", STRING | STORED),
+            extra: builder.add_text_field("extra", STORED),
+            page: builder.add_u64_field("page", INDEXED | STORED),
+            text: builder.add_text_field("text", TEXT | STORED),
+        };
+        (builder.build(), fields)
+    }
+}
+
+/// A full-text index of material, one document per page.
+pub struct SearchIndex {
+    index: Index,
+    writer: IndexWriter,
+    reader: IndexReader,
+    fields: Fields,
+}
+
+impl SearchIndex {
+    /// Open the index in the given directory, creating it if needed.
+    pub fn open(directory: impl AsRef<Path>) -> SecResult<Self> {
+        fs::create_dir_all(&directory)?;
+        let (schema, fields) = Fields::schema();
+        let directory = MmapDirectory::open(directory).map_err(tantivy::TantivyError::from)?;
+        Self::from_index(Index::open_or_create(directory, schema)?, fields)
+    }
+
+    /// Create an index held in memory.
+    pub fn in_memory() -> SecResult<Self> {
+        let (schema, fields) = Fields::schema();
+        Self::from_index(Index::create_in_ram(schema), fields)
+    }
+
+    fn from_index(index: Index, fields: Fields) -> SecResult<Self> {
+        Ok(Self {
+            writer: index.writer_with_num_threads(1, WRITER_MEMORY)?,
+            reader: index
+                .reader_builder()
+                .reload_policy(ReloadPolicy::Manual)
+                .try_into()?,
+            index,
+            fields,
+        })
+    }
+
+    /// Add the pages of a material, replacing any already indexed for its link.
+    ///
+    /// Changes are only visible to searches after [`commit`](Self::commit).
+    pub fn add(&mut self, text: &MaterialText) -> SecResult<()> {
+        let f = self.fields;
+        let material = &text.material;
+        self.writer
+            .delete_term(Term::from_field_text(f.link, material.link.as_str()));
+
+        for page in &text.pages {
+            self.writer.add_document(doc!(
+                f.link => material.link.as_str(),
+                f.type_id => material.type_id.as_str(),
+                f.year => u64::from(material.year),
+                f.exam_id => material.exam_id.as_str(),
+                f.subject_id => u64::from(material.subject_id),
+                f.name => material.name.as_str(),
+                f.level => material.level.to_string(),
+                f.language => material.language.to_string(),
+                f.session => material.session.to_string(),
+                f.kind => material.kind.to_string(),
+                f.format => material.format.to_string(),
+                f.extra => material.joined_extra(),
+                f.page => u64::from(page.page),
+                f.text => page.text.as_str(),
+            ))?;
+        }
+
+        Ok(())
+    }
+
+    /// Extract and add the text of a downloaded material.
+    pub fn index_download(&mut self, download: &Download) -> SecResult<()> {
+        self.add(&MaterialText::from_download(download)?)
+    }
+
+    /// Extract and add the text of every downloaded PDF.
+    ///
+    /// Material in other formats, such as aural recordings, is skipped.
+    /// A file whose text can't be extracted is skipped too, and returned
+    /// with the error alongside how many were indexed.
+    pub fn index_downloads(
+        &mut self,
+        downloads: &[Download],
+    ) -> SecResult<(usize, Vec<(PathBuf, SecError)>)> {
+        let mut count = 0;
+        let mut failures = Vec::new();
+        for download in downloads
+            .iter()
+            .filter(|x| x.material.format == FileFormat::Pdf)
+        {
+            match MaterialText::from_download(download) {
+                Ok(text) => {
+                    self.add(&text)?;
+                    count += 1;
+                }
+                Err(error) => failures.push((download.path.clone(), error)),
+            }
+        }
+        Ok((count, failures))
+    }
+
+    /// Write pending changes to the index and make them searchable.
+    pub fn commit(&mut self) -> SecResult<()> {
+        self.writer.commit()?;
+        self.reader.reload()?;
+        Ok(())
+    }
+
+    /// Number of pages in the index.
+    pub fn page_count(&self) -> u64 {
+        self.reader.searcher().num_docs()
+    }
+
+    /// Search the indexed pages, best matches first.
+    ///
+    /// The query uses the tantivy query syntax, matching the page text and
+    /// material name by default. Results can be narrowed using the other
+    /// fields, such as ``subject_id:3``, ``year:[2015 TO 2019]`` or
+    /// ``level:"Higher Level"``.
+    pub fn search(&self, query: &str, limit: usize) -> SecResult<Vec<SearchHit>> {
+        let f = self.fields;
+        let searcher = self.reader.searcher();
+        let query = QueryParser::for_index(&self.index, vec![f.text, f.name]).parse_query(query)?;
+        let snippets = SnippetGenerator::create(&searcher, &*query, f.text)?;
+
+        let mut hits = Vec::new();
+        for (score, address) in searcher.search(&query, &TopDocs::with_limit(limit))? {
+            let document: TantivyDocument = searcher.doc(address)?;
+            let (material, page) = self.material(&document)?;
+            hits.push(SearchHit {
+                material,
+                page,
+                score,
+                snippet: snippets.snippet_from_doc(&document).to_html(),
+            });
+        }
+
+        Ok(hits)
+    }
+
+    /// Rebuild the material and page number of an indexed page.
+    fn material(&self, document: &TantivyDocument) -> SecResult<(Material, u32)> {
+        let f = self.fields;
+        let text = |field| {
+            document
+                .get_first(field)
+                .and_then(|x| x.as_str())
+                .unwrap_or_default()
+        };
+        let number = |field| document.get_first(field).and_then(|x| x.as_u64());
+
+        let link =
+            Url::parse(text(f.link)).map_err(|_| SecError::InvalidLink(text(f.link).into()))?;
+        let mut material = Material::new(
+            text(f.type_id),
+            number(f.year).unwrap_or_default() as u32,
+            text(f.exam_id),
+            number(f.subject_id).unwrap_or_default() as u32,
+            text(f.name).to_string(),
+            link,
+        );
+
+        // Restore the metadata as it was indexed, it may have been refined.
+        if let Ok(x) = FromStr::from_str(text(f.level)) {
+            material.level = x;
+        }
+        if let Ok(x) = FromStr::from_str(text(f.language)) {
+            material.language = x;
+        }
+        if let Ok(x) = FromStr::from_str(text(f.session)) {
+            material.session = x;
+        }
+        if let Ok(x) = FromStr::from_str(text(f.kind)) {
+            material.kind = x;
+        }
+        if let Ok(x) = FromStr::from_str(text(f.format)) {
+            material.format = x;
+        }
+        material.extra = Material::split_extra(text(f.extra));
+
+        Ok((material, number(f.page).unwrap_or_default() as u32))
+    }
+}
+
+#[cfg(test)]
+mod search_tests {
+    use super::*;
+    use crate::{schema::metadata::Level, test_util::sample_pdf, text::PageText};
+
+    fn material(subject_id: u32, name: &str, file: &str) -> Material {
+        Material::new(
+            "exampapers",
+            2019,
+            "lc",
+            subject_id,
+            name.into(),
+            Url::parse("https://www.examinations.ie/archive/exampapers/2019/")
+                .unwrap()
+                .join(file)
+                .unwrap(),
+        )
+    }
+
+    fn pages(material: Material, pages: &[&str]) -> MaterialText {
+        MaterialText {
+            material,
+            pages: pages
+                .iter()
+                .zip(1..)
+                .map(|(text, page)| PageText {
+                    page,
+                    text: text.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn search_pages() -> SecResult<()> {
+        let mut index = SearchIndex::in_memory()?;
+        index.add(&pages(
+            material(
+                3,
+                "Mathematics / Higher Level / Paper 1 (EV)",
+                "LC003ALP100EV.pdf",
+            ),
+            &[
+                "Question 1 Solve the quadratic equation.",
+                "Question 2 Use integration by parts to evaluate the integral.",
+            ],
+        ))?;
+        index.add(&pages(
+            material(
+                3,
+                "Mathematics / Ordinary Level / Paper 1 (EV)",
+                "LC003GLP100EV.pdf",
+            ),
+            &["Question 1 Find the derivative using the product rule."],
+        ))?;
+        index.commit()?;
+        assert_eq!(3, index.page_count());
+
+        let hits = index.search("\"integration by parts\"", 10)?;
+        assert_eq!(1, hits.len());
+        assert_eq!(2, hits[0].page);
+        assert_eq!(Level::HigherLevel, hits[0].material.level);
+        assert_eq!(3, hits[0].material.subject_id);
+        assert!(hits[0].snippet.contains("<b>integration</b>"));
+
+        // Narrow the results using the material fields.
+        let hits = index.search("question AND level:\"Ordinary Level\"", 10)?;
+        assert_eq!(1, hits.len());
+        assert!(index.search("question AND subject_id:2", 10)?.is_empty());
+        assert!(index.search("question AND", 10).is_err());
+        Ok(())
+    }
+
+    #[test]
+    fn replace_material() -> SecResult<()> {
+        let mut index = SearchIndex::in_memory()?;
+        let paper = material(22, "Chemistry / Higher Level (EV)", "LC022ALP000EV.pdf");
+        index.add(&pages(paper.clone(), &["Titration", "Electrolysis"]))?;
+        index.commit()?;
+        index.add(&pages(paper, &["Titration"]))?;
+        index.commit()?;
+
+        assert_eq!(1, index.page_count());
+        assert!(index.search("electrolysis", 10)?.is_empty());
+        Ok(())
+    }
+
+    #[test]
+    fn index_mirror() -> SecResult<()> {
+        let directory = std::env::temp_dir().join(format!("resec-search-{}", std::process::id()));
+        let path = directory.join("LC003ALP100EV.pdf");
+        fs::create_dir_all(&directory)?;
+        fs::write(
+            &path,
+            sample_pdf(&[
+                "Question 1\nIntegrate by parts.",
+                "Question 2\nProbability.",
+            ]),
+        )?;
+
+        let download = Download {
+            material: material(
+                3,
+                "Mathematics / Higher Level / Paper 1 (EV)",
+                "LC003ALP100EV.pdf",
+            ),
+            path,
+            size: 0,
+            sha256: String::new(),
+            validators: Default::default(),
+        };
+        let mut audio = download.clone();
+        audio.material.format = FileFormat::Mp3;
+        let mut broken = download.clone();
+        broken.path = directory.join("LC003ALP200EV.pdf");
+        fs::write(&broken.path, b"not a pdf")?;
+
+        // Reopening the index should keep the pages on disk.
+        {
+            let mut index = SearchIndex::open(directory.join("index"))?;
+            let (count, failures) = index.index_downloads(&[broken.clone(), download, audio])?;
+            assert_eq!(1, count);
+            assert_eq!(1, failures.len());
+            assert_eq!(broken.path, failures[0].0);
+            index.commit()?;
+        }
+        let index = SearchIndex::open(directory.join("index"))?;
+        let hits = index.search("probability", 10)?;
+        assert_eq!(1, hits.len());
+        assert_eq!(2, hits[0].page);
+
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+}
@@ -0,0 +1,591 @@
+//! Segmentation of examination papers into questions.
+//!
+//! The extracted text of a paper is split into numbered questions,
+//! parts and sub-parts such as ``Q1 (a)(i)``, each with the pages it
+//! covers. Segments of a paper can then be linked to the matching
+//! segments of its marking scheme.
+//!
+//! # Usage:
+//!
+//! ```no_run
+//! use resec::{segment::SegmentTree, text::MaterialText};
+//!
+//! # use resec::downloader::Download;
+//! # async fn run(paper: Download, scheme: Download) -> Result<(), resec::error::SecError> {
+//! let mut tree = SegmentTree::from_text(&MaterialText::from_download(&paper)?);
+//! tree.link_scheme(&SegmentTree::from_text(&MaterialText::from_download(&scheme)?));
+//!
+//! if let Some(segment) = tree.find("2(b)(i)") {
+//!     println!("pages {}-{}: {}", segment.start_page, segment.end_page, segment.text);
+//! }
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::{
+    material::{Material, MaterialKind},
+    text::MaterialText,
+};
+
+#[cfg(feature = "serde")]
+use crate::error::SecResult;
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The depth of a segment in a paper.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SegmentLevel {
+    /// A numbered question, such as ``Question 1``.
+    Question,
+    /// A lettered part of a question, such as ``(a)``.
+    Part,
+    /// A numbered sub-part of a part, such as ``(i)``.
+    Subpart,
+}
+
+/// The segment of a marking scheme matching a segment of a paper.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SchemeLink {
+    /// ID of the segment in the marking scheme.
+    pub id: String,
+    /// First page of the segment in the marking scheme.
+    pub start_page: u32,
+    /// Last page of the segment in the marking scheme.
+    pub end_page: u32,
+}
+
+/// A question, part or sub-part of a paper.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Segment {
+    /// Full ID of the segment, such as ``1(a)(i)``.
+    pub id: String,
+    /// Label of the segment within its parent, such as ``i``.
+    pub label: String,
+    /// Depth of the segment.
+    pub level: SegmentLevel,
+    /// First page of the segment, including its children.
+    pub start_page: u32,
+    /// Last page of the segment, including its children.
+    pub end_page: u32,
+    /// Text of the segment, excluding its children.
+    pub text: String,
+    /// The matching segment of the marking scheme, if linked.
+    pub scheme: Option<SchemeLink>,
+    /// Parts or sub-parts of the segment, in order.
+    pub children: Vec<Segment>,
+}
+
+impl Segment {
+    /// Create an empty segment starting on the given page.
+    fn new(parent: Option<&Segment>, label: String, level: SegmentLevel, page: u32) -> Self {
+        let id = match parent {
+            Some(parent) => format!("{}({})", parent.id, label),
+            None => label.clone(),
+        };
+        Self {
+            id,
+            label,
+            level,
+            start_page: page,
+            end_page: page,
+            text: String::new(),
+            scheme: None,
+            children: Vec::new(),
+        }
+    }
+
+    /// Every segment in this subtree, depth first.
+    fn walk<'a>(&'a self, out: &mut Vec<&'a Segment>) {
+        out.push(self);
+        for child in &self.children {
+            child.walk(out);
+        }
+    }
+
+    /// Link this subtree to the matching segments of a scheme.
+    fn link(&mut self, scheme: &SegmentTree, inherited: Option<&SchemeLink>) {
+        self.scheme = scheme
+            .find(&self.id)
+            .map(|x| SchemeLink {
+                id: x.id.clone(),
+                start_page: x.start_page,
+                end_page: x.end_page,
+            })
+            .or_else(|| inherited.cloned());
+
+        let link = self.scheme.clone();
+        for child in &mut self.children {
+            child.link(scheme, link.as_ref());
+        }
+    }
+}
+
+/// The segments of a material.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct SegmentTree {
+    /// The material that was segmented.
+    pub material: Material,
+    /// The marking scheme the segments are linked to, if any.
+    pub scheme: Option<Material>,
+    /// Questions of the material, in order.
+    pub questions: Vec<Segment>,
+}
+
+impl SegmentTree {
+    /// Split the text of a material into questions, parts and sub-parts.
+    ///
+    /// Any text before the first question, such as the instructions
+    /// on the cover page, isn't part of a segment.
+    pub fn from_text(text: &MaterialText) -> Self {
+        let mut segmenter = Segmenter::default();
+        for page in &text.pages {
+            for line in page.text.lines() {
+                segmenter.line(line, page.page);
+            }
+        }
+
+        Self {
+            material: text.material.clone(),
+            scheme: None,
+            questions: segmenter.questions,
+        }
+    }
+
+    /// Find a segment by its full ID, such as ``2(b)(i)``.
+    pub fn find(&self, id: &str) -> Option<&Segment> {
+        self.segments().into_iter().find(|x| x.id == id)
+    }
+
+    /// Every segment in the tree, depth first.
+    pub fn segments(&self) -> Vec<&Segment> {
+        let mut segments = Vec::new();
+        for question in &self.questions {
+            question.walk(&mut segments);
+        }
+        segments
+    }
+
+    /// Link every segment to the matching segment of a marking scheme.
+    ///
+    /// Schemes often only mark whole parts, so segments without an exact
+    /// match are linked to the closest matching parent instead.
+    pub fn link_scheme(&mut self, scheme: &SegmentTree) {
+        self.scheme = Some(scheme.material.clone());
+        for question in &mut self.questions {
+            question.link(scheme, None);
+        }
+    }
+
+    /// Render the tree as JSON.
+    #[cfg(feature = "serde")]
+    pub fn to_json(&self) -> SecResult<String> {
+        Ok(serde_json::to_string_pretty(self)?)
+    }
+}
+
+/// Find the marking scheme paired with a paper.
+///
+/// The scheme must be for the same year, examination, subject, level and
+/// session. Schemes in the same language and for the same paper number
+/// are preferred, since schemes are often only published in English.
+pub fn paired_scheme<'a>(paper: &Material, schemes: &'a [Material]) -> Option<&'a Material> {
+    schemes
+        .iter()
+        .filter(|x| {
+            x.kind == MaterialKind::MarkingScheme
+                && x.year == paper.year
+                && x.exam_id == paper.exam_id
+                && x.subject_id == paper.subject_id
+                && x.level == paper.level
+                && x.session == paper.session
+        })
+        .max_by_key(|x| {
+            (
+                paper_number(&x.name) == paper_number(&paper.name),
+                x.language == paper.language,
+            )
+        })
+}
+
+/// Find the paper number in a material name, such as ``Paper 2``.
+fn paper_number(name: &str) -> Option<u32> {
+    let name = name.to_lowercase();
+    let (_, rest) = name.split_once("paper")?;
+    rest.trim_start()
+        .split(|x: char| !x.is_ascii_digit())
+        .next()?
+        .parse()
+        .ok()
+}
+
+/// A marker found at the start of a line.
+#[derive(Debug, Clone, PartialEq)]
+enum Marker {
+    /// ``Question 1`` or ``Q1``.
+    Question(u32),
+    /// ``1.`` at the start of a line, only trusted if it is the next question.
+    Numbered(u32),
+    /// ``(a)`` or ``(iv)``.
+    Label(String),
+}
+
+/// Parse the markers at the start of a line, returning the remaining text.
+fn markers(line: &str) -> (Vec<Marker>, &str) {
+    let mut markers = Vec::new();
+    let mut rest = line.trim_start();
+
+    while let Some((marker, remaining)) = marker(rest, markers.is_empty()) {
+        markers.push(marker);
+        rest = remaining.trim_start_matches(['.', ':']).trim_start();
+    }
+
+    (markers, rest)
+}
+
+/// Parse a single marker, numbered questions are only accepted first on a line.
+fn marker(text: &str, first: bool) -> Option<(Marker, &str)> {
+    if let Some((number, rest)) = question(text) {
+        Some((Marker::Question(number), rest))
+    } else if let Some((label, rest)) = label(text) {
+        Some((Marker::Label(label), rest))
+    } else if first {
+        numbered(text).map(|(number, rest)| (Marker::Numbered(number), rest))
+    } else {
+        None
+    }
+}
+
+/// Split leading digits from a string.
+fn digits(text: &str) -> Option<(u32, &str)> {
+    let end = text
+        .find(|x: char| !x.is_ascii_digit())
+        .unwrap_or(text.len());
+    Some((text[..end].parse().ok()?, &text[end..]))
+}
+
+/// Parse ``Question 1``, ``Q1`` or ``Q. 1``.
+fn question(text: &str) -> Option<(u32, &str)> {
+    let prefix = text.get(..8).filter(|x| x.eq_ignore_ascii_case("question"));
+    let rest = if let Some(rest) = prefix.and_then(|_| text.get(8..)) {
+        rest
+    } else if text.starts_with(['Q', 'q']) {
+        text[1..].trim_start_matches('.')
+    } else {
+        return None;
+    };
+    digits(rest.trim_start())
+}
+
+/// Parse ``(a)`` or ``(iv)``.
+fn label(text: &str) -> Option<(String, &str)> {
+    let rest = text.strip_prefix('(')?;
+    let (label, rest) = rest.split_once(')')?;
+    match !label.is_empty() && label.len() <= 4 && label.chars().all(|x| x.is_ascii_lowercase()) {
+        true => Some((label.to_string(), rest)),
+        false => None,
+    }
+}
+
+/// Parse ``1.`` followed by a space.
+fn numbered(text: &str) -> Option<(u32, &str)> {
+    let (number, rest) = digits(text)?;
+    match rest.strip_prefix('.') {
+        Some(rest) if rest.starts_with(char::is_whitespace) => Some((number, rest)),
+        _ => None,
+    }
+}
+
+/// Check if a label is a roman numeral, such as ``iv``.
+fn is_roman(label: &str) -> bool {
+    label.chars().all(|x| matches!(x, 'i' | 'v' | 'x'))
+}
+
+/// State of the segmentation, line by line.
+#[derive(Debug, Default)]
+struct Segmenter {
+    questions: Vec<Segment>,
+    /// Depth of the segment currently receiving text, 0 before the first question.
+    depth: usize,
+}
+
+impl Segmenter {
+    /// The last segment at the given depth, if any.
+    fn current(&mut self, depth: usize) -> Option<&mut Segment> {
+        if depth == 0 {
+            return None;
+        }
+        let mut segment = self.questions.last_mut()?;
+        for _ in 1..depth {
+            segment = segment.children.last_mut()?;
+        }
+        Some(segment)
+    }
+
+    /// Start a new segment at the given depth.
+    fn start(&mut self, label: String, depth: usize, page: u32) {
+        let level = match depth {
+            1 => SegmentLevel::Question,
+            2 => SegmentLevel::Part,
+            _ => SegmentLevel::Subpart,
+        };
+        match self.current(depth - 1) {
+            Some(parent) => {
+                let segment = Segment::new(Some(parent), label, level, page);
+                parent.children.push(segment);
+            }
+            None => self.questions.push(Segment::new(None, label, level, page)),
+        }
+        self.depth = depth;
+    }
+
+    /// Handle a single line of text.
+    fn line(&mut self, line: &str, page: u32) {
+        let (found, rest) = markers(line);
+        for marker in found {
+            match marker {
+                Marker::Question(number) => self.start(number.to_string(), 1, page),
+                Marker::Numbered(number) => {
+                    // A numbered line is only a question if it follows on from the last.
+                    let next = self
+                        .questions
+                        .last()
+                        .and_then(|x| x.label.parse::<u32>().ok())
+                        .map_or(1, |x| x + 1);
+                    match number == next {
+                        true => self.start(number.to_string(), 1, page),
+                        false => return self.text(line, page),
+                    }
+                }
+                Marker::Label(label) => match self.label_depth(&label) {
+                    Some(depth) => self.start(label, depth, page),
+                    None => return self.text(line, page),
+                },
+            }
+        }
+        self.text(rest, page);
+    }
+
+    /// Decide if a label starts a part or a sub-part, if either.
+    fn label_depth(&mut self, label: &str) -> Option<usize> {
+        let has_question = !self.questions.is_empty();
+        let part = match self.current(2) {
+            Some(part) => (part.label.clone(), part.children.is_empty()),
+            // Labels directly under a question are parts.
+            None if has_question => return Some(2),
+            None => return None,
+        };
+
+        // ``(i)`` starts the sub-parts of a part, unless it follows part ``(h)``.
+        let subpart = is_roman(label) && (!part.1 || (label == "i" && part.0 != "h"));
+        match (subpart, label.len()) {
+            (true, _) => Some(3),
+            (false, 1) => Some(2),
+            (false, _) => None,
+        }
+    }
+
+    /// Add text to the current segment, extending the pages of its parents.
+    fn text(&mut self, text: &str, page: u32) {
+        extend(&mut self.questions, self.depth, page);
+
+        let text = text.trim();
+        if let Some(segment) = self.current(self.depth) {
+            if !text.is_empty() {
+                if !segment.text.is_empty() {
+                    segment.text.push('\n');
+                }
+                segment.text.push_str(text);
+            }
+        }
+    }
+}
+
+/// Extend the last page of the last segments down to the given depth.
+fn extend(segments: &mut [Segment], depth: usize, page: u32) {
+    if let Some(segment) = segments.last_mut().filter(|_| depth > 0) {
+        segment.end_page = segment.end_page.max(page);
+        extend(&mut segment.children, depth - 1, page);
+    }
+}
+
+#[cfg(test)]
+mod segment_tests {
+    use super::*;
+    use crate::{
+        schema::metadata::Language,
+        test_util::sample_pdf,
+        text::{extract_pages_from_mem, PageText},
+    };
+    use url::Url;
+
+    fn material(type_id: &str, name: &str) -> Material {
+        Material::new(
+            type_id,
+            2019,
+            "lc",
+            3,
+            name.into(),
+            Url::parse("https://www.examinations.ie/archive/")
+                .unwrap()
+                .join(&format!("{}.pdf", name.len()))
+                .unwrap(),
+        )
+    }
+
+    fn text(material: Material, pages: &[&str]) -> MaterialText {
+        MaterialText {
+            material,
+            pages: pages
+                .iter()
+                .zip(1..)
+                .map(|(text, page)| PageText {
+                    page,
+                    text: text.to_string(),
+                })
+                .collect(),
+        }
+    }
+
+    #[test]
+    fn parse_markers() {
+        assert_eq!(
+            (
+                vec![
+                    Marker::Question(2),
+                    Marker::Label("b".into()),
+                    Marker::Label("ii".into())
+                ],
+                "Find x."
+            ),
+            markers("Question 2 (b) (ii) Find x.")
+        );
+        assert_eq!((vec![Marker::Question(10)], ""), markers("Q.10"));
+        assert_eq!((vec![Marker::Numbered(3)], "Solve."), markers("3. Solve."));
+        assert_eq!((Vec::new(), "Quadratic (a)"), markers("Quadratic (a)"));
+        assert_eq!((Vec::new(), "3.5 metres"), markers("3.5 metres"));
+        assert_eq!(
+            (Vec::new(), "Ríomhaí an toradh"),
+            markers("Ríomhaí an toradh")
+        );
+        assert_eq!(
+            (vec![Marker::Question(4)], "Ceist"),
+            markers("Question 4 Ceist")
+        );
+        assert_eq!(
+            Some(2),
+            paper_number("Mathematics / Higher Level / Paper 2 (EV)")
+        );
+    }
+
+    #[test]
+    fn segment_paper() {
+        let tree = SegmentTree::from_text(&text(
+            material("exampapers", "Mathematics / Higher Level / Paper 1 (EV)"),
+            &[
+                "Instructions\nAnswer all questions.\nQuestion 1\n(a) Solve the equation.\n(b) (i) Expand the brackets.",
+                "(ii) Factorise the result.\n(c) Sketch the graph.\n2. Use integration by parts.\n(a) Evaluate the integral.",
+                "(h) Find the area.\n(i) Find the volume.\n(j) 7. Not a question.",
+            ],
+        ));
+
+        let ids: Vec<&str> = tree.segments().iter().map(|x| x.id.as_str()).collect();
+        assert_eq!(
+            vec![
+                "1", "1(a)", "1(b)", "1(b)(i)", "1(b)(ii)", "1(c)", "2", "2(a)", "2(h)", "2(i)",
+                "2(j)"
+            ],
+            ids
+        );
+
+        let question = tree.find("1").unwrap();
+        assert_eq!((1, 2), (question.start_page, question.end_page));
+        assert_eq!("", question.text);
+
+        let part = tree.find("1(b)").unwrap();
+        assert_eq!(SegmentLevel::Part, part.level);
+        assert_eq!((1, 2), (part.start_page, part.end_page));
+
+        let subpart = tree.find("1(b)(ii)").unwrap();
+        assert_eq!(SegmentLevel::Subpart, subpart.level);
+        assert_eq!("ii", subpart.label);
+        assert_eq!("Factorise the result.", subpart.text);
+
+        assert_eq!("Use integration by parts.", tree.find("2").unwrap().text);
+        assert_eq!("7. Not a question.", tree.find("2(j)").unwrap().text);
+    }
+
+    #[test]
+    fn link_scheme() {
+        let mut paper = SegmentTree::from_text(&text(
+            material("exampapers", "Mathematics / Higher Level / Paper 1 (IV)"),
+            &["Q1 (a) (i) Solve.\n(ii) Check.\n(b) Prove.\nQ2 Sketch."],
+        ));
+        let scheme = SegmentTree::from_text(&text(
+            material("markingschemes", "Mathematics / Higher Level"),
+            &["Q1", "(a) 10 marks", "(b) 5 marks"],
+        ));
+        paper.link_scheme(&scheme);
+
+        assert_eq!(Some(&scheme.material), paper.scheme.as_ref());
+        let link = |id| paper.find(id).unwrap().scheme.clone();
+        assert_eq!(
+            Some(SchemeLink {
+                id: "1(a)".into(),
+                start_page: 2,
+                end_page: 2
+            }),
+            link("1(a)(ii)")
+        );
+        assert_eq!(Some(3), link("1(b)").map(|x| x.start_page));
+        assert_eq!(None, link("2"));
+    }
+
+    #[test]
+    fn pair_scheme() {
+        let paper = material("exampapers", "Mathematics / Higher Level / Paper 2 (IV)");
+        let schemes = vec![
+            material(
+                "markingschemes",
+                "Mathematics / Ordinary Level / Paper 2 (EV)",
+            ),
+            material(
+                "markingschemes",
+                "Mathematics / Higher Level / Paper 1 (IV)",
+            ),
+            material(
+                "markingschemes",
+                "Mathematics / Higher Level / Paper 2 (EV)",
+            ),
+        ];
+        assert_eq!(Some(&schemes[2]), paired_scheme(&paper, &schemes));
+        assert_eq!(Language::English, schemes[2].language);
+        assert_eq!(None, paired_scheme(&paper, &schemes[..1]));
+    }
+
+    #[test]
+    fn segment_pdf() -> crate::error::SecResult<()> {
+        let pdf = sample_pdf(&[
+            "Question 1\n(a) Integrate by parts.",
+            "Question 2\nProbability.",
+        ]);
+        let tree = SegmentTree::from_text(&MaterialText {
+            material: material("exampapers", "Mathematics / Higher Level / Paper 1 (EV)"),
+            pages: extract_pages_from_mem(&pdf)?,
+        });
+
+        assert!(tree
+            .find("1(a)")
+            .unwrap()
+            .text
+            .contains("Integrate by parts"));
+        assert_eq!(2, tree.find("2").unwrap().start_page);
+
+        #[cfg(feature = "serde")]
+        assert_eq!(tree, serde_json::from_str::<SegmentTree>(&tree.to_json()?)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,664 @@
+//! A local REST API exposing the examination archive.
+//!
+//! Each endpoint is backed by the [`parser`](crate::parser) functions of a
+//! [`SecClient`], so a client with a response cache and rate limiter can
+//! serve many users without multiplying the load on the website.
+//!
+//! | Endpoint | Parameters |
+//! |----------|------------|
+//! | ``/types`` | |
+//! | ``/years`` | ``type`` |
+//! | ``/exams`` | ``type``, ``year`` |
+//! | ``/subjects`` | ``type``, ``year``, ``exam`` |
+//! | ``/papers`` | ``type``, ``year``, ``exam``, ``subject``, optional ``filter`` |
+//! | ``/download/{id}`` | |
+//!
+//! Material listed by ``/papers`` is given an ``id`` which can then be
+//! passed to ``/download/{id}`` to fetch the file through the server. The
+//! ID encodes the material's link, so it stays valid across restarts and
+//! between servers querying the same archive. Downloaded files are kept
+//! on disk and streamed from there, so repeat downloads are served without
+//! querying the website until the client's cache TTL passes. The
+//! listed material can be narrowed with a [`filter`](crate::filter)
+//! expression, such as ``filter=level:higher lang:EV``.
+
+use crate::{
+    client::SecClient,
+    consts::ARCHIVE_PATH,
+    downloader::Downloader,
+    error::{SecError, SecResult},
+    filter::MaterialFilter,
+    material::{FileFormat, Material},
+    schema::metadata::Type,
+};
+use futures_util::stream::{self, Stream};
+use hyper::{
+    header::CONTENT_TYPE,
+    service::{make_service_fn, service_fn},
+    Body, Request, Response, Server,
+};
+use serde_json::{json, Value};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    env, fs, io,
+    net::TcpListener,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::{fs::File, io::AsyncReadExt, sync::Mutex};
+use url::{form_urlencoded, Url};
+
+/// Largest material downloaded through the API by default, in bytes.
+const DEFAULT_MAX_DOWNLOAD: u64 = 64 * 1024 * 1024;
+
+/// How long downloads are kept when the client has no response cache.
+const DEFAULT_DOWNLOAD_TTL: Duration = Duration::from_secs(3600);
+
+/// Size of the chunks files are streamed in.
+const CHUNK_SIZE: usize = 64 * 1024;
+
+/// A response from the API.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ApiResponse {
+    /// HTTP status code.
+    pub status: u16,
+    /// Value of the ``Content-Type`` header.
+    pub content_type: String,
+    /// Response body.
+    pub body: ApiBody,
+}
+
+/// The body of a response from the API.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ApiBody {
+    /// Bytes held in memory.
+    Bytes(Vec<u8>),
+    /// A file streamed from disk.
+    File(PathBuf),
+}
+
+impl ApiResponse {
+    /// Create a JSON response.
+    fn json(status: u16, value: Value) -> Self {
+        Self {
+            status,
+            content_type: "application/json".into(),
+            body: ApiBody::Bytes(value.to_string().into_bytes()),
+        }
+    }
+
+    /// Create a JSON error response.
+    fn error(status: u16, message: impl ToString) -> Self {
+        Self::json(status, json!({ "error": message.to_string() }))
+    }
+}
+
+impl From<SecError> for ApiResponse {
+    fn from(error: SecError) -> Self {
+        match error {
+            SecError::NoMaterial => Self::error(404, error),
+            SecError::ParseInt(_) | SecError::Filter(_) => Self::error(400, error),
+            _ => Self::error(502, error),
+        }
+    }
+}
+
+/// The API request handler.
+#[derive(Debug)]
+pub struct Api {
+    client: SecClient,
+    downloader: Downloader,
+    download_dir: PathBuf,
+    downloading: Mutex<()>,
+}
+
+impl Api {
+    /// Create a new API querying the archive using the given client.
+    pub fn new(client: SecClient) -> Self {
+        let download_dir = env::temp_dir().join("resec-server");
+        Self {
+            downloader: Downloader::new(&download_dir)
+                .client(client.clone())
+                .max_size(DEFAULT_MAX_DOWNLOAD),
+            client,
+            download_dir,
+            downloading: Mutex::new(()),
+        }
+    }
+
+    /// Keep downloaded material in the given directory.
+    ///
+    /// Defaults to ``resec-server`` in the system's temporary directory.
+    pub fn download_dir(mut self, directory: impl Into<PathBuf>) -> Self {
+        self.download_dir = directory.into();
+        self
+    }
+
+    /// Refuse to download material larger than the given size in bytes.
+    ///
+    /// Defaults to 64 MiB.
+    pub fn max_download_size(mut self, bytes: u64) -> Self {
+        self.downloader = self.downloader.max_size(bytes);
+        self
+    }
+
+    /// The ID used to download a material through the API.
+    pub fn material_id(material: &Material) -> String {
+        base64::encode_config(material.link.as_str(), base64::URL_SAFE_NO_PAD)
+    }
+
+    /// The link of a material ID, if it belongs to the archive.
+    ///
+    /// Only files under the archive's material path can be downloaded,
+    /// not any other page of the website.
+    fn material_link(&self, id: &str) -> Option<Url> {
+        let link = base64::decode_config(id, base64::URL_SAFE_NO_PAD).ok()?;
+        let link = Url::parse(std::str::from_utf8(&link).ok()?).ok()?;
+        match link.origin() == self.client.base_url().origin()
+            && link.path().starts_with(ARCHIVE_PATH)
+        {
+            true => Some(link),
+            false => None,
+        }
+    }
+
+    /// The file a material link is kept in once downloaded.
+    fn download_path(&self, link: &Url) -> PathBuf {
+        self.download_dir.join(link.path().trim_start_matches('/'))
+    }
+
+    /// Check if a downloaded file is still fresh.
+    fn is_fresh(&self, path: &Path) -> bool {
+        let ttl = self
+            .client
+            .response_cache()
+            .map_or(DEFAULT_DOWNLOAD_TTL, |x| x.ttl());
+        fs::metadata(path)
+            .and_then(|x| x.modified())
+            .ok()
+            .and_then(|x| SystemTime::now().duration_since(x).ok())
+            .is_some_and(|age| age < ttl)
+    }
+
+    /// Handle a request for the given path and query string.
+    pub async fn handle(&self, method: &str, path: &str, query: Option<&str>) -> ApiResponse {
+        if method != "GET" {
+            return ApiResponse::error(405, "only GET requests are supported");
+        }
+
+        let params: HashMap<String, String> =
+            form_urlencoded::parse(query.unwrap_or("").as_bytes())
+                .into_owned()
+                .collect();
+
+        let result = match path.trim_end_matches('/') {
+            "/types" => self.types().await,
+            "/years" => self.years(&params).await,
+            "/exams" => self.exams(&params).await,
+            "/subjects" => self.subjects(&params).await,
+            "/papers" => self.papers(&params).await,
+            x if x.starts_with("/download/") => {
+                return self.download(&x["/download/".len()..]).await;
+            }
+            _ => return ApiResponse::error(404, "unknown endpoint"),
+        };
+
+        match result {
+            Ok(value) => ApiResponse::json(200, value),
+            Err(ApiError::Missing(name)) => {
+                ApiResponse::error(400, format!("missing or invalid parameter: {}", name))
+            }
+            Err(ApiError::Failed(error)) => error.into(),
+        }
+    }
+
+    async fn types(&self) -> Result<Value, ApiError> {
+        let types = self.client.parse_types_typed().await?;
+        Ok(types
+            .iter()
+            .map(|x| json!({ "id": x.id(), "label": x.label() }))
+            .collect())
+    }
+
+    async fn years(&self, params: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let paper_type = Type::from_id(param(params, "type")?, "");
+        let years = self.client.available_years(&paper_type).await?;
+        Ok(years.iter().map(|x| x.0).collect())
+    }
+
+    async fn exams(&self, params: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let exams = self
+            .client
+            .parse_exams_typed(param(params, "type")?, number(params, "year")?)
+            .await?;
+        Ok(exams
+            .iter()
+            .map(|x| json!({ "id": x.id(), "label": x.label() }))
+            .collect())
+    }
+
+    async fn subjects(&self, params: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let mut subjects: Vec<(u32, String)> = self
+            .client
+            .parse_subjects(
+                param(params, "type")?,
+                number(params, "year")?,
+                param(params, "exam")?,
+            )
+            .await?
+            .into_iter()
+            .collect();
+        subjects.sort();
+        Ok(subjects
+            .iter()
+            .map(|(id, name)| json!({ "id": id, "name": name }))
+            .collect())
+    }
+
+    async fn papers(&self, params: &HashMap<String, String>) -> Result<Value, ApiError> {
+        let filter: MaterialFilter = match params.get("filter") {
+            Some(x) => x.parse()?,
+            None => MaterialFilter::new(),
+        };
+        let papers = self
+            .client
+            .parse_papers(
+                param(params, "type")?,
+                number(params, "year")?,
+                param(params, "exam")?,
+                number(params, "subject")?,
+            )
+            .await?;
+
+        let mut materials = Vec::new();
+        for material in papers.materials.iter().filter(|x| filter.matches(x)) {
+            let mut value = serde_json::to_value(material).map_err(SecError::from)?;
+            value["id"] = json!(Self::material_id(material));
+            materials.push(value);
+        }
+
+        Ok(json!({
+            "materials": materials,
+            "warnings": serde_json::to_value(&papers.warnings).map_err(SecError::from)?,
+        }))
+    }
+
+    async fn download(&self, id: &str) -> ApiResponse {
+        let link = match self.material_link(id) {
+            Some(link) => link,
+            None => return ApiResponse::error(404, "unknown material, list it with /papers first"),
+        };
+
+        match self.fetch(link).await {
+            Ok(response) => response,
+            Err(error) => error.into(),
+        }
+    }
+
+    /// Fetch a material, downloading it unless a fresh copy is on disk.
+    ///
+    /// Downloads are made one at a time, so concurrent requests for the
+    /// same material only query the website once.
+    async fn fetch(&self, link: Url) -> SecResult<ApiResponse> {
+        let path = self.download_path(&link);
+        if !self.is_fresh(&path) {
+            let _downloading = self.downloading.lock().await;
+            if !self.is_fresh(&path) {
+                let material = link_material(link.clone());
+                if self
+                    .downloader
+                    .download_to(&material, path.clone())
+                    .await?
+                    .is_none()
+                {
+                    return Ok(ApiResponse::error(413, "material is over the size limit"));
+                }
+            }
+        }
+
+        Ok(ApiResponse {
+            status: 200,
+            content_type: FileFormat::from_link(&link).content_type().into(),
+            body: ApiBody::File(path),
+        })
+    }
+}
+
+/// Why a request couldn't be answered.
+enum ApiError {
+    /// A parameter was missing or invalid.
+    Missing(&'static str),
+    /// The query failed.
+    Failed(SecError),
+}
+
+impl From<SecError> for ApiError {
+    fn from(error: SecError) -> Self {
+        ApiError::Failed(error)
+    }
+}
+
+/// Fetch a required parameter.
+fn param<'a>(params: &'a HashMap<String, String>, name: &'static str) -> Result<&'a str, ApiError> {
+    params
+        .get(name)
+        .map(|x| x.as_str())
+        .filter(|x| !x.is_empty())
+        .ok_or(ApiError::Missing(name))
+}
+
+/// Fetch a required numeric parameter.
+fn number(params: &HashMap<String, String>, name: &'static str) -> Result<u32, ApiError> {
+    param(params, name)?
+        .parse()
+        .map_err(|_| ApiError::Missing(name))
+}
+
+/// Describe a material from its link, such as
+/// ``/archive/exampapers/2019/LC003ALP000EV.pdf``.
+fn link_material(link: Url) -> Material {
+    let segments: Vec<String> = link
+        .path_segments()
+        .map(|x| x.map(String::from).collect())
+        .unwrap_or_default();
+    let type_id = segments.get(1).cloned().unwrap_or_default();
+    let year = segments.get(2).and_then(|x| x.parse().ok()).unwrap_or(0);
+    let name = segments.last().cloned().unwrap_or_default();
+    Material::new(&type_id, year, "", 0, name, link)
+}
+
+/// Read a file in chunks, to stream it as a response body.
+fn file_chunks(file: File) -> impl Stream<Item = io::Result<Vec<u8>>> {
+    stream::unfold(Some(file), |file| async move {
+        let mut file = file?;
+        let mut chunk = vec![0; CHUNK_SIZE];
+        match file.read(&mut chunk).await {
+            Ok(0) => None,
+            Ok(read) => {
+                chunk.truncate(read);
+                Some((Ok(chunk), Some(file)))
+            }
+            Err(e) => Some((Err(e), None)),
+        }
+    })
+}
+
+/// Convert a response from the API into an HTTP response.
+async fn into_response(reply: ApiResponse) -> Response<Body> {
+    let (status, content_type, body) = match reply.body {
+        ApiBody::Bytes(bytes) => (reply.status, reply.content_type, Body::from(bytes)),
+        ApiBody::File(path) => match File::open(&path).await {
+            Ok(file) => (
+                reply.status,
+                reply.content_type,
+                Body::wrap_stream(file_chunks(file)),
+            ),
+            Err(e) => (
+                500,
+                "application/json".into(),
+                Body::from(json!({ "error": e.to_string() }).to_string()),
+            ),
+        },
+    };
+
+    Response::builder()
+        .status(status)
+        .header(CONTENT_TYPE, content_type)
+        .body(body)
+        .expect("response is valid")
+}
+
+/// Serve the API on a listener until the process exits.
+pub async fn serve(api: Api, listener: TcpListener) -> SecResult<()> {
+    let api = Arc::new(api);
+    let service = make_service_fn(move |_| {
+        let api = api.clone();
+        async move {
+            Ok::<_, Infallible>(service_fn(move |request: Request<Body>| {
+                let api = api.clone();
+                async move {
+                    let reply = api
+                        .handle(
+                            request.method().as_str(),
+                            request.uri().path(),
+                            request.uri().query(),
+                        )
+                        .await;
+                    Ok::<_, Infallible>(into_response(reply).await)
+                }
+            }))
+        }
+    });
+
+    Server::from_tcp(listener)?.serve(service).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod server_tests {
+    use super::*;
+    use crate::{cache::ResponseCache, test_util::MockServer};
+    use std::time::Duration;
+
+    fn body(response: &ApiResponse) -> Value {
+        match &response.body {
+            ApiBody::Bytes(bytes) => serde_json::from_slice(bytes).unwrap(),
+            ApiBody::File(path) => panic!("expected JSON, got {}", path.display()),
+        }
+    }
+
+    fn file(response: &ApiResponse) -> Vec<u8> {
+        match &response.body {
+            ApiBody::File(path) => fs::read(path).unwrap(),
+            ApiBody::Bytes(_) => panic!("expected a file, got {:?}", response),
+        }
+    }
+
+    fn download_dir(name: &str) -> PathBuf {
+        let directory =
+            env::temp_dir().join(format!("resec-server-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&directory);
+        directory
+    }
+
+    #[tokio::test]
+    async fn endpoints() {
+        let server = MockServer::start();
+        let directory = download_dir("endpoints");
+        let api = Api::new(server.client()).download_dir(&directory);
+
+        let types = api.handle("GET", "/types", None).await;
+        assert_eq!(200, types.status);
+        assert_eq!("exampapers", body(&types)[0]["id"]);
+
+        let years = body(
+            &api.handle("GET", "/years", Some("type=markingschemes"))
+                .await,
+        );
+        assert_eq!(json!(2001), years[0]);
+
+        let exams = body(
+            &api.handle("GET", "/exams", Some("type=exampapers&year=2019"))
+                .await,
+        );
+        assert_eq!("Leaving Certificate", exams[1]["label"]);
+
+        let subjects = api
+            .handle(
+                "GET",
+                "/subjects",
+                Some("type=exampapers&year=1995&exam=lc"),
+            )
+            .await;
+        assert_eq!(json!({ "id": 1, "name": "Irish" }), body(&subjects)[0]);
+
+        let papers = api
+            .handle(
+                "GET",
+                "/papers",
+                Some("type=exampapers&year=2019&exam=lc&subject=3"),
+            )
+            .await;
+        let papers = body(&papers);
+        assert_eq!(3, papers["materials"].as_array().unwrap().len());
+        assert_eq!(3, papers["materials"][0]["subject_id"]);
+
+        // The listing can be filtered.
+        let higher = api
+            .handle(
+                "GET",
+                "/papers",
+                Some("type=exampapers&year=2019&exam=lc&subject=3&filter=level:higher+lang:EV"),
+            )
+            .await;
+        let higher = body(&higher);
+        assert_eq!(1, higher["materials"].as_array().unwrap().len());
+        assert_eq!(papers["materials"][0], higher["materials"][0]);
+
+        // Material listed by /papers can be downloaded.
+        server.set_file(
+            "/archive/exampapers/2019/LC003ALP000EV.pdf",
+            b"%PDF".to_vec(),
+        );
+        let id = papers["materials"][0]["id"].as_str().unwrap();
+        let download = api.handle("GET", &format!("/download/{}", id), None).await;
+        assert_eq!(200, download.status);
+        assert_eq!("application/pdf", download.content_type);
+        assert_eq!(b"%PDF".to_vec(), file(&download));
+
+        // So can it after a restart, without listing it again or
+        // querying the website.
+        let queries = server.request_count();
+        let restarted = Api::new(server.client()).download_dir(&directory);
+        let download = restarted
+            .handle("GET", &format!("/download/{}", id), None)
+            .await;
+        assert_eq!(b"%PDF".to_vec(), file(&download));
+        assert_eq!(queries, server.request_count());
+        fs::remove_dir_all(directory).unwrap();
+    }
+
+    #[tokio::test]
+    async fn errors() {
+        let server = MockServer::start();
+        let directory = download_dir("errors");
+        let api = Api::new(server.client())
+            .download_dir(&directory)
+            .max_download_size(100);
+
+        assert_eq!(404, api.handle("GET", "/nothing", None).await.status);
+        assert_eq!(405, api.handle("POST", "/types", None).await.status);
+        assert_eq!(404, api.handle("GET", "/download/abcd", None).await.status);
+
+        // Links outside the archive can't be downloaded.
+        let mut material = Material::new(
+            "exampapers",
+            2019,
+            "lc",
+            3,
+            "Mathematics / Higher Level / Paper 1 (EV)".into(),
+            Url::parse("http://example.com/archive/exampapers/2019/LC003ALP100EV.pdf").unwrap(),
+        );
+        let path = format!("/download/{}", Api::material_id(&material));
+        assert_eq!(404, api.handle("GET", &path, None).await.status);
+        material.link = server.url().join(material.link.path()).unwrap();
+        let path = format!("/download/{}", Api::material_id(&material));
+        assert_eq!(200, api.handle("GET", &path, None).await.status);
+
+        // Neither can other pages of the website.
+        server.set_file("/private/notes.pdf", b"%PDF".to_vec());
+        material.link = server.url().join("/private/notes.pdf").unwrap();
+        let path = format!("/download/{}", Api::material_id(&material));
+        assert_eq!(404, api.handle("GET", &path, None).await.status);
+
+        // Nor material over the size limit.
+        server.set_file("/archive/exampapers/2019/large.pdf", vec![0; 200]);
+        material.link = server
+            .url()
+            .join("/archive/exampapers/2019/large.pdf")
+            .unwrap();
+        let path = format!("/download/{}", Api::material_id(&material));
+        assert_eq!(413, api.handle("GET", &path, None).await.status);
+        fs::remove_dir_all(directory).unwrap();
+
+        let missing = api.handle("GET", "/exams", Some("type=exampapers")).await;
+        assert_eq!(400, missing.status);
+        assert_eq!(
+            "missing or invalid parameter: year",
+            body(&missing)["error"]
+        );
+
+        let none = api
+            .handle(
+                "GET",
+                "/papers",
+                Some("type=exampapers&year=2019&exam=lc&subject=99"),
+            )
+            .await;
+        assert_eq!(404, none.status);
+
+        let invalid = api
+            .handle(
+                "GET",
+                "/papers",
+                Some("type=exampapers&year=2019&exam=lc&subject=3&filter=level:highest"),
+            )
+            .await;
+        assert_eq!(400, invalid.status);
+
+        server.fail_next(1, 503);
+        assert_eq!(502, api.handle("GET", "/types", None).await.status);
+    }
+
+    #[tokio::test]
+    async fn cached_queries() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server
+            .client()
+            .cache(ResponseCache::new(Duration::from_secs(60)));
+        let directory = download_dir("cached");
+        let api = Api::new(client).download_dir(&directory);
+
+        for _ in 0..3 {
+            api.handle("GET", "/exams", Some("type=exampapers&year=2019"))
+                .await;
+        }
+        assert_eq!(1, server.request_count());
+
+        // Serve the API over HTTP.
+        let listener = TcpListener::bind("127.0.0.1:0")?;
+        let address = listener.local_addr()?;
+        tokio::spawn(serve(api, listener));
+
+        let types = reqwest::get(&format!("http://{}/types", address))
+            .await?
+            .text()
+            .await?;
+        let types: Value = serde_json::from_str(&types)?;
+        assert_eq!("Exam Papers", types[0]["label"]);
+
+        // Downloads are streamed from disk.
+        server.set_file(
+            "/archive/exampapers/2019/LC003ALP000EV.pdf",
+            vec![9; 200_000],
+        );
+        let link = server
+            .url()
+            .join("/archive/exampapers/2019/LC003ALP000EV.pdf")
+            .unwrap();
+        let material = Material::new("exampapers", 2019, "lc", 3, "Paper (EV)".into(), link);
+        let download = reqwest::get(&format!(
+            "http://{}/download/{}",
+            address,
+            Api::material_id(&material)
+        ))
+        .await?
+        .bytes()
+        .await?;
+        assert_eq!(vec![9; 200_000], download.to_vec());
+        fs::remove_dir_all(directory)?;
+        Ok(())
+    }
+}
@@ -12,9 +12,89 @@
 //! These stages follow each other and require the
 //! previous one to be active in order to work.
 
-use crate::{consts::EXAM_URL, error::SecResult};
-use reqwest::Client;
-use std::collections::HashMap;
+use crate::{
+    client::SecClient,
+    error::{SecError, SecResult},
+};
+use select::{
+    document::Document,
+    node::Node,
+    predicate::{Attr, Name},
+};
+use std::{collections::HashMap, time::Instant};
+use tracing::{debug, field, info_span, Instrument, Span};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// Name of the terms and conditions checkbox field.
+const AGREE_FIELD: &str = "MaterialArchive__noTable__cbv__AgreeCheck";
+
+/// The terms and conditions shown on the first stage.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct Terms {
+    /// Text of the terms and conditions.
+    pub text: String,
+    /// Version of the terms and conditions, if stated.
+    pub version: Option<String>,
+}
+
+impl Terms {
+    /// Parse the terms and conditions from a stage one page.
+    ///
+    /// Returns ``None`` if the page isn't the terms and conditions stage.
+    pub fn from_document(document: &Document) -> Option<Self> {
+        // The agreement checkbox only exists on the first stage.
+        let checkbox = document.find(Attr("name", AGREE_FIELD)).next()?;
+
+        // Use the form surrounding the checkbox, or the whole page if there isn't one.
+        let form = ancestor(checkbox, "form");
+        let paragraphs: Vec<String> = match form {
+            Some(form) => form.find(Name("p")).map(|x| x.text()).collect(),
+            None => {
+                debug!("terms checkbox is outside a form, reading the whole page");
+                document.find(Name("p")).map(|x| x.text()).collect()
+            }
+        };
+        let text = paragraphs
+            .iter()
+            .map(|x| x.split_whitespace().collect::<Vec<_>>().join(" "))
+            .filter(|x| !x.is_empty())
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        Some(Self {
+            version: terms_version(&text),
+            text,
+        })
+    }
+}
+
+/// Find the closest ancestor of a node with the given name.
+fn ancestor<'a>(node: Node<'a>, name: &str) -> Option<Node<'a>> {
+    let mut current = node.parent();
+    while let Some(x) = current {
+        if x.name() == Some(name) {
+            return Some(x);
+        }
+        current = x.parent();
+    }
+    None
+}
+
+/// Find the version stated in the terms, e.g. "Version 2.1".
+fn terms_version(text: &str) -> Option<String> {
+    let words: Vec<&str> = text.split_whitespace().collect();
+    words
+        .windows(2)
+        .find(|x| x[0].trim_end_matches(':').eq_ignore_ascii_case("version"))
+        .map(|x| {
+            x[1].trim_end_matches(|c: char| !c.is_alphanumeric())
+                .to_string()
+        })
+        .filter(|x| !x.is_empty())
+}
 
 /// Main stage builder.
 #[derive(Default)]
@@ -33,8 +113,7 @@ impl StageBuilder {
         // Check the given flag.
         if flag {
             // Agree to the terms and conditions.
-            self.query_form
-                .insert("MaterialArchive__noTable__cbv__AgreeCheck", "Y".to_string());
+            self.query_form.insert(AGREE_FIELD, "Y".to_string());
             self.query_form
                 .insert("MaterialArchive__noTable__cbh__AgreeCheck", "N".to_string());
         }
@@ -104,87 +183,227 @@ impl StageBuilder {
     }
 
     /// Finish building the stage and query using built object.
+    ///
+    /// If the terms were agreed to but the website returned the
+    /// terms and conditions page, [`SecError::TermsNotAccepted`] is returned.
     pub async fn query(&self) -> SecResult<String> {
-        // Prepare the reqwest client.
-        let client = Client::new();
+        self.query_with(&SecClient::new()).await
+    }
+
+    /// Finish building the stage and query using the given client.
+    ///
+    /// Pages are served from the client's cache when possible. Each query
+    /// runs in a ``stage_query`` span recording the selected options,
+    /// and the latency, status and size of the response.
+    pub async fn query_with(&self, client: &SecClient) -> SecResult<String> {
+        let option = |name: &str| {
+            self.query_form
+                .get(format!("MaterialArchive__noTable__sbv__{}", name).as_str())
+                .map(String::as_str)
+                .unwrap_or_default()
+        };
+        let span = info_span!(
+            "stage_query",
+            paper_type = option("ViewType"),
+            year = option("YearSelect"),
+            exam = option("ExaminationSelect"),
+            subject = option("SubjectSelect"),
+            cached = field::Empty,
+            status = field::Empty,
+            bytes = field::Empty,
+            latency_ms = field::Empty,
+        );
+
+        self.fetch(client, &span).instrument(span.clone()).await
+    }
+
+    /// Fetch the page for the query, recording the response in the span.
+    async fn fetch(&self, client: &SecClient, span: &Span) -> SecResult<String> {
+        // Check if the page has already been fetched.
+        let key = self.cache_key(client);
+        if let Some(html) = client.response_cache().and_then(|x| x.get(&key)) {
+            span.record("cached", true);
+            span.record("bytes", html.len());
+            return Ok(html);
+        }
+        span.record("cached", false);
 
         // Post the details using the generated form body.
-        let response = client.post(EXAM_URL).form(&self.query_form).send().await?;
-        Ok(response.text().await?)
+        client.throttle().await;
+        let started = Instant::now();
+        let response = client
+            .read(
+                client
+                    .http()
+                    .post(client.base_url().clone())
+                    .form(&self.query_form)
+                    .send(),
+            )
+            .await?;
+        span.record("status", response.status().as_u16());
+        let html = client.read(response.error_for_status()?.text()).await?;
+        span.record("bytes", html.len());
+        span.record("latency_ms", started.elapsed().as_millis() as u64);
+
+        // Check if the query bounced back to the first stage.
+        if self.query_form.contains_key(AGREE_FIELD) && is_terms_page(&html) {
+            return Err(SecError::TermsNotAccepted);
+        }
+
+        if let Some(cache) = client.response_cache() {
+            cache.insert(key, html.clone());
+        }
+        Ok(html)
     }
+
+    /// Check if the page for the query is in the client's cache.
+    pub(crate) fn is_cached(&self, client: &SecClient) -> bool {
+        client
+            .response_cache()
+            .is_some_and(|x| x.get(&self.cache_key(client)).is_some())
+    }
+
+    /// Generate a key for the query, independent of the field order.
+    fn cache_key(&self, client: &SecClient) -> String {
+        let mut fields: Vec<String> = self
+            .query_form
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect();
+        fields.sort();
+        format!("{}?{}", client.base_url(), fields.join("&"))
+    }
+}
+
+/// Check if a page is the terms and conditions stage.
+fn is_terms_page(html: &str) -> bool {
+    let document = Document::from(html);
+    Terms::from_document(&document).is_some()
+        && document
+            .find(Attr("name", "MaterialArchive__noTable__sbv__ViewType"))
+            .next()
+            .is_none()
 }
 
 #[cfg(test)]
 mod stages_tests {
     use super::*;
-    use std::fs::File;
-    use std::io::Write;
+    use crate::test_util::MockServer;
+    use std::{
+        collections::BTreeMap,
+        fmt,
+        sync::{Arc, Mutex},
+    };
+    use tracing::{
+        field::{Field, Visit},
+        span::{Attributes, Id, Record},
+        Event, Metadata, Subscriber,
+    };
+
+    const TERMS_HTML: &str = r#"<html><body><form method="post">
+        <p>Terms and Conditions   Version 2.1.</p>
+        <p>Material may be reproduced for educational purposes.</p>
+        <input type="checkbox" name="MaterialArchive__noTable__cbv__AgreeCheck" value="Y">
+    </form></body></html>"#;
+
+    #[test]
+    fn terms_parsing() {
+        let terms = Terms::from_document(&Document::from(TERMS_HTML)).unwrap();
+        assert_eq!(Some("2.1".into()), terms.version);
+        assert_eq!(
+            "Terms and Conditions Version 2.1.\nMaterial may be reproduced for educational purposes.",
+            terms.text
+        );
+
+        assert!(is_terms_page(TERMS_HTML));
+        assert!(!is_terms_page(
+            r#"<select name="MaterialArchive__noTable__sbv__ViewType"></select>"#
+        ));
+    }
+
+    /// Check if a page contains the given select field.
+    fn has_field(html: &str, field: &str) -> bool {
+        Document::from(html)
+            .find(Attr("name", field))
+            .next()
+            .is_some()
+    }
 
     #[tokio::test]
     async fn stage_one() -> SecResult<()> {
-        // Attempt to get HTML.
-        let html = StageBuilder::new().agree_flag(false).query().await?;
+        let server = MockServer::start();
 
-        // Open up a file and write the html.
-        let mut file = File::create("stage_one.html")?;
-        file.write_all(html.as_bytes())?;
+        // Attempt to get HTML.
+        let html = StageBuilder::new()
+            .agree_flag(false)
+            .query_with(&server.client())
+            .await?;
 
+        assert!(is_terms_page(&html));
         Ok(())
     }
 
     #[tokio::test]
     async fn stage_two() -> SecResult<()> {
+        let server = MockServer::start();
+
         // Attempt to get HTML.
         let html = StageBuilder::new()
             .agree_flag(true)
             .paper_type("exampapers")
-            .query()
+            .query_with(&server.client())
             .await?;
 
-        // Open up a file and write the html.
-        let mut file = File::create("stage_two.html")?;
-        file.write_all(html.as_bytes())?;
-
+        assert!(has_field(
+            &html,
+            "MaterialArchive__noTable__sbv__YearSelect"
+        ));
         Ok(())
     }
 
     #[tokio::test]
     async fn stage_three() -> SecResult<()> {
+        let server = MockServer::start();
+
         // Attempt to get HTML.
         let html = StageBuilder::new()
             .agree_flag(true)
             .paper_type("exampapers")
             .year(2019)
-            .query()
+            .query_with(&server.client())
             .await?;
 
-        // Open up a file and write the html.
-        let mut file = File::create("stage_three.html")?;
-        file.write_all(html.as_bytes())?;
-
+        assert!(has_field(
+            &html,
+            "MaterialArchive__noTable__sbv__ExaminationSelect"
+        ));
         Ok(())
     }
 
     #[tokio::test]
     async fn stage_four() -> SecResult<()> {
+        let server = MockServer::start();
+
         // Attempt to get HTML.
         let html = StageBuilder::new()
             .agree_flag(true)
             .paper_type("exampapers")
             .year(2019)
             .examination("lc")
-            .query()
+            .query_with(&server.client())
             .await?;
 
-        // Open up a file and write the html.
-        let mut file = File::create("stage_four.html")?;
-        file.write_all(html.as_bytes())?;
-
+        assert!(has_field(
+            &html,
+            "MaterialArchive__noTable__sbv__SubjectSelect"
+        ));
         Ok(())
     }
 
     #[tokio::test]
     async fn stage_five() -> SecResult<()> {
+        let server = MockServer::start();
+
         // Attempt to get HTML.
         let html = StageBuilder::new()
             .agree_flag(true)
@@ -192,13 +411,79 @@ mod stages_tests {
             .year(2019)
             .examination("lc")
             .subject(1)
-            .query()
+            .query_with(&server.client())
             .await?;
 
-        // Open up a file and write the html.
-        let mut file = File::create("stage_five.html")?;
-        file.write_all(html.as_bytes())?;
+        assert!(html.contains("materialbody"));
+        Ok(())
+    }
+
+    /// Name and fields of a recorded span.
+    type SpanRecord = (String, BTreeMap<String, String>);
+
+    /// A subscriber recording the name and fields of every span.
+    #[derive(Clone, Default)]
+    struct Recorder(Arc<Mutex<Vec<SpanRecord>>>);
+
+    struct Fields<'a>(&'a mut BTreeMap<String, String>);
+
+    impl Visit for Fields<'_> {
+        fn record_str(&mut self, field: &Field, value: &str) {
+            self.0.insert(field.name().into(), value.into());
+        }
+
+        fn record_debug(&mut self, field: &Field, value: &dyn fmt::Debug) {
+            self.0.insert(field.name().into(), format!("{:?}", value));
+        }
+    }
+
+    impl Subscriber for Recorder {
+        fn enabled(&self, _: &Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &Attributes<'_>) -> Id {
+            let mut fields = BTreeMap::new();
+            span.record(&mut Fields(&mut fields));
+            let mut spans = self.0.lock().unwrap();
+            spans.push((span.metadata().name().into(), fields));
+            Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, span: &Id, values: &Record<'_>) {
+            let mut spans = self.0.lock().unwrap();
+            values.record(&mut Fields(&mut spans[span.into_u64() as usize - 1].1));
+        }
+
+        fn record_follows_from(&self, _: &Id, _: &Id) {}
+        fn event(&self, _: &Event<'_>) {}
+        fn enter(&self, _: &Id) {}
+        fn exit(&self, _: &Id) {}
+    }
+
+    #[tokio::test]
+    async fn query_span() -> SecResult<()> {
+        let recorder = Recorder::default();
+        let _guard = tracing::subscriber::set_default(recorder.clone());
+        let server = MockServer::start();
+
+        StageBuilder::new()
+            .agree_flag(true)
+            .paper_type("exampapers")
+            .year(2019)
+            .query_with(&server.client())
+            .await?;
 
+        let spans = recorder.0.lock().unwrap();
+        let (name, fields) = &spans[0];
+        assert_eq!("stage_query", name);
+        assert_eq!("exampapers", fields["paper_type"]);
+        assert_eq!("2019", fields["year"]);
+        assert_eq!("", fields["exam"]);
+        assert_eq!("200", fields["status"]);
+        assert_eq!("false", fields["cached"]);
+        assert!(fields["bytes"].parse::<usize>().unwrap() > 0);
+        assert!(fields.contains_key("latency_ms"));
         Ok(())
     }
 }
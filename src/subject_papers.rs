@@ -0,0 +1,264 @@
+//! Every paper and marking scheme of a subject across the years.
+//!
+//! Collecting a subject's material means querying each year offered for
+//! each paper type. [`papers_for_subject`] does the fan-out using the
+//! [`parser`](crate::parser) functions, and merges the results by year.
+
+use crate::{
+    client::SecClient,
+    error::{SecError, SecResult},
+    material::{Material, Papers},
+    schema::{
+        metadata::{Examination, Type},
+        subjects::Subject,
+    },
+    years::{fallback_years, Year, YearRange},
+};
+use futures_util::stream::{self, StreamExt};
+use std::collections::BTreeMap;
+use strum::{EnumProperty, IntoEnumIterator};
+use tracing::{debug, warn};
+
+/// The material of a subject offered in a year.
+#[derive(Debug, Clone, PartialEq)]
+pub struct YearPapers {
+    /// Year of the examination.
+    pub year: Year,
+    /// Exam papers followed by marking schemes.
+    pub papers: Papers,
+}
+
+/// A year whose material couldn't be listed.
+#[derive(Debug)]
+pub struct YearError {
+    /// Year of the examination.
+    pub year: Year,
+    /// The paper type whose query failed.
+    pub paper_type: Type,
+    /// Why the query failed.
+    pub error: SecError,
+}
+
+/// The material of a subject across a range of years.
+#[derive(Debug)]
+pub struct SubjectPapers {
+    /// The subject queried.
+    pub subject: Subject,
+    /// The examination queried.
+    pub examination: Examination,
+    /// Years offering material, oldest first.
+    pub years: Vec<YearPapers>,
+    /// Queries which failed, oldest first.
+    pub errors: Vec<YearError>,
+}
+
+impl SubjectPapers {
+    /// The material offered in a year.
+    pub fn year(&self, year: impl Into<Year>) -> Option<&Papers> {
+        let year = year.into();
+        self.years
+            .iter()
+            .find(|x| x.year == year)
+            .map(|x| &x.papers)
+    }
+
+    /// Every material found, oldest first.
+    pub fn materials(&self) -> impl Iterator<Item = &Material> {
+        self.years.iter().flat_map(|x| x.papers.materials.iter())
+    }
+}
+
+/// Collect every exam paper and marking scheme of a subject.
+///
+/// ```no_run
+/// # use resec::{prelude::*, subject_papers::papers_for_subject};
+/// # async fn run() -> Result<(), resec::error::SecError> {
+/// let chemistry = papers_for_subject(
+///     Subject::Chemistry,
+///     Examination::LeavingCertificate,
+///     YearRange::from(2015..=2019),
+///     |x: &Material| x.level == Level::HigherLevel,
+/// )
+/// .await?;
+/// for material in chemistry.materials() {
+///     println!("{} {}", material.year, material.name);
+/// }
+/// # Ok(())
+/// # }
+/// ```
+pub async fn papers_for_subject(
+    subject: Subject,
+    examination: Examination,
+    years: YearRange,
+    filter: impl Fn(&Material) -> bool,
+) -> SecResult<SubjectPapers> {
+    SecClient::new()
+        .papers_for_subject(subject, examination, years, filter)
+        .await
+}
+
+impl SecClient {
+    /// Collect every exam paper and marking scheme of a subject.
+    ///
+    /// Each year offered for each paper type within the range is queried,
+    /// up to the client's [`max_in_flight`](Self::max_in_flight) limit at
+    /// once, keeping only material accepted by the filter. A year without
+    /// material for the subject is left out, and a failed query is kept
+    /// as an error without failing the other years.
+    pub async fn papers_for_subject(
+        &self,
+        subject: Subject,
+        examination: Examination,
+        years: YearRange,
+        filter: impl Fn(&Material) -> bool,
+    ) -> SecResult<SubjectPapers> {
+        let subject_id: u32 = subject
+            .get_str("id")
+            .ok_or(SecError::Value("subject ID"))?
+            .parse()?;
+
+        // Find the years offered for each paper type.
+        let mut queries = Vec::new();
+        for paper_type in Type::iter() {
+            let offered = match self.available_years(&paper_type).await {
+                Ok(x) => x,
+                Err(e) => {
+                    warn!(paper_type = paper_type.id(), error = %e, "could not list years");
+                    fallback_years(&paper_type)
+                }
+            };
+            queries.extend(
+                offered
+                    .into_iter()
+                    .filter(|x| years.contains(*x))
+                    .map(|x| (x, paper_type.clone())),
+            );
+        }
+        queries.sort_by_key(|(year, _)| *year);
+
+        // Query the material of every year, merging the paper types.
+        let exam_id = examination.id();
+        let results: Vec<_> = stream::iter(queries)
+            .map(|(year, paper_type)| async move {
+                let result = self
+                    .parse_papers(paper_type.id(), year.0, exam_id, subject_id)
+                    .await;
+                (year, paper_type, result)
+            })
+            .buffered(self.in_flight_limit())
+            .collect()
+            .await;
+
+        let mut by_year: BTreeMap<Year, Papers> = BTreeMap::new();
+        let mut errors = Vec::new();
+        for (year, paper_type, result) in results {
+            match result {
+                Ok(mut papers) => {
+                    papers.materials.retain(|x| filter(x));
+                    by_year.entry(year).or_default().extend(Some(papers));
+                }
+                Err(SecError::NoMaterial) => {
+                    debug!(%year, paper_type = paper_type.id(), "no material offered");
+                }
+                Err(error) => errors.push(YearError {
+                    year,
+                    paper_type,
+                    error,
+                }),
+            }
+        }
+
+        Ok(SubjectPapers {
+            subject,
+            examination,
+            years: by_year
+                .into_iter()
+                .filter(|(_, papers)| !papers.materials.is_empty())
+                .map(|(year, papers)| YearPapers { year, papers })
+                .collect(),
+            errors,
+        })
+    }
+}
+
+#[cfg(test)]
+mod subject_papers_tests {
+    use super::*;
+    use crate::{material::MaterialKind, schema::metadata::Level, test_util::MockServer};
+
+    #[tokio::test]
+    async fn merged_years() -> SecResult<()> {
+        let server = MockServer::start();
+        server.set_delay(std::time::Duration::from_millis(20));
+        let output = server
+            .client()
+            .max_in_flight(2)
+            .papers_for_subject(
+                Subject::Chemistry,
+                Examination::LeavingCertificate,
+                YearRange::from(1999..=2002),
+                |x: &Material| x.level == Level::HigherLevel,
+            )
+            .await?;
+
+        // Marking schemes start in 2001.
+        let years: Vec<u32> = output.years.iter().map(|x| x.year.0).collect();
+        assert_eq!(vec![1999, 2000, 2001, 2002], years);
+        assert!(output
+            .year(2000)
+            .unwrap()
+            .materials
+            .iter()
+            .all(|x| x.kind == MaterialKind::Paper));
+        let kinds: Vec<MaterialKind> = output
+            .year(2001)
+            .unwrap()
+            .materials
+            .iter()
+            .map(|x| x.kind)
+            .collect();
+        assert_eq!(
+            vec![
+                MaterialKind::Paper,
+                MaterialKind::Paper,
+                MaterialKind::MarkingScheme,
+                MaterialKind::MarkingScheme,
+            ],
+            kinds
+        );
+        assert!(output.materials().all(|x| x.level == Level::HigherLevel));
+        assert!(output.errors.is_empty());
+        assert_eq!(2, server.max_in_flight());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn failed_years() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server.client();
+        client.available_years(&Type::ExamPaper).await?;
+        client.available_years(&Type::MarkingScheme).await?;
+        let query = || {
+            client.papers_for_subject(
+                Subject::Chemistry,
+                Examination::LeavingCertificate,
+                YearRange::from(1995..=1995),
+                |_: &Material| true,
+            )
+        };
+
+        // A failed query is kept separate.
+        server.fail_next(1, 500);
+        let output = query().await?;
+        assert!(output.years.is_empty());
+        assert_eq!(1, output.errors.len());
+        assert_eq!(Year(1995), output.errors[0].year);
+        assert_eq!(Type::ExamPaper, output.errors[0].paper_type);
+
+        // Chemistry wasn't offered in 1995, which isn't a failure.
+        let output = query().await?;
+        assert!(output.years.is_empty());
+        assert!(output.errors.is_empty());
+        Ok(())
+    }
+}
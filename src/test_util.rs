@@ -0,0 +1,788 @@
+//! Test support for running ``resec`` without a network connection.
+//!
+//! [`MockServer`] starts a local HTTP server which answers the
+//! ``MaterialArchive__*`` form fields the same way the SEC website does,
+//! serving stage pages generated from a [`MockArchive`]. It can also
+//! simulate errors, slow responses and changes to the page layout.
+//!
+//! # Usage:
+//!
+//! ```
+//! use resec::test_util::MockServer;
+//!
+//! # #[tokio::main]
+//! # async fn main() -> Result<(), resec::error::SecError> {
+//! let server = MockServer::start();
+//! let types = server.client().parse_types().await?;
+//! assert!(types.contains_key("exampapers"));
+//! # Ok(())
+//! # }
+//! ```
+
+use crate::client::SecClient;
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::{BufRead, BufReader, Read, Write},
+    net::{SocketAddr, TcpListener, TcpStream},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    thread,
+    time::Duration,
+};
+use url::{form_urlencoded, Url};
+
+/// Path the mock archive is served from, matching the real website.
+const ARCHIVE_PATH: &str = "/exammaterialarchive/";
+
+/// Changes to the layout of the stage pages.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// Pages laid out like the real website.
+    Standard,
+    /// Material rows gain an extra column after the link.
+    ExtraColumn,
+    /// Material rows are missing their download link.
+    MissingLinks,
+    /// Every query returns the terms and conditions page.
+    BounceToTerms,
+}
+
+/// How material files are sent.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BodyMode {
+    /// The whole file, with a ``Content-Length`` header.
+    Sized,
+    /// The whole file without a ``Content-Length``, like a chunked response.
+    Unsized,
+    /// Half the file with the full ``Content-Length``, as if the connection dropped.
+    Truncated,
+}
+
+/// Paper type ID, year, examination ID and subject ID of a query.
+pub type QueryKey = (String, u32, String, u32);
+
+/// The data served by a [`MockServer`].
+#[derive(Debug, Clone)]
+pub struct MockArchive {
+    /// Paper type IDs and labels, in the order offered.
+    pub types: Vec<(String, String)>,
+    /// Years offered per paper type ID, newest first.
+    pub years: HashMap<String, Vec<u32>>,
+    /// Examination IDs and labels, in the order offered.
+    pub exams: Vec<(String, String)>,
+    /// Examination IDs offered per year, falling back to every one of ``exams``.
+    pub year_exams: HashMap<u32, Vec<String>>,
+    /// Subjects offered per year, falling back to ``default_subjects``.
+    pub subjects: HashMap<u32, Vec<(u32, String)>>,
+    /// Subjects offered for years without an entry in ``subjects``.
+    pub default_subjects: Vec<(u32, String)>,
+    /// Material names and links per paper type, year, examination and subject.
+    ///
+    /// Queries without an entry have material generated from the subject name.
+    pub materials: HashMap<QueryKey, Vec<(String, String)>>,
+}
+
+impl Default for MockArchive {
+    fn default() -> Self {
+        let pairs = |x: &[(&str, &str)]| -> Vec<(String, String)> {
+            x.iter()
+                .map(|(a, b)| (a.to_string(), b.to_string()))
+                .collect()
+        };
+        let subjects = |x: &[(u32, &str)]| -> Vec<(u32, String)> {
+            x.iter().map(|(a, b)| (*a, b.to_string())).collect()
+        };
+
+        let mut years = HashMap::new();
+        years.insert("exampapers".into(), (1995..=2021).rev().collect());
+        years.insert("markingschemes".into(), (2001..=2021).rev().collect());
+
+        let mut by_year = HashMap::new();
+        by_year.insert(
+            1995,
+            subjects(&[
+                (1, "Irish"),
+                (2, "English"),
+                (10, "French"),
+                (11, "German"),
+                (14, "Art"),
+            ]),
+        );
+
+        Self {
+            types: pairs(&[
+                ("exampapers", "Exam Papers"),
+                ("markingschemes", "Marking Schemes"),
+            ]),
+            years,
+            exams: pairs(&[
+                ("lb", "Leaving Certificate Applied"),
+                ("lc", "Leaving Certificate"),
+                ("jc", "Junior Certificate / Cycle"),
+            ]),
+            year_exams: HashMap::new(),
+            subjects: by_year,
+            default_subjects: subjects(&[
+                (1, "Irish"),
+                (2, "English"),
+                (3, "Mathematics"),
+                (10, "French"),
+                (22, "Chemistry"),
+            ]),
+            materials: HashMap::new(),
+        }
+    }
+}
+
+impl MockArchive {
+    /// Check if an examination is offered in a year.
+    fn offers_exam(&self, year: u32, exam_id: &str) -> bool {
+        self.year_exams
+            .get(&year)
+            .is_none_or(|x| x.iter().any(|id| id == exam_id))
+    }
+
+    /// The subjects offered for a year.
+    fn subjects_for(&self, year: u32) -> &[(u32, String)] {
+        self.subjects.get(&year).unwrap_or(&self.default_subjects)
+    }
+
+    /// The material names and links offered for a query.
+    fn materials_for(
+        &self,
+        type_id: &str,
+        year: u32,
+        exam_id: &str,
+        subject_id: u32,
+    ) -> Vec<(String, String)> {
+        let key = (type_id.to_string(), year, exam_id.to_string(), subject_id);
+        if let Some(materials) = self.materials.get(&key) {
+            return materials.clone();
+        }
+
+        // Generate material for the subject, if it is offered.
+        let name = match self
+            .subjects_for(year)
+            .iter()
+            .find(|(id, _)| *id == subject_id)
+        {
+            Some((_, name)) => name,
+            None => return Vec::new(),
+        };
+        let code = format!("{}{:03}", exam_id.to_uppercase(), subject_id);
+        let link = |file: String| format!("../archive/{}/{}/{}", type_id, year, file);
+
+        let mut materials = vec![
+            (
+                format!("{} / Higher Level (EV)", name),
+                link(format!("{}ALP000EV.pdf", code)),
+            ),
+            (
+                format!("{} / Higher Level (IV)", name),
+                link(format!("{}ALP000IV.pdf", code)),
+            ),
+            (
+                format!("{} / Ordinary Level (EV)", name),
+                link(format!("{}GLP000EV.pdf", code)),
+            ),
+        ];
+
+        // Language subjects have aural recordings.
+        if type_id == "exampapers" && (subject_id == 10 || subject_id == 11) {
+            materials.push((
+                format!("{} / Higher Level / Aural", name),
+                link(format!("{}ALP000EV.mp3", code)),
+            ));
+        }
+
+        // Deferred sittings were held from 2021.
+        if year >= 2021 {
+            materials.push((
+                format!("{} / Higher Level - Deferred (EV)", name),
+                link(format!("{}ALP000EVD.pdf", code)),
+            ));
+        }
+
+        materials
+    }
+}
+
+/// Shared state between the server thread and its handle.
+#[derive(Debug)]
+struct MockState {
+    archive: MockArchive,
+    layout: Layout,
+    delay: Duration,
+    failures: usize,
+    failure_status: u16,
+    requests: Vec<BTreeMap<String, String>>,
+    headers: Vec<HashMap<String, String>>,
+    files: HashMap<String, Vec<u8>>,
+    body_mode: BodyMode,
+    in_flight: usize,
+    max_in_flight: usize,
+}
+
+/// A local HTTP server imitating the SEC website.
+///
+/// The server is stopped when the handle is dropped.
+#[derive(Debug)]
+pub struct MockServer {
+    address: SocketAddr,
+    state: Arc<Mutex<MockState>>,
+    running: Arc<AtomicBool>,
+}
+
+impl MockServer {
+    /// Start a server with the default archive.
+    pub fn start() -> Self {
+        Self::with_archive(MockArchive::default())
+    }
+
+    /// Start a server with the given archive.
+    pub fn with_archive(archive: MockArchive) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").expect("could not bind mock server");
+        let address = listener.local_addr().expect("could not get mock address");
+        let state = Arc::new(Mutex::new(MockState {
+            archive,
+            layout: Layout::Standard,
+            delay: Duration::from_millis(0),
+            failures: 0,
+            failure_status: 500,
+            requests: Vec::new(),
+            headers: Vec::new(),
+            files: HashMap::new(),
+            body_mode: BodyMode::Sized,
+            in_flight: 0,
+            max_in_flight: 0,
+        }));
+        let running = Arc::new(AtomicBool::new(true));
+
+        // Accept connections until the handle is dropped.
+        let (thread_state, thread_running) = (state.clone(), running.clone());
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                if !thread_running.load(Ordering::SeqCst) {
+                    break;
+                }
+                if let Ok(stream) = stream {
+                    let state = thread_state.clone();
+                    thread::spawn(move || handle(stream, &state));
+                }
+            }
+        });
+
+        Self {
+            address,
+            state,
+            running,
+        }
+    }
+
+    /// The URL of the mock examination archive.
+    pub fn url(&self) -> Url {
+        Url::parse(&format!("http://{}{}", self.address, ARCHIVE_PATH)).expect("mock URL is valid")
+    }
+
+    /// A client querying the mock examination archive.
+    pub fn client(&self) -> SecClient {
+        SecClient::with_base_url(self.url().as_str()).expect("mock URL is valid")
+    }
+
+    /// Change the layout of the stage pages.
+    pub fn set_layout(&self, layout: Layout) {
+        self.state.lock().unwrap().layout = layout;
+    }
+
+    /// Delay every response by the given duration.
+    pub fn set_delay(&self, delay: Duration) {
+        self.state.lock().unwrap().delay = delay;
+    }
+
+    /// Fail the next ``count`` requests with the given status code.
+    pub fn fail_next(&self, count: usize, status: u16) {
+        let mut state = self.state.lock().unwrap();
+        state.failures = count;
+        state.failure_status = status;
+    }
+
+    /// Serve the given body for a file path instead of the generated one.
+    pub fn set_file(&self, path: &str, body: Vec<u8>) {
+        self.state.lock().unwrap().files.insert(path.into(), body);
+    }
+
+    /// Change how material files are sent.
+    pub fn set_body_mode(&self, mode: BodyMode) {
+        self.state.lock().unwrap().body_mode = mode;
+    }
+
+    /// The form fields of every archive query received so far.
+    pub fn requests(&self) -> Vec<BTreeMap<String, String>> {
+        self.state.lock().unwrap().requests.clone()
+    }
+
+    /// The headers of every request received so far, with lowercase names.
+    pub fn request_headers(&self) -> Vec<HashMap<String, String>> {
+        self.state.lock().unwrap().headers.clone()
+    }
+
+    /// The most requests handled at once so far.
+    pub fn max_in_flight(&self) -> usize {
+        self.state.lock().unwrap().max_in_flight
+    }
+
+    /// The number of archive queries received so far.
+    pub fn request_count(&self) -> usize {
+        self.state.lock().unwrap().requests.len()
+    }
+}
+
+impl Drop for MockServer {
+    fn drop(&mut self) {
+        // Stop the accept loop, waking it up with a final connection.
+        self.running.store(false, Ordering::SeqCst);
+        let _ = TcpStream::connect(self.address);
+    }
+}
+
+/// A parsed HTTP request.
+struct Request {
+    method: String,
+    path: String,
+    headers: HashMap<String, String>,
+    body: Vec<u8>,
+}
+
+/// Read a single HTTP request from a stream.
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+
+    // Parse the request line.
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let mut path = parts.next()?.to_string();
+
+    // Requests through a proxy name the whole URL, so the server can act as one.
+    if let Ok(url) = Url::parse(&path) {
+        path = url[url::Position::BeforePath..].to_string();
+    }
+
+    // Parse the headers.
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    // Read the body, if any.
+    let length = headers
+        .get("content-length")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(0);
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).ok()?;
+
+    Some(Request {
+        method,
+        path,
+        headers,
+        body,
+    })
+}
+
+/// Write a HTTP response to a stream.
+fn write_response(
+    mut stream: &TcpStream,
+    status: u16,
+    content_type: &str,
+    headers: &[(&str, String)],
+    body: &[u8],
+    include_body: bool,
+) {
+    write_head(stream, status, content_type, headers, Some(body.len()));
+    if include_body {
+        let _ = stream.write_all(body);
+    }
+    let _ = stream.flush();
+}
+
+/// Write the status line and headers of a HTTP response to a stream.
+fn write_head(
+    mut stream: &TcpStream,
+    status: u16,
+    content_type: &str,
+    headers: &[(&str, String)],
+    length: Option<usize>,
+) {
+    let reason = match status {
+        200 => "OK",
+        304 => "Not Modified",
+        404 => "Not Found",
+        _ => "Error",
+    };
+    let mut response = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: {}\r\nConnection: close\r\n",
+        status, reason, content_type
+    );
+    if let Some(length) = length {
+        response.push_str(&format!("Content-Length: {}\r\n", length));
+    }
+    for (name, value) in headers {
+        response.push_str(&format!("{}: {}\r\n", name, value));
+    }
+    response.push_str("\r\n");
+    let _ = stream.write_all(response.as_bytes());
+}
+
+/// Handle a single connection.
+fn handle(stream: TcpStream, state: &Mutex<MockState>) {
+    let request = match read_request(&stream) {
+        Some(x) => x,
+        None => return,
+    };
+
+    // Apply the simulated delay and failures.
+    let (delay, failure) = {
+        let mut state = state.lock().unwrap();
+        state.headers.push(request.headers.clone());
+        state.in_flight += 1;
+        state.max_in_flight = state.max_in_flight.max(state.in_flight);
+        let failure = if state.failures > 0 {
+            state.failures -= 1;
+            Some(state.failure_status)
+        } else {
+            None
+        };
+        (state.delay, failure)
+    };
+    thread::sleep(delay);
+    state.lock().unwrap().in_flight -= 1;
+    if let Some(status) = failure {
+        write_response(&stream, status, "text/html", &[], b"Mock failure", true);
+        return;
+    }
+
+    let path = request.path.split('?').next().unwrap_or("").to_string();
+    if request.method == "POST" && path == ARCHIVE_PATH {
+        // Parse the form fields.
+        let form: BTreeMap<String, String> =
+            form_urlencoded::parse(&request.body).into_owned().collect();
+
+        let html = {
+            let mut state = state.lock().unwrap();
+            state.requests.push(form.clone());
+            stage_page(&state.archive, state.layout, &form)
+        };
+        write_response(
+            &stream,
+            200,
+            "text/html; charset=utf-8",
+            &[],
+            html.as_bytes(),
+            true,
+        );
+    } else if path.starts_with("/archive/") {
+        let (body, mode) = {
+            let state = state.lock().unwrap();
+            let body = state
+                .files
+                .get(&path)
+                .cloned()
+                .unwrap_or_else(|| format!("mock material {}", path).into_bytes());
+            (body, state.body_mode)
+        };
+        serve_file(&stream, &request, &path, &body, mode);
+    } else {
+        write_response(&stream, 404, "text/html", &[], b"Not Found", true);
+    }
+}
+
+/// Serve a material file, honouring conditional requests.
+fn serve_file(mut stream: &TcpStream, request: &Request, path: &str, body: &[u8], mode: BodyMode) {
+    let content_type = match path.rsplit('.').next() {
+        Some("pdf") => "application/pdf",
+        Some("mp3") => "audio/mpeg",
+        Some("zip") => "application/zip",
+        _ => "application/octet-stream",
+    };
+
+    // Use the length and a simple checksum as a stable entity tag.
+    let checksum = body
+        .iter()
+        .fold(0u32, |acc, x| acc.wrapping_mul(31).wrapping_add(*x as u32));
+    let etag = format!("\"{:x}-{:x}\"", body.len(), checksum);
+    let headers = [
+        ("ETag", etag.clone()),
+        ("Last-Modified", "Mon, 01 Jul 2019 09:00:00 GMT".to_string()),
+    ];
+
+    if request.headers.get("if-none-match") == Some(&etag) {
+        write_response(stream, 304, content_type, &headers, &[], false);
+    } else if request.method == "HEAD" || mode == BodyMode::Sized {
+        let include_body = request.method != "HEAD";
+        write_response(stream, 200, content_type, &headers, body, include_body);
+    } else {
+        let (length, sent) = match mode {
+            BodyMode::Truncated => (Some(body.len()), &body[..body.len() / 2]),
+            _ => (None, body),
+        };
+        write_head(stream, 200, content_type, &headers, length);
+        let _ = stream.write_all(sent);
+        let _ = stream.flush();
+    }
+}
+
+/// Find a form value set by the stage builder.
+fn field<'a>(form: &'a BTreeMap<String, String>, name: &str) -> Option<&'a str> {
+    form.get(&format!("MaterialArchive__noTable__{}", name))
+        .map(|x| x.as_str())
+        .filter(|x| !x.is_empty())
+}
+
+/// Generate a select field with the given options.
+fn select(name: &str, placeholder: &str, options: &[(String, String)]) -> String {
+    let mut html = format!(
+        "<select name=\"MaterialArchive__noTable__sbv__{}\">\n<option value=\"\">{}</option>\n",
+        name, placeholder
+    );
+    for (value, label) in options {
+        html.push_str(&format!("<option value=\"{}\">{}</option>\n", value, label));
+    }
+    html.push_str("</select>\n");
+    html
+}
+
+/// Generate the page for the stage selected by the form fields.
+fn stage_page(archive: &MockArchive, layout: Layout, form: &BTreeMap<String, String>) -> String {
+    let agreed = field(form, "cbv__AgreeCheck") == Some("Y");
+    if !agreed || layout == Layout::BounceToTerms {
+        return page(
+            "<form method=\"post\">\n\
+             <p>Terms and Conditions of Use - Version 1.0</p>\n\
+             <p>Material may be reproduced for educational purposes only.</p>\n\
+             <input type=\"checkbox\" name=\"MaterialArchive__noTable__cbv__AgreeCheck\" value=\"Y\">\n\
+             </form>",
+        );
+    }
+
+    let mut body = select("ViewType", "Select Type", &archive.types);
+    let type_id = match field(form, "sbv__ViewType") {
+        Some(x) => x,
+        None => return page(&body),
+    };
+
+    // Years offered for the type.
+    let years: Vec<(String, String)> = archive
+        .years
+        .get(type_id)
+        .map(|x| x.iter().map(|y| (y.to_string(), y.to_string())).collect())
+        .unwrap_or_default();
+    body.push_str(&select("YearSelect", "Select Year", &years));
+    let year: u32 = match field(form, "sbv__YearSelect").and_then(|x| x.parse().ok()) {
+        Some(x) => x,
+        None => return page(&body),
+    };
+
+    let exams: Vec<(String, String)> = archive
+        .exams
+        .iter()
+        .filter(|(id, _)| archive.offers_exam(year, id))
+        .cloned()
+        .collect();
+    body.push_str(&select("ExaminationSelect", "Select Examination", &exams));
+    let exam_id = match field(form, "sbv__ExaminationSelect") {
+        // Like the website, an examination not offered has no subjects.
+        Some(x) if archive.offers_exam(year, x) => x,
+        _ => return page(&body),
+    };
+
+    let subjects: Vec<(String, String)> = archive
+        .subjects_for(year)
+        .iter()
+        .map(|(id, name)| (id.to_string(), name.clone()))
+        .collect();
+    body.push_str(&select("SubjectSelect", "Select Subject", &subjects));
+    let subject_id: u32 = match field(form, "sbv__SubjectSelect").and_then(|x| x.parse().ok()) {
+        Some(x) => x,
+        None => return page(&body),
+    };
+
+    // Finally, list the material.
+    let materials = archive.materials_for(type_id, year, exam_id, subject_id);
+    if materials.is_empty() {
+        body.push_str("<p>No material is available for this selection.</p>\n");
+        return page(&body);
+    }
+
+    body.push_str("<table>\n<tr><th>Description</th><th>Download</th></tr>\n");
+    for (name, link) in materials {
+        body.push_str(&format!("<tr>\n<td class=\"materialbody\">{}</td>\n", name));
+        if layout != Layout::MissingLinks {
+            body.push_str(&format!(
+                "<td class=\"materialbody\"><a href=\"{}\" target=\"_blank\">Click Here</a></td>\n",
+                link
+            ));
+        }
+        if layout == Layout::ExtraColumn {
+            body.push_str("<td class=\"materialbody\">1.2 MB</td>\n");
+        }
+        body.push_str("</tr>\n");
+    }
+    body.push_str("</table>\n");
+
+    page(&body)
+}
+
+/// Wrap a body in a page.
+fn page(body: &str) -> String {
+    format!(
+        "<!DOCTYPE html>\n<html>\n<head><title>Examination Material Archive</title></head>\n\
+         <body>\n<form method=\"post\">\n{}\n</form>\n</body>\n</html>\n",
+        body
+    )
+}
+
+/// Generate a minimal PDF with one page per string.
+///
+/// Each line of a page is drawn in Helvetica, which is enough
+/// for the text to be extracted again by a PDF reader.
+pub fn sample_pdf(pages: &[&str]) -> Vec<u8> {
+    // Object 1 is the catalog, 2 the page tree, 3 the font,
+    // then every page is followed by its content stream.
+    let mut objects = vec![
+        "<< /Type /Catalog /Pages 2 0 R >>".to_string(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            (0..pages.len())
+                .map(|x| format!("{} 0 R", 4 + x * 2))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        ),
+        "<< /Type /Font /Subtype /Type1 /BaseFont /Helvetica /Encoding /WinAnsiEncoding >>"
+            .to_string(),
+    ];
+
+    for (i, text) in pages.iter().enumerate() {
+        let mut content = String::from("BT\n/F1 12 Tf\n14 TL\n72 770 Td\n");
+        for line in text.lines() {
+            let line = line
+                .replace('\\', "\\\\")
+                .replace('(', "\\(")
+                .replace(')', "\\)");
+            content.push_str(&format!("({}) Tj T*\n", line));
+        }
+        content.push_str("ET");
+
+        objects.push(format!(
+            "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 595 842] \
+             /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+            5 + i * 2
+        ));
+        objects.push(format!(
+            "<< /Length {} >>\nstream\n{}\nendstream",
+            content.len(),
+            content
+        ));
+    }
+
+    // Write the objects, recording their offsets for the cross-reference table.
+    let mut pdf = String::from("%PDF-1.4\n");
+    let mut offsets = Vec::new();
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.push_str(&format!("{} 0 obj\n{}\nendobj\n", i + 1, object));
+    }
+
+    let xref = pdf.len();
+    pdf.push_str(&format!(
+        "xref\n0 {}\n0000000000 65535 f \n",
+        objects.len() + 1
+    ));
+    for offset in offsets {
+        pdf.push_str(&format!("{:010} 00000 n \n", offset));
+    }
+    pdf.push_str(&format!(
+        "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+        objects.len() + 1,
+        xref
+    ));
+
+    pdf.into_bytes()
+}
+
+#[cfg(test)]
+mod test_util_tests {
+    use super::*;
+    use crate::error::{SecError, SecResult};
+
+    #[tokio::test]
+    async fn stages() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server.client();
+
+        let years = client.parse_years("exampapers").await?;
+        assert_eq!(Some(&2021), years.first());
+        assert_eq!(Some(&1995), years.last());
+
+        let papers = client.parse_papers("exampapers", 2019, "lc", 3).await?;
+        assert_eq!(3, papers.materials.len());
+        assert_eq!(
+            server
+                .url()
+                .join("../archive/exampapers/2019/LC003ALP000EV.pdf")
+                .unwrap(),
+            papers.materials[0].link
+        );
+
+        assert_eq!(2, server.request_count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn simulated_failures() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server.client();
+
+        server.fail_next(1, 503);
+        assert!(matches!(
+            client.parse_types().await,
+            Err(SecError::Reqwest(_))
+        ));
+        assert!(client.parse_types().await.is_ok());
+
+        server.set_layout(Layout::BounceToTerms);
+        assert!(matches!(
+            client.parse_types().await,
+            Err(SecError::TermsNotAccepted)
+        ));
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn layout_variants() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server.client();
+
+        server.set_layout(Layout::ExtraColumn);
+        let papers = client.parse_papers("exampapers", 2019, "lc", 10).await?;
+        assert!(papers.materials.iter().all(|x| x.extra == ["1.2 MB"]));
+
+        server.set_layout(Layout::MissingLinks);
+        let papers = client.parse_papers("exampapers", 2019, "lc", 10).await?;
+        assert!(papers.materials.is_empty());
+        assert_eq!(4, papers.warnings.len());
+        Ok(())
+    }
+}
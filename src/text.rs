@@ -0,0 +1,143 @@
+//! Text extraction from downloaded material.
+//!
+//! Text is extracted page by page using a pure Rust PDF reader,
+//! so a local mirror can be processed without a network connection.
+
+use crate::{
+    downloader::Download,
+    error::{SecError, SecResult},
+    material::{FileFormat, Material},
+};
+use std::{panic, path::Path};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+/// The text of a single page.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct PageText {
+    /// Page number, starting from 1.
+    pub page: u32,
+    /// Text extracted from the page.
+    pub text: String,
+}
+
+/// The text of a material, page by page.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, PartialEq)]
+pub struct MaterialText {
+    /// The material the text was extracted from.
+    pub material: Material,
+    /// Text of every page, in order.
+    pub pages: Vec<PageText>,
+}
+
+impl MaterialText {
+    /// Extract the text of a downloaded material.
+    pub fn from_download(download: &Download) -> SecResult<Self> {
+        Self::from_file(&download.material, &download.path)
+    }
+
+    /// Extract the text of a material saved at the given path.
+    pub fn from_file(material: &Material, path: impl AsRef<Path>) -> SecResult<Self> {
+        // Only PDFs contain text to extract.
+        if material.format != FileFormat::Pdf {
+            return Err(SecError::Value("PDF material"));
+        }
+
+        Ok(Self {
+            material: material.clone(),
+            pages: extract_pages(path)?,
+        })
+    }
+
+    /// The text of every page, separated by form feeds.
+    pub fn text(&self) -> String {
+        self.pages
+            .iter()
+            .map(|x| x.text.as_str())
+            .collect::<Vec<_>>()
+            .join("\u{c}")
+    }
+}
+
+/// Extract the text of each page of a PDF file.
+pub fn extract_pages(path: impl AsRef<Path>) -> SecResult<Vec<PageText>> {
+    let bytes = std::fs::read(path)?;
+    extract_pages_from_mem(&bytes)
+}
+
+/// Extract the text of each page of a PDF held in memory.
+///
+/// The PDF reader can panic on malformed files, which is returned as
+/// [`SecError::MalformedPdf`] instead.
+pub fn extract_pages_from_mem(bytes: &[u8]) -> SecResult<Vec<PageText>> {
+    let pages = panic::catch_unwind(|| pdf_extract::extract_text_from_mem_by_pages(bytes))
+        .map_err(|panic| {
+            let message = match (panic.downcast_ref::<&str>(), panic.downcast_ref::<String>()) {
+                (Some(x), _) => x.to_string(),
+                (_, Some(x)) => x.clone(),
+                _ => "unknown panic".into(),
+            };
+            SecError::MalformedPdf(message)
+        })??;
+
+    Ok(pages
+        .into_iter()
+        .zip(1..)
+        .map(|(text, page)| PageText { page, text })
+        .collect())
+}
+
+#[cfg(test)]
+mod text_tests {
+    use super::*;
+    use crate::test_util::sample_pdf;
+
+    #[test]
+    fn extract_sample() -> SecResult<()> {
+        let pdf = sample_pdf(&[
+            "Question 1\nUse integration by parts to find the integral.",
+            "Question 2\nSolve the quadratic equation.",
+        ]);
+
+        let pages = extract_pages_from_mem(&pdf)?;
+        assert_eq!(2, pages.len());
+        assert_eq!(1, pages[0].page);
+        assert!(pages[0].text.contains("integration by parts"));
+        assert!(pages[1].text.contains("quadratic"));
+        assert!(!pages[1].text.contains("integration"));
+        Ok(())
+    }
+
+    #[test]
+    fn invalid_pdf() {
+        assert!(matches!(
+            extract_pages_from_mem(b"not a pdf"),
+            Err(SecError::Pdf(_))
+        ));
+
+        // The reader panics on a missing font, which is caught.
+        let pdf = String::from_utf8(sample_pdf(&["Question 1"]))
+            .unwrap()
+            .replace("\n3 0 obj", "\n9 0 obj");
+        assert!(matches!(
+            extract_pages_from_mem(pdf.as_bytes()),
+            Err(SecError::MalformedPdf(_))
+        ));
+    }
+
+    #[test]
+    fn only_pdfs() {
+        let material = Material::new(
+            "exampapers",
+            2019,
+            "lc",
+            10,
+            "French / Higher Level / Aural".into(),
+            url::Url::parse("https://www.examinations.ie/archive/LC010ALP000EV.mp3").unwrap(),
+        );
+        assert!(MaterialText::from_file(&material, "missing.mp3").is_err());
+    }
+}
@@ -0,0 +1,255 @@
+//! Discovery of the years offered on the SEC website.
+//!
+//! The years are scraped once per [`Type`] using [`parse_years`](crate::parser::parse_years)
+//! and cached for the lifetime of the process, or for the TTL of the client's
+//! [`ResponseCache`](crate::cache::ResponseCache) if it has one.
+
+use crate::{
+    client::SecClient,
+    consts::{EXAM_PAPER_YEARS, MARKING_SCHEME_YEARS},
+    error::{SecError, SecResult},
+    schema::metadata::Type,
+};
+use lazy_static::lazy_static;
+use std::{collections::HashMap, fmt, str::FromStr, sync::Mutex, time::Instant};
+use tracing::debug;
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+lazy_static! {
+    /// Years discovered per archive URL and paper type ID, and when.
+    static ref YEAR_CACHE: Mutex<HashMap<String, (Instant, Vec<Year>)>> =
+        Mutex::new(HashMap::new());
+}
+
+/// An examination year.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize), serde(transparent))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Year(pub u32);
+
+impl From<u32> for Year {
+    fn from(year: u32) -> Self {
+        Year(year)
+    }
+}
+
+impl From<Year> for u32 {
+    fn from(year: Year) -> Self {
+        year.0
+    }
+}
+
+impl fmt::Display for Year {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+/// An inclusive range of examination years.
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct YearRange {
+    pub start: Year,
+    pub end: Year,
+}
+
+impl YearRange {
+    /// Create a new inclusive year range.
+    pub fn new(start: impl Into<Year>, end: impl Into<Year>) -> Self {
+        Self {
+            start: start.into(),
+            end: end.into(),
+        }
+    }
+
+    /// Check if the range contains the given year.
+    pub fn contains(&self, year: impl Into<Year>) -> bool {
+        let year = year.into();
+        self.start <= year && year <= self.end
+    }
+
+    /// Iterate through every year in the range.
+    pub fn iter(&self) -> impl Iterator<Item = Year> {
+        (self.start.0..=self.end.0).map(Year)
+    }
+}
+
+impl From<std::ops::RangeInclusive<u32>> for YearRange {
+    fn from(range: std::ops::RangeInclusive<u32>) -> Self {
+        Self::new(*range.start(), *range.end())
+    }
+}
+
+/// Parse a range written as ``2015..2019``, or a single year such as ``2019``.
+impl FromStr for YearRange {
+    type Err = SecError;
+
+    fn from_str(raw: &str) -> SecResult<Self> {
+        match raw.split_once("..") {
+            Some((start, end)) => Ok(Self::new(
+                start.trim().parse::<u32>()?,
+                end.trim().trim_start_matches('=').parse::<u32>()?,
+            )),
+            None => {
+                let year: u32 = raw.trim().parse()?;
+                Ok(Self::new(year, year))
+            }
+        }
+    }
+}
+
+impl fmt::Display for YearRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}..{}", self.start, self.end)
+    }
+}
+
+/// Fetch the years offered for a paper type, oldest first.
+///
+/// The result is cached, so only the first call for each type queries the website.
+pub async fn available_years(paper_type: &Type) -> SecResult<Vec<Year>> {
+    SecClient::new().available_years(paper_type).await
+}
+
+/// Fetch the range of years offered for a paper type.
+pub async fn year_range(paper_type: &Type) -> SecResult<YearRange> {
+    SecClient::new().year_range(paper_type).await
+}
+
+impl SecClient {
+    /// Fetch the years offered for a paper type, oldest first.
+    ///
+    /// The result is cached per archive URL, so only the first call
+    /// for each type queries the website. A client with a response cache
+    /// queries the website again once its TTL has passed.
+    pub async fn available_years(&self, paper_type: &Type) -> SecResult<Vec<Year>> {
+        // Check if the years have already been discovered.
+        if let Some(years) = self.cached_years(paper_type) {
+            return Ok(years);
+        }
+
+        // Scrape the years and sort them.
+        let mut years: Vec<Year> = self
+            .parse_years(paper_type.id())
+            .await?
+            .into_iter()
+            .map(Year)
+            .collect();
+        years.sort();
+
+        // Finally, cache the result.
+        let key = format!("{}#{}", self.base_url(), paper_type.id());
+        YEAR_CACHE
+            .lock()
+            .unwrap()
+            .insert(key, (Instant::now(), years.clone()));
+
+        Ok(years)
+    }
+
+    /// The years discovered for a paper type, if they have been cached
+    /// and haven't expired.
+    pub(crate) fn cached_years(&self, paper_type: &Type) -> Option<Vec<Year>> {
+        let key = format!("{}#{}", self.base_url(), paper_type.id());
+        YEAR_CACHE
+            .lock()
+            .unwrap()
+            .get(&key)
+            .filter(|(stored, _)| {
+                self.response_cache()
+                    .is_none_or(|x| stored.elapsed() < x.ttl())
+            })
+            .map(|(_, years)| years.clone())
+    }
+
+    /// Fetch the range of years offered for a paper type.
+    pub async fn year_range(&self, paper_type: &Type) -> SecResult<YearRange> {
+        let years = self.available_years(paper_type).await?;
+        match (years.first(), years.last()) {
+            (Some(start), Some(end)) => Ok(YearRange::new(*start, *end)),
+            _ => Err(SecError::Value("paper year range")),
+        }
+    }
+}
+
+/// The years assumed to be offered for a paper type when the website can't be reached.
+pub fn fallback_years(paper_type: &Type) -> Vec<Year> {
+    debug!(paper_type = paper_type.id(), "using the fallback years");
+    let years = match paper_type {
+        Type::ExamPaper => &*EXAM_PAPER_YEARS,
+        Type::MarkingScheme => &*MARKING_SCHEME_YEARS,
+        Type::Unknown { .. } => return Vec::new(),
+    };
+    years.iter().copied().map(Year).collect()
+}
+
+/// Clear the cached years, forcing the next lookup to query the website.
+pub fn clear_year_cache() {
+    YEAR_CACHE.lock().unwrap().clear();
+}
+
+#[cfg(test)]
+mod years_tests {
+    use super::*;
+    use crate::{cache::ResponseCache, test_util::MockServer};
+    use std::time::Duration;
+
+    #[test]
+    fn year_range() {
+        let range = YearRange::from(2010..=2019);
+        assert!(range.contains(2010));
+        assert!(range.contains(2019));
+        assert!(!range.contains(2020));
+        assert_eq!(10, range.iter().count());
+        assert_eq!("2010..2019", range.to_string());
+        assert_eq!(range, "2010..2019".parse().unwrap());
+        assert_eq!(range, "2010..=2019".parse().unwrap());
+        assert_eq!(YearRange::from(2019..=2019), "2019".parse().unwrap());
+        assert!("2010..".parse::<YearRange>().is_err());
+    }
+
+    #[test]
+    fn fallback() {
+        let papers = fallback_years(&Type::ExamPaper);
+        let schemes = fallback_years(&Type::MarkingScheme);
+        assert_eq!(Some(&Year(1995)), papers.first());
+        assert_eq!(Some(&Year(2001)), schemes.first());
+        assert_eq!(papers.last(), schemes.last());
+    }
+
+    #[tokio::test]
+    async fn discovered_years() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server.client();
+
+        let years = client.available_years(&Type::ExamPaper).await?;
+        let range = client.year_range(&Type::ExamPaper).await?;
+        assert_eq!(YearRange::from(1995..=2021), range);
+        assert!(years.iter().all(|x| range.contains(*x)));
+        assert!(years.windows(2).all(|x| x[0] < x[1]));
+
+        // The second lookup should be served from the cache.
+        client.available_years(&Type::ExamPaper).await?;
+        assert_eq!(1, server.request_count());
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn expired_years() -> SecResult<()> {
+        let server = MockServer::start();
+        let client = server
+            .client()
+            .cache(ResponseCache::new(Duration::from_millis(50)));
+
+        client.available_years(&Type::ExamPaper).await?;
+        client.available_years(&Type::ExamPaper).await?;
+        assert_eq!(1, server.request_count());
+
+        // The years expire alongside the client's cached pages.
+        tokio::time::delay_for(Duration::from_millis(60)).await;
+        client.available_years(&Type::ExamPaper).await?;
+        assert_eq!(2, server.request_count());
+        Ok(())
+    }
+}